@@ -1,9 +1,41 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rdst::tuner::{Algorithm, Decision, Tuner, TuningParams};
+use rdst::tuners::StandardTuner;
 use rdst::utils::bench_utils::{bench_common, bench_medley};
 use rdst::utils::test_utils::NumericTest;
 use rdst::RadixSort;
 use voracious_radix_sort::{RadixKey as VorKey, RadixSort as Vor, Radixable};
 
+/// `NoWriteCombining` makes every decision exactly as the default tuner does, except that it
+/// never uses write-combining.
+struct NoWriteCombining;
+
+impl Tuner for NoWriteCombining {
+    fn pick_algorithm(&self, p: &TuningParams, counts: &[usize]) -> Algorithm {
+        StandardTuner.pick_algorithm(p, counts)
+    }
+
+    fn decide(&self, p: &TuningParams, counts: &[usize]) -> Decision {
+        StandardTuner.decide(p, counts)
+    }
+
+    fn stable(&self) -> bool {
+        StandardTuner.stable()
+    }
+
+    fn write_combining(&self, _p: &TuningParams, _algorithm: Algorithm) -> bool {
+        false
+    }
+
+    fn count_all_levels(&self, p: &TuningParams) -> bool {
+        StandardTuner.count_all_levels(p)
+    }
+
+    fn indirect(&self, p: &TuningParams) -> bool {
+        StandardTuner.indirect(p)
+    }
+}
+
 fn full_sort_common<T>(c: &mut Criterion, shift: T, name_suffix: &str)
 where
     T: NumericTest<T> + Radixable<T> + VorKey,
@@ -23,6 +55,16 @@ where
                 black_box(input);
             }),
         ),
+        (
+            "rdst_no_write_combining",
+            Box::new(|mut input| {
+                input
+                    .radix_sort_builder()
+                    .with_tuner(&NoWriteCombining)
+                    .sort();
+                black_box(input);
+            }),
+        ),
         (
             "voracious",
             Box::new(|mut input| {
@@ -54,6 +96,16 @@ where
                 black_box(input);
             }),
         ),
+        (
+            "rdst_no_write_combining",
+            Box::new(|mut input| {
+                input
+                    .radix_sort_builder()
+                    .with_tuner(&NoWriteCombining)
+                    .sort();
+                black_box(input);
+            }),
+        ),
         (
             "voracious",
             Box::new(|mut input| {
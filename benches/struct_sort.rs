@@ -101,6 +101,25 @@ fn full_sort_struct(c: &mut Criterion) {
                 black_box(input);
             }),
         ),
+        (
+            "rdst_standard_tuner",
+            Box::new(|mut input| {
+                // The default tuner decides for itself whether to sort indirectly
+                input.radix_sort_builder().sort();
+                black_box(input);
+            }),
+        ),
+        (
+            "rdst_indirect",
+            Box::new(|mut input| {
+                input
+                    .radix_sort_builder()
+                    .with_single_threaded_tuner()
+                    .indirect()
+                    .sort();
+                black_box(input);
+            }),
+        ),
         (
             "voracious",
             Box::new(|mut input| {
@@ -0,0 +1,12 @@
+use rdst::RadixSort;
+
+fn main() {
+    // A simple pseudo-random dataset. Replace this with your own data to see how the tuner
+    // performs on it.
+    let mut inputs: Vec<u64> = (0..1_000_000u64)
+        .map(|i| i.wrapping_mul(0x9E37_79B9_7F4A_7C15))
+        .collect();
+
+    let report = inputs.radix_sort_builder().sort_with_regret_report();
+    println!("{}", report);
+}
@@ -0,0 +1,271 @@
+//! `chrome_trace` records a timeline of a sort's execution, which can be written out as Chrome
+//! trace-event JSON and loaded into a trace viewer such as `chrome://tracing` or Perfetto.
+//!
+//! Begin and end timestamps are recorded per thread for each phase of the sort (counting, each
+//! algorithm run, recursion into the next level, and the internal phases of the multi-threaded
+//! algorithms such as tile sorts, region swaps and scanner bucket locks). This makes stragglers
+//! and lock contention visible, which aggregate timings can't show.
+//!
+//! Recording is global and only happens between calls to `start()` and `stop()`, so it is best
+//! used in a standalone program that runs a single sort at a time.
+//!
+//! ```
+//! use rdst::chrome_trace;
+//! use rdst::RadixSort;
+//!
+//! let mut data: Vec<u32> = (0..100_000).rev().collect();
+//!
+//! chrome_trace::start();
+//! data.radix_sort_unstable();
+//! let trace = chrome_trace::stop();
+//!
+//! let mut json = Vec::new();
+//! trace.write_json(&mut json).unwrap();
+//! ```
+
+use std::borrow::Cow;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+type TraceArgs = Vec<(&'static str, u64)>;
+
+static RECORDING: AtomicBool = AtomicBool::new(false);
+static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+static EVENTS: Mutex<Vec<TraceEvent>> = Mutex::new(Vec::new());
+static THREAD_NAMES: Mutex<Vec<(u64, String)>> = Mutex::new(Vec::new());
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+thread_local! {
+    static THREAD_ID: u64 = {
+        let id = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+        let name = std::thread::current()
+            .name()
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| format!("thread-{}", id));
+        THREAD_NAMES.lock().unwrap().push((id, name));
+
+        id
+    };
+}
+
+#[derive(Debug, Clone)]
+enum EventKind {
+    Complete(Duration),
+    Counter(u64),
+}
+
+#[derive(Debug, Clone)]
+struct TraceEvent {
+    name: Cow<'static, str>,
+    tid: u64,
+    start: Duration,
+    kind: EventKind,
+    args: TraceArgs,
+}
+
+/// `Trace` holds the events recorded between `start()` and `stop()`.
+#[derive(Debug, Clone)]
+pub struct Trace {
+    events: Vec<TraceEvent>,
+    thread_names: Vec<(u64, String)>,
+}
+
+impl Trace {
+    /// `len()` returns the number of events recorded.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// `is_empty()` returns whether no events were recorded.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// `write_json()` writes the trace in Chrome's trace-event JSON format.
+    pub fn write_json<W: Write>(&self, mut w: W) -> std::io::Result<()> {
+        write!(w, "{{\"traceEvents\":[")?;
+
+        let mut first = true;
+        let mut sep = |w: &mut W| -> std::io::Result<()> {
+            if !first {
+                write!(w, ",")?;
+            }
+            first = false;
+
+            Ok(())
+        };
+
+        for (tid, name) in self.thread_names.iter() {
+            sep(&mut w)?;
+            write!(
+                w,
+                "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{},\"args\":{{\"name\":\"{}\"}}}}",
+                tid,
+                escape(name)
+            )?;
+        }
+
+        for e in self.events.iter() {
+            sep(&mut w)?;
+            write!(
+                w,
+                "{{\"name\":\"{}\",\"cat\":\"rdst\",\"pid\":1,\"tid\":{},\"ts\":{:.3}",
+                escape(&e.name),
+                e.tid,
+                e.start.as_nanos() as f64 / 1_000.0
+            )?;
+
+            match e.kind {
+                EventKind::Complete(dur) => {
+                    write!(
+                        w,
+                        ",\"ph\":\"X\",\"dur\":{:.3},\"args\":{{",
+                        dur.as_nanos() as f64 / 1_000.0
+                    )?;
+
+                    for (i, (k, v)) in e.args.iter().enumerate() {
+                        if i > 0 {
+                            write!(w, ",")?;
+                        }
+                        write!(w, "\"{}\":{}", k, v)?;
+                    }
+
+                    write!(w, "}}}}")?;
+                }
+                EventKind::Counter(value) => {
+                    write!(w, ",\"ph\":\"C\",\"args\":{{\"value\":{}}}}}", value)?;
+                }
+            }
+        }
+
+        write!(w, "],\"displayTimeUnit\":\"ns\"}}")
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// `start()` discards any previously recorded events and starts recording.
+pub fn start() {
+    EPOCH.get_or_init(Instant::now);
+    EVENTS.lock().unwrap().clear();
+    RECORDING.store(true, Ordering::SeqCst);
+}
+
+/// `stop()` stops recording and returns the events recorded since `start()`.
+pub fn stop() -> Trace {
+    RECORDING.store(false, Ordering::SeqCst);
+
+    Trace {
+        events: std::mem::take(&mut *EVENTS.lock().unwrap()),
+        thread_names: THREAD_NAMES.lock().unwrap().clone(),
+    }
+}
+
+/// `is_recording()` returns whether events are currently being recorded.
+#[inline]
+pub fn is_recording() -> bool {
+    RECORDING.load(Ordering::Relaxed)
+}
+
+#[inline]
+fn now() -> Duration {
+    EPOCH.get_or_init(Instant::now).elapsed()
+}
+
+fn push(event: TraceEvent) {
+    // Events that finish after `stop()` are dropped rather than left for the next trace
+    if is_recording() {
+        EVENTS.lock().unwrap().push(event);
+    }
+}
+
+/// `Scope` records a complete event from when it is created until it is dropped. Use the
+/// `trace_scope!` macro rather than creating these directly.
+pub(crate) struct Scope {
+    inner: Option<(Cow<'static, str>, Duration, TraceArgs)>,
+}
+
+impl Scope {
+    #[inline]
+    pub(crate) fn new<F>(name: F, args: &[(&'static str, u64)]) -> Self
+    where
+        F: FnOnce() -> Cow<'static, str>,
+    {
+        if !is_recording() {
+            return Self { inner: None };
+        }
+
+        Self {
+            inner: Some((name(), now(), args.to_vec())),
+        }
+    }
+}
+
+impl Drop for Scope {
+    #[inline]
+    fn drop(&mut self) {
+        if let Some((name, start, args)) = self.inner.take() {
+            push(TraceEvent {
+                name,
+                tid: THREAD_ID.with(|id| *id),
+                kind: EventKind::Complete(now() - start),
+                start,
+                args,
+            });
+        }
+    }
+}
+
+/// `counter` records the value of a counter at the current time.
+#[inline]
+pub(crate) fn counter(name: &'static str, value: u64) {
+    if !is_recording() {
+        return;
+    }
+
+    push(TraceEvent {
+        name: Cow::Borrowed(name),
+        tid: THREAD_ID.with(|id| *id),
+        start: now(),
+        kind: EventKind::Counter(value),
+        args: Vec::new(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chrome_trace;
+    use crate::RadixSort;
+
+    #[test]
+    pub fn test_trace_json() {
+        let mut data: Vec<u64> = (0..2_000_000u64)
+            .map(|v| v.wrapping_mul(0x9E37_79B9_7F4A_7C15))
+            .collect();
+
+        chrome_trace::start();
+        assert!(chrome_trace::is_recording());
+        data.radix_sort_builder().with_low_mem_tuner().sort();
+        let trace = chrome_trace::stop();
+        assert!(!chrome_trace::is_recording());
+
+        assert!(!trace.is_empty());
+        assert!(data.windows(2).all(|w| w[0] <= w[1]));
+
+        let mut json = Vec::new();
+        trace.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+
+        assert!(json.starts_with("{\"traceEvents\":["));
+        assert!(json.ends_with("],\"displayTimeUnit\":\"ns\"}"));
+        assert!(json.contains("\"name\":\"count\""));
+        assert!(json.contains("\"name\":\"director\""));
+        assert!(json.contains("\"name\":\"Regions\""));
+        assert!(json.contains("\"name\":\"regions_tile_sort\""));
+        assert_eq!(json.matches('{').count(), json.matches('}').count());
+    }
+}
@@ -0,0 +1,362 @@
+//! # tune
+//!
+//! This is used to generate a tuner for the current machine. For each type size, distribution,
+//! depth and input size in a grid, it measures sorts with each `Algorithm` forced for that
+//! input, determines the fastest choice per cell, and emits either a `ProfileTuner` profile or
+//! the Rust source for an equivalent `Tuner` implementation that can be checked in.
+//!
+//! ## Usage
+//!
+//! You may need to tweak the command below for your own machine.
+//!
+//! ```
+//! RUSTFLAGS='--cfg bench --cfg tuning -C opt-level=3 -C target-cpu=native' cargo run --release --bin tune --features tune -- profile 16000000 > my_machine.profile
+//! ```
+//!
+//!  - `profile` is the output format, either `profile` or `rust`
+//!  - `16000000` is the largest input size to measure (optional, defaults to 16,000,000)
+//!
+//! Progress is printed to stderr, so only the generated tuner is written to stdout.
+//!
+//! Cells are measured at the top level with otherwise random inputs, and at depth 1 with the top
+//! byte cleared so that the top level is skipped. Levels other than the one being measured are
+//! tuned by the built-in standard profile.
+
+#[cfg(not(all(tuning, bench)))]
+compile_error!("This binary must be run with `RUSTFLAGS='--cfg tuning --cfg bench'`");
+
+use rdst::tuner::{Algorithm, ProfileTuner, Tuner, TuningParams};
+use rdst::utils::test_utils::{gen_inputs, NumericTest};
+use rdst::RadixSort;
+use std::fmt::Write;
+use std::mem::size_of;
+use std::time::{Duration, Instant};
+#[cfg(all(tuning, not(target_env = "msvc")))]
+use tikv_jemallocator::Jemalloc;
+
+#[cfg(all(tuning, not(target_env = "msvc")))]
+#[global_allocator]
+static ALLOC: Jemalloc = Jemalloc;
+
+/// Uses `algo` for the input being measured (identified by its length) and the base tuner for
+/// everything else.
+struct CandidateTuner<'a> {
+    algo: Algorithm,
+    len: usize,
+    base: &'a ProfileTuner,
+}
+
+impl Tuner for CandidateTuner<'_> {
+    fn pick_algorithm(&self, p: &TuningParams, counts: &[usize]) -> Algorithm {
+        if p.input_len == self.len {
+            self.algo
+        } else {
+            self.base.pick_algorithm(p, counts)
+        }
+    }
+}
+
+struct Cell {
+    elem_size: usize,
+    skewed: bool,
+    depth: usize,
+    len: usize,
+    algorithm: Algorithm,
+}
+
+fn fastest<T>(input: &[T], base: &ProfileTuner) -> Algorithm
+where
+    T: NumericTest<T>,
+{
+    let mut best = (Duration::MAX, Algorithm::Lsb);
+
+    for algo in Algorithm::ALL.iter().copied() {
+        let tuner = CandidateTuner {
+            algo,
+            len: input.len(),
+            base,
+        };
+
+        // Warmup
+        let mut warmup = input.to_vec();
+        warmup.radix_sort_builder().with_tuner(&tuner).sort();
+        drop(warmup);
+
+        let mut times = Vec::new();
+        for _ in 0..3 {
+            let mut to_sort = input.to_vec();
+            let time = Instant::now();
+            to_sort.radix_sort_builder().with_tuner(&tuner).sort();
+            times.push(time.elapsed());
+        }
+
+        times.sort_unstable();
+        if times[1] < best.0 {
+            best = (times[1], algo);
+        }
+    }
+
+    best.1
+}
+
+fn measure<T>(
+    shift: T,
+    skew_shift: T,
+    clear_top: fn(T) -> T,
+    sizes: &[usize],
+    base: &ProfileTuner,
+    cells: &mut Vec<Cell>,
+) where
+    T: NumericTest<T>,
+{
+    let max = *sizes.iter().max().unwrap();
+
+    for (skewed, shift) in [(false, shift), (true, skew_shift)] {
+        let inputs = gen_inputs(max, shift);
+
+        for depth in [0, 1] {
+            for len in sizes.iter().copied() {
+                let start = (max - len) / 2;
+                let mut input = inputs[start..start + len].to_vec();
+
+                if depth == 1 {
+                    input.iter_mut().for_each(|v| *v = clear_top(*v));
+                }
+
+                let algorithm = fastest(&input, base);
+                eprintln!(
+                    "elem_size={} skewed={} depth={} len={} -> {}",
+                    size_of::<T>(),
+                    skewed,
+                    depth,
+                    len,
+                    algorithm
+                );
+
+                cells.push(Cell {
+                    elem_size: size_of::<T>(),
+                    skewed,
+                    depth,
+                    len,
+                    algorithm,
+                });
+            }
+        }
+    }
+}
+
+/// Groups of cells sharing an element size, distribution and depth, each reduced to a list of
+/// (maximum length, algorithm) with crossovers at the geometric mean of neighbouring sizes.
+#[allow(clippy::type_complexity)]
+fn crossovers(
+    cells: &[Cell],
+) -> Vec<((usize, usize), bool, usize, Vec<(Option<usize>, Algorithm)>)> {
+    let mut elem_sizes: Vec<usize> = cells.iter().map(|c| c.elem_size).collect();
+    elem_sizes.sort_unstable();
+    elem_sizes.dedup();
+
+    let mut out = Vec::new();
+
+    for (i, elem_size) in elem_sizes.iter().copied().enumerate() {
+        let min_size = if i == 0 { 0 } else { elem_sizes[i - 1] + 1 };
+        let max_size = if i == elem_sizes.len() - 1 {
+            usize::MAX
+        } else {
+            elem_size
+        };
+
+        for skewed in [false, true] {
+            for depth in [0, 1] {
+                let group: Vec<&Cell> = cells
+                    .iter()
+                    .filter(|c| c.elem_size == elem_size && c.skewed == skewed && c.depth == depth)
+                    .collect();
+                let mut ranges: Vec<(Option<usize>, Algorithm)> = Vec::new();
+
+                for (j, cell) in group.iter().enumerate() {
+                    let max_len = group
+                        .get(j + 1)
+                        .map(|next| ((cell.len as f64) * (next.len as f64)).sqrt() as usize);
+
+                    match ranges.last_mut() {
+                        Some((m, a)) if *a == cell.algorithm => *m = max_len,
+                        _ => ranges.push((max_len, cell.algorithm)),
+                    }
+                }
+
+                out.push(((min_size, max_size), skewed, depth, ranges));
+            }
+        }
+    }
+
+    out
+}
+
+fn emit_profile(cells: &[Cell]) -> String {
+    let mut out = String::new();
+    writeln!(out, "# Generated by `tune`").unwrap();
+    writeln!(out, "input_len=..128 -> Comparative").unwrap();
+
+    for ((min_size, max_size), skewed, depth, ranges) in crossovers(cells) {
+        let elem_size = match (min_size, max_size) {
+            (m, usize::MAX) => format!("{}..", m),
+            (0, m) => format!("..{}", m),
+            (a, b) => format!("{}..{}", a, b),
+        };
+        let depth = if depth == 0 { "0" } else { "1.." };
+
+        for (max_len, algorithm) in ranges {
+            let len = match max_len {
+                Some(m) => format!(" len=..{}", m),
+                None => String::new(),
+            };
+
+            writeln!(
+                out,
+                "elem_size={} skewed={} depth={}{} -> {}",
+                elem_size, skewed, depth, len, algorithm
+            )
+            .unwrap();
+        }
+    }
+
+    writeln!(out, "-> Lsb").unwrap();
+
+    out
+}
+
+fn emit_rust(cells: &[Cell]) -> String {
+    let mut out = String::new();
+    writeln!(out, "//! Generated by `tune`").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "use crate::tuner::{{Algorithm, Tuner, TuningParams}};").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "pub struct GeneratedTuner;").unwrap();
+    writeln!(out, "impl Tuner for GeneratedTuner {{").unwrap();
+    writeln!(out, "    #[inline]").unwrap();
+    writeln!(
+        out,
+        "    fn pick_algorithm(&self, p: &TuningParams, _counts: &[usize]) -> Algorithm {{"
+    )
+    .unwrap();
+    writeln!(out, "        if p.input_len <= 128 {{").unwrap();
+    writeln!(out, "            return Algorithm::Comparative;").unwrap();
+    writeln!(out, "        }}").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "        let depth = p.total_levels - p.level - 1;").unwrap();
+    writeln!(
+        out,
+        "        let skewed = p.max_bucket_share >= 2.0 / 256.0;"
+    )
+    .unwrap();
+    writeln!(out, "        let len = p.weighted_len();").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "        match (p.element_size, skewed, depth) {{").unwrap();
+
+    for ((min_size, max_size), skewed, depth, ranges) in crossovers(cells) {
+        let elem_size = if max_size == usize::MAX {
+            format!("{}..", min_size)
+        } else {
+            format!("{}..={}", min_size, max_size)
+        };
+        let depth = if depth == 0 { "0" } else { "1.." };
+
+        if let [(None, algorithm)] = ranges[..] {
+            writeln!(
+                out,
+                "            ({}, {}, {}) => Algorithm::{},",
+                elem_size, skewed, depth, algorithm
+            )
+            .unwrap();
+            continue;
+        }
+
+        writeln!(
+            out,
+            "            ({}, {}, {}) => match len {{",
+            elem_size, skewed, depth
+        )
+        .unwrap();
+
+        let mut min_len = 0;
+        for (max_len, algorithm) in ranges {
+            match max_len {
+                Some(m) => {
+                    writeln!(
+                        out,
+                        "                {}..={} => Algorithm::{},",
+                        min_len, m, algorithm
+                    )
+                    .unwrap();
+                    min_len = m + 1;
+                }
+                None => writeln!(out, "                _ => Algorithm::{},", algorithm).unwrap(),
+            }
+        }
+
+        writeln!(out, "            }},").unwrap();
+    }
+
+    writeln!(out, "        }}").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}").unwrap();
+
+    out
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let format = args.next().unwrap_or_else(|| "profile".to_string());
+    let max_len: usize = args
+        .next()
+        .map(|m| m.parse().expect("max size must be a number"))
+        .unwrap_or(16_000_000);
+
+    assert!(
+        format == "profile" || format == "rust",
+        "format must be either `profile` or `rust`"
+    );
+
+    let mut sizes = Vec::new();
+    let mut len = 1_000;
+    while len <= max_len {
+        sizes.push(len);
+        len *= 4;
+    }
+    assert!(!sizes.is_empty(), "max size must be at least 1000");
+
+    let base = ProfileTuner::standard();
+    let mut cells = Vec::new();
+
+    measure(0u32, 16u32, |v| v & 0x00FF_FFFF, &sizes, &base, &mut cells);
+    measure(
+        0u64,
+        32u64,
+        |v| v & (u64::MAX >> 8),
+        &sizes,
+        &base,
+        &mut cells,
+    );
+    measure(
+        0u128,
+        64u128,
+        |v| v & (u128::MAX >> 8),
+        &sizes,
+        &base,
+        &mut cells,
+    );
+
+    let out = if format == "profile" {
+        let profile = emit_profile(&cells);
+        // Sanity check that the output can be loaded again
+        profile
+            .parse::<ProfileTuner>()
+            .expect("generated profile is valid");
+
+        profile
+    } else {
+        emit_rust(&cells)
+    };
+
+    print!("{}", out);
+}
@@ -0,0 +1,142 @@
+use crate::sorts::cmp_levels;
+use crate::RadixKey;
+use std::cmp::Ordering;
+
+/// `LoserTree` picks the smallest of the heads of several sorted runs. Each internal node holds
+/// the loser of the match played there, and the overall winner is kept at index 0, so that when
+/// the winner is replaced by the next record of its run, only the matches on the path from its
+/// leaf to the root need to be replayed. That's one comparison per level of the tree.
+///
+/// A run that has been used up has a head of `None`, which loses to everything. Ties go to the
+/// run with the lower index, so runs given in input order are merged stably.
+pub(crate) struct LoserTree<T> {
+    tree: Vec<usize>,
+    heads: Vec<Option<T>>,
+}
+
+impl<T> LoserTree<T>
+where
+    T: RadixKey + Copy,
+{
+    pub(crate) fn new(heads: Vec<Option<T>>) -> Self {
+        let k = heads.len();
+        let mut lt = LoserTree {
+            tree: vec![0; k.max(1)],
+            heads,
+        };
+
+        if k <= 1 {
+            return lt;
+        }
+
+        // Leaves are at k..2k and internal nodes at 1..k, with the children of node n at 2n and
+        // 2n + 1. Play every match bottom-up, keeping the loser and passing the winner upwards.
+        let mut winners = vec![0; 2 * k];
+
+        for (i, w) in winners[k..].iter_mut().enumerate() {
+            *w = i;
+        }
+
+        for n in (1..k).rev() {
+            let (a, b) = (winners[2 * n], winners[2 * n + 1]);
+
+            if lt.beats(a, b) {
+                winners[n] = a;
+                lt.tree[n] = b;
+            } else {
+                winners[n] = b;
+                lt.tree[n] = a;
+            }
+        }
+
+        lt.tree[0] = winners[1];
+
+        lt
+    }
+
+    /// `beats()` returns whether the head of run `a` comes before the head of run `b`.
+    #[inline]
+    fn beats(&self, a: usize, b: usize) -> bool {
+        match (&self.heads[a], &self.heads[b]) {
+            (Some(x), Some(y)) => match cmp_levels(x, y, T::LEVELS - 1) {
+                Ordering::Less => true,
+                Ordering::Greater => false,
+                Ordering::Equal => a < b,
+            },
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => a < b,
+        }
+    }
+
+    /// `peek()` returns the index of the run with the smallest head, along with that head, or
+    /// `None` once every run has been used up.
+    #[inline]
+    pub(crate) fn peek(&self) -> Option<(usize, T)> {
+        let winner = *self.tree.first()?;
+
+        self.heads.get(winner)?.map(|head| (winner, head))
+    }
+
+    /// `replace_winner()` replaces the head of the winning run with `head`, the next record of that
+    /// run, and finds the new winner.
+    #[inline]
+    pub(crate) fn replace_winner(&mut self, head: Option<T>) {
+        let k = self.heads.len();
+        let mut winner = self.tree[0];
+        self.heads[winner] = head;
+
+        let mut n = (winner + k) / 2;
+
+        while n > 0 {
+            if self.beats(self.tree[n], winner) {
+                std::mem::swap(&mut self.tree[n], &mut winner);
+            }
+
+            n /= 2;
+        }
+
+        self.tree[0] = winner;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::external::loser_tree::LoserTree;
+    use block_pseudorand::block_rand;
+
+    fn merge_all(runs: Vec<Vec<u32>>) -> Vec<u32> {
+        let mut iters: Vec<_> = runs.into_iter().map(|r| r.into_iter()).collect();
+        let heads = iters.iter_mut().map(|i| i.next()).collect();
+        let mut tree = LoserTree::new(heads);
+        let mut out = Vec::new();
+
+        while let Some((i, head)) = tree.peek() {
+            out.push(head);
+            tree.replace_winner(iters[i].next());
+        }
+
+        out
+    }
+
+    #[test]
+    pub fn test_loser_tree() {
+        assert_eq!(merge_all(vec![]), Vec::<u32>::new());
+        assert_eq!(merge_all(vec![vec![]]), Vec::<u32>::new());
+        assert_eq!(merge_all(vec![vec![1, 2, 3]]), vec![1, 2, 3]);
+        assert_eq!(
+            merge_all(vec![vec![5], vec![], vec![1, 5, 9], vec![2, 3]]),
+            vec![1, 2, 3, 5, 5, 9]
+        );
+
+        for k in [2, 3, 7, 16, 33] {
+            let mut runs: Vec<Vec<u32>> = (0..k).map(|i| block_rand(i * 100 + 10)).collect();
+            runs.iter_mut().for_each(|r| r.sort_unstable());
+
+            let mut expected: Vec<u32> = runs.concat();
+            expected.sort_unstable();
+
+            assert_eq!(merge_all(runs), expected, "k: {}", k);
+        }
+    }
+}
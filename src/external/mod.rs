@@ -0,0 +1,356 @@
+//! Sorting of inputs that are too large to fit in memory.
+//!
+//! `ExternalSorter` reads fixed-width records from any `Read`, sorts them in runs that fit within
+//! its memory budget, spills each sorted run to a temporary file and then merges the runs into
+//! any `Write`. Records are converted to and from bytes with the `Record` trait, which is
+//! implemented for the primitive types and for byte arrays.
+//!
+//! ```
+//! use rdst::external::ExternalSorter;
+//! use std::io::Cursor;
+//!
+//! let data: Vec<u8> = [9u32, 3, 7, 1]
+//!     .iter()
+//!     .flat_map(|v| v.to_le_bytes())
+//!     .collect();
+//! let mut output = Vec::new();
+//!
+//! ExternalSorter::new()
+//!     .with_memory_budget(64 * 1024 * 1024)
+//!     .sort::<u32, _, _>(Cursor::new(data), &mut output)
+//!     .unwrap();
+//!
+//! let sorted: Vec<u8> = [1u32, 3, 7, 9]
+//!     .iter()
+//!     .flat_map(|v| v.to_le_bytes())
+//!     .collect();
+//! assert_eq!(output, sorted);
+//! ```
+
+mod loser_tree;
+mod record;
+mod runs;
+
+pub use record::Record;
+
+use crate::external::loser_tree::LoserTree;
+use crate::external::runs::{
+    read_records, spill, write_records, RunReader, SpillFile, IO_BLOCK_LEN,
+};
+use crate::sorter::Sorter;
+use crate::tuner::Tuner;
+use crate::tuners::SingleThreadedTuner;
+#[cfg(feature = "multi-threaded")]
+use crate::tuners::StandardTuner;
+use std::cmp::{max, min};
+use std::env;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Default number of bytes an `ExternalSorter` may use.
+const DEFAULT_MEMORY_BUDGET: usize = 256 * 1024 * 1024;
+
+/// Most runs merged at once. Beyond this, runs are merged in several passes so that each run
+/// still gets a reasonably sized read buffer.
+const MAX_MERGE_WIDTH: usize = 128;
+
+/// `ExternalSorter` sorts records from a `Read` into a `Write` using a bounded amount of memory,
+/// spilling sorted runs to temporary files when the input doesn't fit. See the `external` module
+/// for an example.
+pub struct ExternalSorter<'a> {
+    memory_budget: usize,
+    temp_dir: Option<PathBuf>,
+    multi_threaded: bool,
+    tuner: &'a (dyn Tuner + Send + Sync),
+}
+
+impl<'a> Default for ExternalSorter<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> ExternalSorter<'a> {
+    pub fn new() -> Self {
+        #[cfg(feature = "multi-threaded")]
+        let (tuner, multi_threaded) = (&StandardTuner, true);
+        #[cfg(not(feature = "multi-threaded"))]
+        let (tuner, multi_threaded) = (&SingleThreadedTuner, false);
+
+        Self {
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            temp_dir: None,
+            multi_threaded,
+            tuner,
+        }
+    }
+
+    /// `with_memory_budget()` sets the number of bytes used for records held in memory. Half of
+    /// it holds each run while it is sorted and the other half is left to the sort for temporary
+    /// buffers. While merging, it is split between the read buffers of the runs being merged.
+    ///
+    /// As with `RadixSortBuilder::with_memory_budget`, small bookkeeping allocations are not
+    /// included. Defaults to 256 MiB.
+    pub fn with_memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = bytes;
+
+        self
+    }
+
+    /// `with_temp_dir()` sets the directory sorted runs are spilled to. Defaults to
+    /// `std::env::temp_dir()`. Spill files are removed once they have been merged, or if the sort
+    /// fails.
+    pub fn with_temp_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.temp_dir = Some(dir.into());
+
+        self
+    }
+
+    /// `with_parallel()` sets whether runs are sorted using multiple threads. Reading, spilling
+    /// and merging are always done on the calling thread.
+    pub fn with_parallel(mut self, parallel: bool) -> Self {
+        self.multi_threaded = parallel;
+
+        self
+    }
+
+    /// `with_single_threaded_tuner()` configures the sort of each run to use a tuner which only
+    /// uses single-threaded sorting algorithms.
+    pub fn with_single_threaded_tuner(mut self) -> Self {
+        self.tuner = &SingleThreadedTuner;
+
+        self
+    }
+
+    /// `with_tuner()` allows you to provide your own tuning for sorting each run.
+    pub fn with_tuner(mut self, tuner: &'a (dyn Tuner + Send + Sync)) -> Self {
+        self.tuner = tuner;
+
+        self
+    }
+
+    /// `sort()` reads every record from `input` and writes them to `output` in sorted order.
+    /// Returns an error of kind `InvalidData` if `input` ends part way through a record, along
+    /// with any error from reading, writing or the spill files.
+    pub fn sort<T, R, W>(&self, mut input: R, mut output: W) -> io::Result<()>
+    where
+        T: Record,
+        R: Read,
+        W: Write,
+    {
+        assert_ne!(T::LEVELS, 0, "RadixKey must have at least 1 level");
+        assert_ne!(T::SIZE, 0, "Record must be at least 1 byte");
+
+        let item_size = max(1, size_of::<T>());
+        let run_len = max(1, self.memory_budget / 2 / item_size);
+        let sorter = Sorter::new(self.multi_threaded, self.tuner).with_memory_budget(Some(
+            self.memory_budget - min(self.memory_budget, run_len * item_size),
+        ));
+
+        let temp_dir = match &self.temp_dir {
+            Some(dir) => dir.clone(),
+            None => env::temp_dir(),
+        };
+
+        let mut records: Vec<T> = Vec::new();
+        let mut bytes = Vec::new();
+        let mut spills = Vec::new();
+
+        loop {
+            records.clear();
+            read_records(&mut input, &mut records, run_len, &mut bytes)?;
+
+            if records.is_empty() {
+                break;
+            }
+
+            let full = records.len() == run_len;
+            sorter.top_level_director(&mut records);
+
+            // The whole input fit in a single run, so there's nothing to merge
+            if spills.is_empty() && !full {
+                write_records(&mut output, &records, IO_BLOCK_LEN, &mut bytes)?;
+                return output.flush();
+            }
+
+            spills.push(spill(&temp_dir, &records, &mut bytes)?);
+
+            if !full {
+                break;
+            }
+        }
+
+        // Free the run buffer before merging, as the merge has its own budget
+        drop(records);
+        drop(bytes);
+
+        while spills.len() > MAX_MERGE_WIDTH {
+            spills = self.merge_pass::<T>(&temp_dir, spills)?;
+        }
+
+        self.merge::<T, W>(spills, &mut output)?;
+
+        output.flush()
+    }
+
+    /// `block_len()` returns how many records each of `k` run readers and the output buffer may
+    /// hold at once.
+    fn block_len<T>(&self, k: usize) -> usize {
+        let per_buffer = self.memory_budget / (k + 1) / max(1, size_of::<T>());
+
+        per_buffer.clamp(1, IO_BLOCK_LEN)
+    }
+
+    /// `merge_pass()` merges each group of up to `MAX_MERGE_WIDTH` spilled runs into a single
+    /// spilled run.
+    fn merge_pass<T: Record>(
+        &self,
+        temp_dir: &Path,
+        spills: Vec<SpillFile>,
+    ) -> io::Result<Vec<SpillFile>> {
+        let mut merged = Vec::new();
+        let mut spills = spills.into_iter();
+
+        loop {
+            let group: Vec<SpillFile> = spills.by_ref().take(MAX_MERGE_WIDTH).collect();
+
+            if group.is_empty() {
+                break;
+            }
+
+            let (mut out, mut file) = SpillFile::create(temp_dir)?;
+            let len = group.iter().map(|s| s.len()).sum();
+            self.merge::<T, _>(group, &mut file)?;
+            file.flush()?;
+            out.set_len(len);
+            merged.push(out);
+        }
+
+        Ok(merged)
+    }
+
+    /// `merge()` merges the sorted runs `spills` into `output`, deleting them as it goes.
+    fn merge<T, W>(&self, spills: Vec<SpillFile>, output: &mut W) -> io::Result<()>
+    where
+        T: Record,
+        W: Write,
+    {
+        let block_len = self.block_len::<T>(spills.len());
+        let mut readers = spills
+            .into_iter()
+            .map(|s| RunReader::<T>::open(s, block_len))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let heads = readers
+            .iter_mut()
+            .map(|r| r.next())
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut tree = LoserTree::new(heads);
+        let mut out = Vec::with_capacity(block_len);
+        let mut bytes = Vec::new();
+
+        while let Some((i, head)) = tree.peek() {
+            out.push(head);
+
+            if out.len() == block_len {
+                write_records(output, &out, block_len, &mut bytes)?;
+                out.clear();
+            }
+
+            tree.replace_winner(readers[i].next()?);
+        }
+
+        write_records(output, &out, block_len, &mut bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::external::ExternalSorter;
+    use block_pseudorand::block_rand;
+    use std::fs;
+    use std::io::{Cursor, ErrorKind};
+
+    fn to_bytes(data: &[u32]) -> Vec<u8> {
+        data.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    fn external_sort(sorter: &ExternalSorter, data: &[u32]) -> Vec<u8> {
+        let mut output = Vec::new();
+        sorter
+            .sort::<u32, _, _>(Cursor::new(to_bytes(data)), &mut output)
+            .unwrap();
+
+        output
+    }
+
+    #[test]
+    pub fn test_external_sort() {
+        let sizes = [0, 1, 200, 1_000, 100_000];
+        let budgets = [4, 4096, 64 * 1024, 64 * 1024 * 1024];
+
+        for size in sizes {
+            let data: Vec<u32> = block_rand(size);
+            let mut expected = data.clone();
+            expected.sort_unstable();
+            let expected = to_bytes(&expected);
+
+            for budget in budgets {
+                for parallel in [false, true] {
+                    // Tiny budgets spill too many runs to merge at once for large inputs
+                    if budget == 4 && size > 1_000 {
+                        continue;
+                    }
+
+                    let sorter = ExternalSorter::new()
+                        .with_memory_budget(budget)
+                        .with_parallel(parallel);
+
+                    assert_eq!(
+                        external_sort(&sorter, &data),
+                        expected,
+                        "size: {}, budget: {}, parallel: {}",
+                        size,
+                        budget,
+                        parallel
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_external_sort_partial_record() {
+        let mut bytes = to_bytes(&[3, 2, 1]);
+        bytes.push(0);
+
+        for budget in [4, 64 * 1024] {
+            let err = ExternalSorter::new()
+                .with_memory_budget(budget)
+                .sort::<u32, _, _>(Cursor::new(bytes.clone()), Vec::new())
+                .unwrap_err();
+
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    pub fn test_external_sort_temp_dir() {
+        let dir = std::env::temp_dir().join(format!("rdst-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let data: Vec<u32> = block_rand(50_000);
+        let mut expected = data.clone();
+        expected.sort_unstable();
+
+        let sorter = ExternalSorter::new()
+            .with_memory_budget(16 * 1024)
+            .with_temp_dir(&dir);
+
+        assert_eq!(external_sort(&sorter, &data), to_bytes(&expected));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+        fs::remove_dir(&dir).unwrap();
+    }
+}
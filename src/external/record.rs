@@ -0,0 +1,140 @@
+use crate::RadixKey;
+use std::convert::TryInto;
+
+/// `Record` is a fixed-width item that can be written out as bytes and read back again. This is
+/// the format `ExternalSorter` reads its input in, spills runs to disk in, and writes its output
+/// in. Primitive types are stored in little-endian byte order.
+///
+/// ```
+/// use rdst::external::Record;
+/// use rdst::RadixKey;
+///
+/// #[derive(Clone, Copy)]
+/// struct Entry {
+///     key: u32,
+///     value: u16,
+/// }
+///
+/// impl RadixKey for Entry {
+///     const LEVELS: usize = 4;
+///
+///     #[inline]
+///     fn get_level(&self, level: usize) -> u8 {
+///         self.key.get_level(level)
+///     }
+/// }
+///
+/// impl Record for Entry {
+///     const SIZE: usize = 6;
+///
+///     fn write_bytes(&self, out: &mut [u8]) {
+///         self.key.write_bytes(&mut out[..4]);
+///         self.value.write_bytes(&mut out[4..]);
+///     }
+///
+///     fn read_bytes(bytes: &[u8]) -> Self {
+///         Entry {
+///             key: u32::read_bytes(&bytes[..4]),
+///             value: u16::read_bytes(&bytes[4..]),
+///         }
+///     }
+/// }
+/// ```
+pub trait Record: RadixKey + Copy + Send + Sync {
+    /// Number of bytes each record takes up. This must be greater than zero.
+    const SIZE: usize;
+
+    /// `write_bytes()` writes the record into `out`, which is exactly `SIZE` bytes long.
+    fn write_bytes(&self, out: &mut [u8]);
+
+    /// `read_bytes()` reads a record back from `bytes`, which is exactly `SIZE` bytes long.
+    fn read_bytes(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_record {
+    ($($t:ty),*) => {
+        $(
+            impl Record for $t {
+                const SIZE: usize = size_of::<$t>();
+
+                #[inline]
+                fn write_bytes(&self, out: &mut [u8]) {
+                    out.copy_from_slice(&self.to_le_bytes());
+                }
+
+                #[inline]
+                fn read_bytes(bytes: &[u8]) -> Self {
+                    <$t>::from_le_bytes(bytes.try_into().unwrap())
+                }
+            }
+        )*
+    };
+}
+
+impl_record!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+impl<const N: usize> Record for [u8; N] {
+    const SIZE: usize = N;
+
+    #[inline]
+    fn write_bytes(&self, out: &mut [u8]) {
+        out.copy_from_slice(self);
+    }
+
+    #[inline]
+    fn read_bytes(bytes: &[u8]) -> Self {
+        bytes.try_into().unwrap()
+    }
+}
+
+/// `encode_records` replaces the contents of `bytes` with `records` written out one after another.
+#[inline]
+pub(crate) fn encode_records<T: Record>(records: &[T], bytes: &mut Vec<u8>) {
+    bytes.clear();
+    bytes.resize(records.len() * T::SIZE, 0);
+
+    for (record, out) in records.iter().zip(bytes.chunks_exact_mut(T::SIZE)) {
+        record.write_bytes(out);
+    }
+}
+
+/// `decode_records` appends the records written out in `bytes` to `records`. The length of
+/// `bytes` must be a multiple of `T::SIZE`.
+#[inline]
+pub(crate) fn decode_records<T: Record>(bytes: &[u8], records: &mut Vec<T>) {
+    records.extend(bytes.chunks_exact(T::SIZE).map(T::read_bytes));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::external::record::{decode_records, encode_records, Record};
+    use std::fmt::Debug;
+
+    fn round_trip<T>(records: &[T])
+    where
+        T: Record + PartialEq + Debug,
+    {
+        let mut bytes = Vec::new();
+        encode_records(records, &mut bytes);
+        assert_eq!(bytes.len(), records.len() * T::SIZE);
+
+        let mut decoded: Vec<T> = Vec::new();
+        decode_records(&bytes, &mut decoded);
+        assert_eq!(decoded, records);
+    }
+
+    #[test]
+    pub fn test_round_trip() {
+        round_trip(&[0u8, 1, 255]);
+        round_trip(&[0u32, 1, u32::MAX]);
+        round_trip(&[i64::MIN, -1, 0, i64::MAX]);
+        round_trip(&[u128::MAX, 12345]);
+        round_trip(&[-1.5f32, 0.0, f32::MAX]);
+        round_trip(&[f64::MIN, -0.25, 3.5]);
+        round_trip(&[[1u8, 2, 3], [4, 5, 6]]);
+
+        let mut bytes = [0u8; 4];
+        0x0403_0201u32.write_bytes(&mut bytes);
+        assert_eq!(bytes, [1, 2, 3, 4]);
+    }
+}
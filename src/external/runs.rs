@@ -0,0 +1,195 @@
+use crate::external::record::{decode_records, encode_records, Record};
+use std::cmp::{max, min};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Number of records read or written at a time, unless the memory budget is too small for it.
+pub(crate) const IO_BLOCK_LEN: usize = 8192;
+
+/// Used to give every spill file created by this process a different name.
+static NEXT_SPILL_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// `SpillFile` is a temporary file holding a sorted run of `len` records. The file is deleted
+/// when this is dropped.
+pub(crate) struct SpillFile {
+    path: PathBuf,
+    len: u64,
+}
+
+impl SpillFile {
+    /// `create()` creates a new, empty spill file in `dir`, returning it along with a handle to
+    /// write to it. Call `set_len()` once the records have been written.
+    pub(crate) fn create(dir: &Path) -> io::Result<(SpillFile, File)> {
+        loop {
+            let id = NEXT_SPILL_ID.fetch_add(1, Ordering::Relaxed);
+            let path = dir.join(format!("rdst-{}-{}.run", process::id(), id));
+
+            // A file of the same name may have been left behind by an earlier process
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => return Ok((SpillFile { path, len: 0 }, file)),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    #[inline]
+    pub(crate) fn len(&self) -> u64 {
+        self.len
+    }
+
+    #[inline]
+    pub(crate) fn set_len(&mut self, len: u64) {
+        self.len = len;
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        // There's nothing useful to do about a temporary file that can't be removed
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// `RunReader` reads the records of a spill file back in order, `block_len` at a time.
+pub(crate) struct RunReader<T> {
+    // Declared before `_spill` so that the file is closed before it is deleted
+    file: File,
+    _spill: SpillFile,
+    remaining: u64,
+    block_len: usize,
+    records: Vec<T>,
+    pos: usize,
+    bytes: Vec<u8>,
+}
+
+impl<T: Record> RunReader<T> {
+    pub(crate) fn open(spill: SpillFile, block_len: usize) -> io::Result<Self> {
+        Ok(RunReader {
+            file: File::open(&spill.path)?,
+            remaining: spill.len(),
+            _spill: spill,
+            block_len: max(1, block_len),
+            records: Vec::new(),
+            pos: 0,
+            bytes: Vec::new(),
+        })
+    }
+
+    /// `next()` returns the next record of the run, or `None` once every record has been read.
+    #[inline]
+    pub(crate) fn next(&mut self) -> io::Result<Option<T>> {
+        if self.pos == self.records.len() {
+            if self.remaining == 0 {
+                return Ok(None);
+            }
+
+            let len = min(self.block_len as u64, self.remaining) as usize;
+            self.bytes.resize(len * T::SIZE, 0);
+            self.file.read_exact(&mut self.bytes)?;
+            self.records.clear();
+            decode_records(&self.bytes, &mut self.records);
+            self.remaining -= len as u64;
+            self.pos = 0;
+        }
+
+        let record = self.records[self.pos];
+        self.pos += 1;
+
+        Ok(Some(record))
+    }
+}
+
+/// `read_full` reads from `reader` until `buf` is full or the end of the input is reached, and
+/// returns the number of bytes read.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(filled)
+}
+
+/// `read_records` reads records from `reader` into `records` until it holds `max_len` of them or
+/// the end of the input is reached. `records` only grows as far as it needs to, and never past a
+/// capacity of `max_len`. Returns an error if the input ends part way through a record.
+pub(crate) fn read_records<T, R>(
+    reader: &mut R,
+    records: &mut Vec<T>,
+    max_len: usize,
+    bytes: &mut Vec<u8>,
+) -> io::Result<()>
+where
+    T: Record,
+    R: Read,
+{
+    while records.len() < max_len {
+        let want = min(IO_BLOCK_LEN, max_len - records.len());
+        bytes.resize(want * T::SIZE, 0);
+        let read = read_full(reader, bytes)?;
+
+        if read % T::SIZE != 0 {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "input ended part way through a record",
+            ));
+        }
+
+        // Grow geometrically, but never past the memory budget
+        if records.capacity() < records.len() + read / T::SIZE {
+            let target = min(max_len, max(records.capacity() * 2, records.len() + want));
+            records.reserve_exact(target - records.len());
+        }
+
+        decode_records(&bytes[..read], records);
+
+        if read < bytes.len() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// `write_records` writes `records` to `writer`, `block_len` at a time.
+pub(crate) fn write_records<T, W>(
+    writer: &mut W,
+    records: &[T],
+    block_len: usize,
+    bytes: &mut Vec<u8>,
+) -> io::Result<()>
+where
+    T: Record,
+    W: Write,
+{
+    for block in records.chunks(max(1, block_len)) {
+        encode_records(block, bytes);
+        writer.write_all(bytes)?;
+    }
+
+    Ok(())
+}
+
+/// `spill` writes the sorted run `records` to a new spill file in `dir`.
+pub(crate) fn spill<T: Record>(
+    dir: &Path,
+    records: &[T],
+    bytes: &mut Vec<u8>,
+) -> io::Result<SpillFile> {
+    let (mut spill, mut file) = SpillFile::create(dir)?;
+    write_records(&mut file, records, IO_BLOCK_LEN, bytes)?;
+    file.flush()?;
+    spill.set_len(records.len() as u64);
+
+    Ok(spill)
+}
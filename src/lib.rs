@@ -121,6 +121,19 @@
 //!     .sort();
 //! ```
 //!
+//! If you use a custom tuner, or need a hard guarantee that nothing will run on another thread, use `with_strict_single_threaded()` instead. Any multi-threaded algorithm the tuner picks is then replaced with its closest single-threaded equivalent, or `try_sort()` can be used to return an error instead.
+//!
+//! ```rust
+//! use rdst::RadixSort;
+//! let mut my_vec: Vec<usize> = vec![10, 15, 0, 22, 9];
+//!
+//! my_vec
+//!     .radix_sort_builder()
+//!     .with_strict_single_threaded()
+//!     .try_sort()
+//!     .unwrap();
+//! ```
+//!
 //! NOTE: If you are ONLY using the single-threaded variant of this radix sort, you can disable the default `"multi-threaded"` feature on the `rdst` dependency to remove large sub-dependencies like Rayon.
 //!
 //! ```toml
@@ -131,6 +144,80 @@
 //!
 //! With the `"multi-threaded"` feature disabled, even the default `my_data.radix_sort_unstable()` will use a single-threaded tuner.
 //!
+//! ## Key Hints
+//!
+//! If you already know something about your keys, such as the range they fall within, you can pass it on with `with_key_hint()` so that the sort doesn't have to discover it again. A range lets the sort skip the leading bytes that every key shares, and `KeyHint::Uniform` skips checking whether uniformly random keys are already in order. Every hint is also passed to the tuner. Hints are trusted rather than checked, so a wrong hint may leave the data unsorted.
+//!
+//! ```
+//! use rdst::{KeyHint, RadixSort};
+//! let mut my_vec: Vec<u32> = vec![10, 9_999, 0, 22, 9];
+//!
+//! my_vec
+//!     .radix_sort_builder()
+//!     .with_key_hint(KeyHint::Range { min: 0, max: 9_999 })
+//!     .sort();
+//! ```
+//!
+//! ## Applying a Permutation
+//!
+//! When several arrays need to be reordered in the same way, such as the columns of a table sorted by one of them, the `permutation` module can apply a list of indexes to each of them. `gather` copies into a new array using multiple threads, while `apply_permutation_in_place` reorders an array without allocating a copy of it.
+//!
+//! ```
+//! use rdst::permutation::{apply_permutation_in_place, gather};
+//!
+//! let perm = vec![2, 0, 1];
+//! let prices = vec![3.0, 1.0, 2.0];
+//! let mut names = vec![String::from("a"), String::from("b"), String::from("c")];
+//!
+//! let prices = gather(&prices, &perm);
+//! apply_permutation_in_place(&mut names, &perm);
+//!
+//! assert_eq!(prices, vec![2.0, 3.0, 1.0]);
+//! assert_eq!(names, vec!["c", "a", "b"]);
+//! ```
+//!
+//! ## External Sorting
+//!
+//! For inputs too large to fit in memory, the `external` module sorts fixed-width records from any `Read` into any `Write`. Runs that fit within the memory budget are sorted and spilled to temporary files, then merged. Records implement the `Record` trait to convert to and from bytes, which is implemented for the primitive types and for byte arrays.
+//!
+//! ```rust
+//! use rdst::external::ExternalSorter;
+//! use std::fs::File;
+//! use std::io::{BufReader, BufWriter};
+//!
+//! fn sort_file() -> std::io::Result<()> {
+//!     let input = BufReader::new(File::open("input.bin")?);
+//!     let output = BufWriter::new(File::create("output.bin")?);
+//!
+//!     ExternalSorter::new()
+//!         .with_memory_budget(1024 * 1024 * 1024)
+//!         .with_temp_dir("/mnt/scratch")
+//!         .sort::<u64, _, _>(input, output)
+//! }
+//! ```
+//!
+//! ## Memory-mapped Files
+//!
+//! Files that fit in the address space but not comfortably in RAM can be sorted in place through a memory mapping with the `"mmap"` feature. `MmapSorter` maps a file of `#[repr(C)]` records, checks that its length is a whole number of records, sorts it with only the in-place algorithms by default, and flushes it back to disk when done. See the `mmap` module for details.
+//!
+//! ```toml
+//! [dependencies.rdst]
+//! version = "x.y.z"
+//! features = ["mmap"]
+//! ```
+//!
+//! ## Tracing
+//!
+//! Enabling the `"tracing"` feature emits [tracing](https://docs.rs/tracing) spans for each director call, counting pass and algorithm run, with fields for the level, length, thread and chosen algorithm. Director and algorithm spans are at `DEBUG` level, while counting spans are at `TRACE` level.
+//!
+//! ```toml
+//! [dependencies.rdst]
+//! version = "x.y.z"
+//! features = ["tracing"]
+//! ```
+//!
+//! To see a timeline of a sort's execution instead, the `"chrome_trace"` feature records begin and end timestamps per thread for each phase of the sort, which can be written out as Chrome trace-event JSON and loaded into a viewer such as Perfetto. See the `chrome_trace` module for details.
+//!
 //! ## Custom Tuners
 //!
 //! Tuners are things which you can implement to control which sorting algorithms are used. There are many radix sorting algorithms implemented as part of this crate, and they all have their pros and cons. If you have a very specific use-case it may be worth your time to tune the sort yourself.
@@ -158,6 +245,57 @@
 //!     .sort();
 //! ```
 //!
+//! To also choose parameters for the algorithm, such as the tile size or the number of threads to split it between, a tuner can override `decide()`, which returns a `Decision`. Any parameter that isn't set keeps its default.
+//!
+//! ```
+//! use rdst::RadixSort;
+//! use rdst::tuner::{Algorithm, Decision, Tuner, TuningParams};
+//!
+//! struct MyTuner;
+//!
+//! impl Tuner for MyTuner {
+//!     fn pick_algorithm(&self, p: &TuningParams, _counts: &[usize]) -> Algorithm {
+//!         if p.input_len >= 500_000 {
+//!             Algorithm::Regions
+//!         } else {
+//!             Algorithm::Lsb
+//!         }
+//!     }
+//!
+//!     fn decide(&self, p: &TuningParams, counts: &[usize]) -> Decision {
+//!         match self.pick_algorithm(p, counts) {
+//!             Algorithm::Regions => Decision::new(Algorithm::Regions).with_tile_size(100_000),
+//!             algorithm => Decision::new(algorithm).with_count_while_sorting(false),
+//!         }
+//!     }
+//! }
+//!
+//! let mut my_vec: Vec<usize> = vec![10, 25, 9, 22, 6];
+//! my_vec
+//!     .radix_sort_builder()
+//!     .with_tuner(&MyTuner {})
+//!     .sort();
+//! ```
+//!
+//! Rather than writing a tuner by hand, the `tune` command can measure each algorithm on your machine and generate one, either as a profile for `ProfileTuner` or as Rust source for a `Tuner` implementation:
+//!
+//! ```text
+//! RUSTFLAGS='--cfg bench --cfg tuning' cargo run --release --bin tune --features tune -- profile > my_machine.profile
+//! ```
+//!
+//! ```rust,ignore
+//! use rdst::RadixSort;
+//! use rdst::tuner::ProfileTuner;
+//!
+//! let tuner = ProfileTuner::from_file("my_machine.profile").unwrap();
+//!
+//! let mut my_vec: Vec<usize> = vec![10, 25, 9, 22, 6];
+//! my_vec
+//!     .radix_sort_builder()
+//!     .with_tuner(&tuner)
+//!     .sort();
+//! ```
+//!
 //! ## License
 //!
 //! Licensed under either of
@@ -171,6 +309,28 @@
 //!
 //! Unless you explicitly state otherwise, any contribution intentionally submitted for inclusion in the work by you, as defined in the Apache-2.0 license, shall be dual licensed as above, without any additional terms or conditions.
 
+/// `trace_scope!` records a `chrome_trace` event covering the rest of the enclosing block when the
+/// `chrome_trace` feature is enabled, and does nothing otherwise.
+macro_rules! trace_scope {
+    ($name:expr $(, $arg:ident = $val:expr)* $(,)?) => {
+        #[cfg(feature = "chrome_trace")]
+        let _trace_scope = crate::chrome_trace::Scope::new(
+            || $name.into(),
+            &[$((stringify!($arg), $val as u64)),*],
+        );
+    };
+}
+
+/// `trace_counter!` records the value of a `chrome_trace` counter when the `chrome_trace` feature
+/// is enabled, and does nothing otherwise.
+#[cfg(feature = "multi-threaded")]
+macro_rules! trace_counter {
+    ($name:expr, $val:expr) => {
+        #[cfg(feature = "chrome_trace")]
+        crate::chrome_trace::counter($name, $val as u64);
+    };
+}
+
 mod radix_key;
 mod radix_key_impl;
 mod radix_sort_builder;
@@ -187,13 +347,23 @@ pub mod utils;
 
 mod radix_sort;
 mod sorter;
+
+#[cfg(not(any(test, bench)))]
 mod tuners;
+#[cfg(any(test, bench))]
+pub mod tuners;
 
 // Public modules
+#[cfg(feature = "chrome_trace")]
+pub mod chrome_trace;
+pub mod external;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+pub mod permutation;
 pub mod tuner;
 
 // Public exports
 pub use radix_key::RadixKey;
 pub use radix_sort::RadixSort;
 #[doc(inline)]
-pub use radix_sort_builder::RadixSortBuilder;
+pub use radix_sort_builder::{KeyHint, RadixSortBuilder, SortError};
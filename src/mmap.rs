@@ -0,0 +1,291 @@
+//! `mmap` sorts a file of fixed-width records in place by mapping it into memory, for files that
+//! fit in the address space but not comfortably in RAM.
+//!
+//! The records are sorted directly in the mapped pages. By default no temporary buffers are
+//! allowed, so the built-in tuners only pick the in-place algorithms (`Ska` and `Regions`) and
+//! each page is read and written back at most a few times, rather than being copied to and from
+//! a buffer of the same size as the file. Once the sort finishes, the mapping is flushed to disk.
+//!
+//! Each record is read as the raw bytes of `T`, so `T` should be `#[repr(C)]` (or a primitive)
+//! and the file must have been written in that layout on a machine of the same endianness.
+//!
+//! ```
+//! use rdst::mmap::MmapSorter;
+//! use std::fs::{self, OpenOptions};
+//!
+//! let path = std::env::temp_dir().join(format!("rdst-mmap-doc-{}", std::process::id()));
+//! let bytes: Vec<u8> = [9u32, 3, 7, 1]
+//!     .iter()
+//!     .flat_map(|v| v.to_ne_bytes())
+//!     .collect();
+//! fs::write(&path, bytes).unwrap();
+//!
+//! let file = OpenOptions::new().read(true).write(true).open(&path).unwrap();
+//!
+//! // SAFETY: Every bit pattern is a valid u32 and nothing else has the file open.
+//! unsafe { MmapSorter::new().sort::<u32>(&file).unwrap() };
+//!
+//! let sorted: Vec<u8> = [1u32, 3, 7, 9]
+//!     .iter()
+//!     .flat_map(|v| v.to_ne_bytes())
+//!     .collect();
+//! assert_eq!(fs::read(&path).unwrap(), sorted);
+//! # fs::remove_file(&path).unwrap();
+//! ```
+
+use crate::sorter::Sorter;
+use crate::tuner::Tuner;
+use crate::tuners::SingleThreadedTuner;
+#[cfg(feature = "multi-threaded")]
+use crate::tuners::StandardTuner;
+use crate::RadixKey;
+use memmap2::MmapOptions;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{self, ErrorKind};
+use std::mem::align_of;
+use std::slice;
+
+/// `MmapSorter` sorts a file of fixed-width records in place through a memory mapping. See the
+/// `mmap` module for an example.
+pub struct MmapSorter<'a> {
+    memory_budget: usize,
+    multi_threaded: bool,
+    tuner: &'a (dyn Tuner + Send + Sync),
+}
+
+impl<'a> Default for MmapSorter<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> MmapSorter<'a> {
+    pub fn new() -> Self {
+        #[cfg(feature = "multi-threaded")]
+        let (tuner, multi_threaded) = (&StandardTuner, true);
+        #[cfg(not(feature = "multi-threaded"))]
+        let (tuner, multi_threaded) = (&SingleThreadedTuner, false);
+
+        Self {
+            memory_budget: 0,
+            multi_threaded,
+            tuner,
+        }
+    }
+
+    /// `with_memory_budget()` sets the number of bytes the sort may allocate for temporary
+    /// buffers, as with `RadixSortBuilder::with_memory_budget`. Defaults to 0, so that the
+    /// built-in tuners only use in-place algorithms. Raising it lets them pick out-of-place
+    /// algorithms for buckets that fit, which can be faster when the file is mostly resident.
+    pub fn with_memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = bytes;
+
+        self
+    }
+
+    /// `with_parallel()` sets whether the sort may use multiple threads.
+    pub fn with_parallel(mut self, parallel: bool) -> Self {
+        self.multi_threaded = parallel;
+
+        self
+    }
+
+    /// `with_single_threaded_tuner()` configures the sort to use a tuner which only uses
+    /// single-threaded sorting algorithms.
+    pub fn with_single_threaded_tuner(mut self) -> Self {
+        self.tuner = &SingleThreadedTuner;
+
+        self
+    }
+
+    /// `with_tuner()` allows you to provide your own tuning for which sorting algorithm to use
+    /// in a given situation. Custom tuners should check `TuningParams::fits_memory_budget()` to
+    /// keep to in-place algorithms.
+    pub fn with_tuner(mut self, tuner: &'a (dyn Tuner + Send + Sync)) -> Self {
+        self.tuner = tuner;
+
+        self
+    }
+
+    /// `sort()` maps `file`, which must be open for reading and writing, sorts its records in
+    /// place and flushes the changes to disk before returning.
+    ///
+    /// Returns an error of kind `InvalidData` if the length of the file isn't a multiple of the
+    /// size of `T` or the mapping isn't aligned for `T`, along with any error from mapping or
+    /// flushing the file.
+    ///
+    /// # Safety
+    ///
+    /// Every bit pattern of the size of `T` must be a valid `T`, as the contents of the file are
+    /// used as-is. The file must not be modified or truncated, by this or any other process,
+    /// while it is being sorted.
+    pub unsafe fn sort<T>(&self, file: &File) -> io::Result<()>
+    where
+        T: RadixKey + Copy + Send + Sync,
+    {
+        assert_ne!(T::LEVELS, 0, "RadixKey must have at least 1 level");
+
+        let item_size = size_of::<T>();
+
+        if item_size == 0 {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "zero-sized records can't be read from a file",
+            ));
+        }
+
+        let file_len: usize = file
+            .metadata()?
+            .len()
+            .try_into()
+            .map_err(|_| io::Error::new(ErrorKind::InvalidData, "file is too large to map"))?;
+
+        if !file_len.is_multiple_of(item_size) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "file length {} is not a multiple of the record size {}",
+                    file_len, item_size
+                ),
+            ));
+        }
+
+        // An empty file can't be mapped, and is already sorted
+        if file_len == 0 {
+            return Ok(());
+        }
+
+        let mut map = MmapOptions::new().len(file_len).map_mut(file)?;
+
+        if !(map.as_ptr() as usize).is_multiple_of(align_of::<T>()) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "mapping is not aligned for the record type",
+            ));
+        }
+
+        let data = slice::from_raw_parts_mut(map.as_mut_ptr() as *mut T, file_len / item_size);
+
+        if data.len() > 1 {
+            Sorter::new(self.multi_threaded, self.tuner)
+                .with_memory_budget(Some(self.memory_budget))
+                .top_level_director(data);
+        }
+
+        map.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mmap::MmapSorter;
+    use crate::RadixKey;
+    use block_pseudorand::block_rand;
+    use std::convert::TryInto;
+    use std::fs::{self, File, OpenOptions};
+    use std::io::ErrorKind;
+    use std::path::PathBuf;
+
+    #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+    #[repr(C)]
+    struct Entry {
+        key: u32,
+        value: u32,
+    }
+
+    impl RadixKey for Entry {
+        const LEVELS: usize = 4;
+
+        #[inline]
+        fn get_level(&self, level: usize) -> u8 {
+            self.key.get_level(level)
+        }
+    }
+
+    fn temp_file(name: &str, bytes: &[u8]) -> (PathBuf, File) {
+        let path = std::env::temp_dir().join(format!("rdst-mmap-{}-{}", name, std::process::id()));
+        fs::write(&path, bytes).unwrap();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+
+        (path, file)
+    }
+
+    #[test]
+    pub fn test_mmap_sort() {
+        for size in [0, 1, 200, 50_000, 1_500_000] {
+            let data: Vec<u64> = block_rand(size);
+            let bytes: Vec<u8> = data.iter().flat_map(|v| v.to_ne_bytes()).collect();
+            let mut expected = data.clone();
+            expected.sort_unstable();
+
+            for parallel in [false, true] {
+                let (path, file) = temp_file("u64", &bytes);
+                unsafe {
+                    MmapSorter::new()
+                        .with_parallel(parallel)
+                        .sort::<u64>(&file)
+                        .unwrap()
+                };
+
+                let sorted: Vec<u64> = fs::read(&path)
+                    .unwrap()
+                    .chunks_exact(8)
+                    .map(|c| u64::from_ne_bytes(c.try_into().unwrap()))
+                    .collect();
+                fs::remove_file(&path).unwrap();
+
+                assert_eq!(sorted, expected, "size: {}, parallel: {}", size, parallel);
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_mmap_sort_struct() {
+        let keys: Vec<u32> = block_rand(100_000);
+        let entries: Vec<Entry> = keys
+            .iter()
+            .enumerate()
+            .map(|(i, &key)| Entry {
+                key,
+                value: i as u32,
+            })
+            .collect();
+        let bytes: Vec<u8> = entries
+            .iter()
+            .flat_map(|e| [e.key.to_ne_bytes(), e.value.to_ne_bytes()].concat())
+            .collect();
+
+        let (path, file) = temp_file("struct", &bytes);
+        unsafe { MmapSorter::new().sort::<Entry>(&file).unwrap() };
+
+        let sorted: Vec<Entry> = fs::read(&path)
+            .unwrap()
+            .chunks_exact(8)
+            .map(|c| Entry {
+                key: u32::from_ne_bytes(c[..4].try_into().unwrap()),
+                value: u32::from_ne_bytes(c[4..].try_into().unwrap()),
+            })
+            .collect();
+        fs::remove_file(&path).unwrap();
+
+        // Unstable, so compare the keys and check that each record kept its value
+        let mut expected = keys.clone();
+        expected.sort_unstable();
+        assert_eq!(sorted.iter().map(|e| e.key).collect::<Vec<_>>(), expected);
+        assert!(sorted.iter().all(|e| keys[e.value as usize] == e.key));
+    }
+
+    #[test]
+    pub fn test_mmap_sort_bad_length() {
+        let (path, file) = temp_file("bad-length", &[0u8; 13]);
+        let err = unsafe { MmapSorter::new().sort::<u32>(&file).unwrap_err() };
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
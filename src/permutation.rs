@@ -0,0 +1,210 @@
+//! Utilities for reordering data by a permutation, such as one produced by sorting indexes.
+//!
+//! A permutation `perm` says that the item at `perm[i]` belongs at position `i`, which is the
+//! order an argsort produces. Applying the same permutation to several arrays keeps them in step
+//! with each other, for instance when storing a table as separate columns.
+//!
+//! ```
+//! use rdst::permutation::{apply_permutation_in_place, gather};
+//!
+//! let keys: Vec<u32> = vec![30, 10, 20];
+//! let mut names = vec!["c", "a", "b"];
+//!
+//! let mut order: Vec<(u32, usize)> = keys.iter().copied().zip(0..).collect();
+//! order.sort_unstable();
+//! let perm: Vec<usize> = order.iter().map(|(_, i)| *i).collect();
+//!
+//! assert_eq!(gather(&keys, &perm), vec![10, 20, 30]);
+//!
+//! apply_permutation_in_place(&mut names, &perm);
+//! assert_eq!(names, vec!["a", "b", "c"]);
+//! ```
+//!
+//! `gather` reads each item once and writes it once, spread across threads when the
+//! `multi-threaded` feature is enabled, but needs a second array to write into.
+//! `apply_permutation_in_place` only needs one bit per item of extra memory, but follows each
+//! cycle of the permutation one item at a time, so is generally slower for large inputs.
+
+#[cfg(feature = "multi-threaded")]
+use rayon::prelude::*;
+
+/// `apply_permutation_in_place` reorders `data` so that the item previously at `perm[i]` is at
+/// position `i`. It follows each cycle of the permutation in turn, swapping items into place and
+/// marking them in a bitset as it goes.
+///
+/// Panics if `perm` is a different length to `data` or is not a permutation, i.e. if any index
+/// is out of bounds or appears twice. In that case `data` is left in an unspecified order.
+///
+/// ```
+/// use rdst::permutation::apply_permutation_in_place;
+///
+/// let mut data = vec!['a', 'b', 'c', 'd'];
+/// apply_permutation_in_place(&mut data, &[3, 0, 1, 2]);
+///
+/// assert_eq!(data, vec!['d', 'a', 'b', 'c']);
+/// ```
+pub fn apply_permutation_in_place<T>(data: &mut [T], perm: &[usize]) {
+    assert_eq!(
+        data.len(),
+        perm.len(),
+        "permutation length must match data length"
+    );
+
+    permute_in_place(data, |i| perm[i]);
+}
+
+/// `permute_in_place` is `apply_permutation_in_place` with the permutation given as a function
+/// from each position to the position its item comes from, so that it needn't be stored as a
+/// `usize` slice.
+pub(crate) fn permute_in_place<T>(data: &mut [T], perm: impl Fn(usize) -> usize) {
+    let mut visited = vec![0u64; data.len().div_ceil(64)];
+    let mut is_visited = |i: usize| {
+        let (word, bit) = (i / 64, 1 << (i % 64));
+        let seen = visited[word] & bit != 0;
+        visited[word] |= bit;
+
+        seen
+    };
+
+    for start in 0..data.len() {
+        if is_visited(start) {
+            continue;
+        }
+
+        let mut current = start;
+
+        loop {
+            let next = perm(current);
+            assert!(
+                next < data.len(),
+                "permutation index {} out of bounds",
+                next
+            );
+
+            if next == start {
+                break;
+            }
+
+            assert!(
+                !is_visited(next),
+                "index {} appears twice in permutation",
+                next
+            );
+
+            // `current` takes the item from `next`, which hasn't been moved yet, while the item
+            // from `start` moves along to `next`
+            data.swap(current, next);
+            current = next;
+        }
+    }
+}
+
+/// `gather` returns a new array holding `data[perm[i]]` at each position `i`. When the
+/// `multi-threaded` feature is enabled, larger inputs are gathered in parallel.
+///
+/// Unlike `apply_permutation_in_place`, `perm` doesn't need to be a permutation or the same
+/// length as `data`. Any list of in-bounds indexes can be gathered, including repeated ones.
+///
+/// Panics if any index is out of bounds.
+///
+/// ```
+/// use rdst::permutation::gather;
+///
+/// let data = vec![1.5, 2.5, 3.5];
+///
+/// assert_eq!(gather(&data, &[2, 0, 1]), vec![3.5, 1.5, 2.5]);
+/// ```
+pub fn gather<T>(data: &[T], perm: &[usize]) -> Vec<T>
+where
+    T: Copy + Send + Sync,
+{
+    #[cfg(feature = "multi-threaded")]
+    if perm.len() >= 100_000 {
+        return perm.par_iter().map(|i| data[*i]).collect();
+    }
+
+    perm.iter().map(|i| data[*i]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_permutation_in_place, gather};
+    use block_pseudorand::block_rand;
+
+    fn random_permutation(len: usize) -> Vec<usize> {
+        let keys: Vec<u64> = block_rand(len);
+        let mut perm: Vec<usize> = (0..len).collect();
+        perm.sort_unstable_by_key(|i| keys[*i]);
+
+        perm
+    }
+
+    #[test]
+    pub fn test_apply_permutation_in_place() {
+        for len in [0, 1, 2, 3, 64, 65, 1_000, 200_000] {
+            let data: Vec<u64> = block_rand(len);
+            let mut perms = vec![
+                (0..len).collect::<Vec<usize>>(),
+                (0..len).rev().collect(),
+                random_permutation(len),
+            ];
+
+            if len > 0 {
+                // A single cycle covering every item
+                perms.push((0..len).map(|i| (i + 1) % len).collect());
+            }
+
+            for perm in perms {
+                let expected: Vec<u64> = perm.iter().map(|i| data[*i]).collect();
+
+                let mut actual = data.clone();
+                apply_permutation_in_place(&mut actual, &perm);
+                assert_eq!(actual, expected);
+
+                assert_eq!(gather(&data, &perm), expected);
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_apply_permutation_in_place_not_copy() {
+        let mut data: Vec<String> = (0..100).map(|i| i.to_string()).collect();
+        let perm = random_permutation(100);
+        let expected: Vec<String> = perm.iter().map(|i| data[*i].clone()).collect();
+
+        apply_permutation_in_place(&mut data, &perm);
+
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    #[should_panic(expected = "appears twice")]
+    pub fn test_apply_permutation_in_place_duplicate() {
+        apply_permutation_in_place(&mut [1, 2, 3, 4], &[1, 2, 1, 0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    pub fn test_apply_permutation_in_place_out_of_bounds() {
+        apply_permutation_in_place(&mut [1, 2, 3], &[0, 3, 1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "length")]
+    pub fn test_apply_permutation_in_place_wrong_length() {
+        apply_permutation_in_place(&mut [1, 2, 3], &[0, 1]);
+    }
+
+    #[test]
+    pub fn test_gather_repeated() {
+        let data: Vec<u32> = (0..10).collect();
+        let indexes: Vec<usize> = (0..300_000).map(|i| (i * 7) % 10).collect();
+
+        let gathered = gather(&data, &indexes);
+
+        assert!(gathered
+            .iter()
+            .zip(indexes.iter())
+            .all(|(g, i)| *g as usize == *i));
+    }
+}
@@ -2,4 +2,17 @@ pub trait RadixKey {
     const LEVELS: usize;
 
     fn get_level(&self, level: usize) -> u8;
+
+    /// `extract_level` writes `items[i].get_level(level)` into `out[i]` for every item, returning
+    /// false without writing anything if the type has no faster way of doing so than calling
+    /// `get_level` on each item. This is implemented for primitive integers to allow counting to
+    /// use SIMD.
+    #[doc(hidden)]
+    #[inline]
+    fn extract_level(_items: &[Self], _level: usize, _out: &mut [u8]) -> bool
+    where
+        Self: Sized,
+    {
+        false
+    }
 }
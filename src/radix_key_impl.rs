@@ -1,3 +1,4 @@
+use crate::utils::simd_count::*;
 use crate::RadixKey;
 
 impl RadixKey for u8 {
@@ -25,6 +26,11 @@ impl RadixKey for u32 {
     fn get_level(&self, level: usize) -> u8 {
         (self >> (level * 8)) as u8
     }
+
+    #[inline]
+    fn extract_level(items: &[Self], level: usize, out: &mut [u8]) -> bool {
+        extract_u32(items, level, out)
+    }
 }
 
 impl RadixKey for u64 {
@@ -34,6 +40,11 @@ impl RadixKey for u64 {
     fn get_level(&self, level: usize) -> u8 {
         (self >> (level * 8)) as u8
     }
+
+    #[inline]
+    fn extract_level(items: &[Self], level: usize, out: &mut [u8]) -> bool {
+        extract_u64(items, level, out)
+    }
 }
 
 impl RadixKey for u128 {
@@ -63,6 +74,11 @@ impl RadixKey for usize {
     fn get_level(&self, level: usize) -> u8 {
         (self >> (level * 8)) as u8
     }
+
+    #[inline]
+    fn extract_level(items: &[Self], level: usize, out: &mut [u8]) -> bool {
+        extract_cast(items, level, out, extract_u32)
+    }
 }
 
 #[cfg(target_pointer_width = "64")]
@@ -73,6 +89,11 @@ impl RadixKey for usize {
     fn get_level(&self, level: usize) -> u8 {
         (self >> (level * 8)) as u8
     }
+
+    #[inline]
+    fn extract_level(items: &[Self], level: usize, out: &mut [u8]) -> bool {
+        extract_cast(items, level, out, extract_u64)
+    }
 }
 
 impl<const N: usize> RadixKey for [u8; N] {
@@ -109,6 +130,11 @@ impl RadixKey for i32 {
     fn get_level(&self, level: usize) -> u8 {
         ((self ^ i32::MIN) >> (level * 8)) as u8
     }
+
+    #[inline]
+    fn extract_level(items: &[Self], level: usize, out: &mut [u8]) -> bool {
+        extract_signed(items, level, out, extract_u32)
+    }
 }
 
 impl RadixKey for i64 {
@@ -118,6 +144,11 @@ impl RadixKey for i64 {
     fn get_level(&self, level: usize) -> u8 {
         ((self ^ i64::MIN) >> (level * 8)) as u8
     }
+
+    #[inline]
+    fn extract_level(items: &[Self], level: usize, out: &mut [u8]) -> bool {
+        extract_signed(items, level, out, extract_u64)
+    }
 }
 
 impl RadixKey for i128 {
@@ -147,6 +178,11 @@ impl RadixKey for isize {
     fn get_level(&self, level: usize) -> u8 {
         ((self ^ isize::MIN) >> (level * 8)) as u8
     }
+
+    #[inline]
+    fn extract_level(items: &[Self], level: usize, out: &mut [u8]) -> bool {
+        extract_signed(items, level, out, extract_u32)
+    }
 }
 
 #[cfg(target_pointer_width = "64")]
@@ -157,6 +193,11 @@ impl RadixKey for isize {
     fn get_level(&self, level: usize) -> u8 {
         ((self ^ isize::MIN) >> (level * 8)) as u8
     }
+
+    #[inline]
+    fn extract_level(items: &[Self], level: usize, out: &mut [u8]) -> bool {
+        extract_signed(items, level, out, extract_u64)
+    }
 }
 
 impl RadixKey for f32 {
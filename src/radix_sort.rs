@@ -15,7 +15,7 @@ pub trait RadixSort<T> {
     /// ```
     fn radix_sort_unstable(&mut self);
 
-    fn radix_sort_builder(&mut self) -> RadixSortBuilder<T>;
+    fn radix_sort_builder(&mut self) -> RadixSortBuilder<'_, T>;
 }
 
 impl<T> RadixSort<T> for Vec<T>
@@ -26,7 +26,7 @@ where
         self.radix_sort_builder().sort();
     }
 
-    fn radix_sort_builder(&mut self) -> RadixSortBuilder<T> {
+    fn radix_sort_builder(&mut self) -> RadixSortBuilder<'_, T> {
         RadixSortBuilder::new(self)
     }
 }
@@ -39,19 +39,23 @@ where
         self.radix_sort_builder().sort();
     }
 
-    fn radix_sort_builder(&mut self) -> RadixSortBuilder<T> {
+    fn radix_sort_builder(&mut self) -> RadixSortBuilder<'_, T> {
         RadixSortBuilder::new(self)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::tuner::{Algorithm, Tuner, TuningParams};
+    use crate::tuner::{Algorithm, Decision, KeyHints, Tuner, TuningParams};
+    use crate::tuners::StandardTuner;
     use crate::utils::test_utils::{sort_comparison_suite, NumericTest, SingleAlgoTuner};
-    use crate::RadixSort;
+    use crate::{KeyHint, RadixSort};
     use block_pseudorand::block_rand;
     use std::cmp::Ordering;
     use std::fmt::Debug;
+    use std::ops::RangeInclusive;
+    use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+    use std::sync::Mutex;
 
     fn test_full_sort<T>(shift: T)
     where
@@ -69,6 +73,15 @@ mod tests {
         });
     }
 
+    fn test_memory_budget_full_sort<T>(shift: T)
+    where
+        T: NumericTest<T>,
+    {
+        sort_comparison_suite(shift, |inputs| {
+            inputs.radix_sort_builder().with_memory_budget(1024).sort()
+        });
+    }
+
     fn test_custom_tuner_full_sort<T>(shift: T)
     where
         T: NumericTest<T>,
@@ -288,6 +301,16 @@ mod tests {
         });
     }
 
+    #[test]
+    pub fn test_memory_budget_u32() {
+        test_memory_budget_full_sort(16u32);
+    }
+
+    #[test]
+    pub fn test_memory_budget_u64() {
+        test_memory_budget_full_sort(32u64);
+    }
+
     #[test]
     pub fn test_custom_tuner_u32() {
         test_custom_tuner_full_sort(16u32);
@@ -298,6 +321,335 @@ mod tests {
         test_custom_tuner_full_sort(32u64);
     }
 
+    #[test]
+    pub fn test_reversed_u32() {
+        let mut data: Vec<u32> = (0..1_000_000u32).rev().collect();
+        data.radix_sort_unstable();
+
+        assert!(data.iter().copied().eq(0..1_000_000u32));
+    }
+
+    #[test]
+    pub fn test_mixed_reversed_u64() {
+        // Runs alternating between ascending and descending, and a descending run within a
+        // bucket which is otherwise unsorted
+        let mut data: Vec<u64> = (0..16u64)
+            .flat_map(|run| {
+                let values = (run << 56)..((run << 56) + 50_000);
+                if run % 2 == 0 {
+                    values.rev().collect::<Vec<_>>()
+                } else {
+                    values.collect()
+                }
+            })
+            .collect();
+        data.extend((0..50_000u64).map(|v| v.wrapping_mul(0x9E37_79B9_7F4A_7C15)));
+        let mut expected = data.clone();
+        expected.sort_unstable();
+
+        data.radix_sort_unstable();
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    pub fn test_appended_runs_u64() {
+        // Two sorted inputs appended to each other, which the tuners merge
+        for multi_threaded in [true, false] {
+            let mut data: Vec<u64> = block_rand(600_000);
+            data[..400_000].sort_unstable();
+            data[400_000..].sort_unstable();
+            let mut expected = data.clone();
+            expected.sort_unstable();
+
+            data.radix_sort_builder()
+                .with_parallel(multi_threaded)
+                .sort();
+            assert_eq!(data, expected);
+        }
+    }
+
+    #[test]
+    pub fn test_tile_reversed_u32() {
+        // Each tile is in descending order, but the chunk as a whole is not
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+
+        for tiles in [2u32, 4] {
+            let len = 400_000u32;
+            let mut data: Vec<u32> = (0..len)
+                .map(|i| {
+                    let tile = i / (len / tiles);
+                    let offset = i % (len / tiles);
+                    tile << 30 | ((len / tiles) - 1 - offset) << 12
+                })
+                .collect();
+            let mut expected = data.clone();
+            expected.sort_unstable();
+
+            pool.install(|| data.radix_sort_unstable());
+            assert_eq!(data, expected);
+        }
+    }
+
+    #[test]
+    pub fn test_skewed_buckets_u32() {
+        // One huge bucket, a few mid-sized ones and many tiny ones, including single items
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+
+        for len in [50_000, 2_000_000] {
+            let mut data: Vec<u32> = block_rand::<u32>(len)
+                .into_iter()
+                .enumerate()
+                .map(|(i, v)| match i % 100 {
+                    0..=79 => v & 0x00FF_FFFF,
+                    80..=94 => (v & 0x03FF_FFFF) | 0x1000_0000,
+                    _ => v | 0x8000_0000,
+                })
+                .collect();
+            let mut expected = data.clone();
+            expected.sort_unstable();
+
+            pool.install(|| data.radix_sort_unstable());
+            assert_eq!(data, expected);
+        }
+    }
+
+    /// Picks `algorithm` for every bucket, checking that it is never called from a rayon thread.
+    struct StrictTuner {
+        algorithm: Algorithm,
+    }
+
+    impl Tuner for StrictTuner {
+        fn pick_algorithm(&self, _p: &TuningParams, _counts: &[usize]) -> Algorithm {
+            assert_eq!(rayon::current_thread_index(), None);
+
+            self.algorithm
+        }
+    }
+
+    #[test]
+    pub fn test_strict_single_threaded() {
+        let inputs = block_rand::<u64>(400_000);
+        let mut expected = inputs.clone();
+        expected.sort_unstable();
+
+        for algorithm in Algorithm::ALL {
+            let tuner = StrictTuner {
+                algorithm: *algorithm,
+            };
+
+            let mut data = inputs.clone();
+            data.radix_sort_builder()
+                .with_tuner(&tuner)
+                .with_strict_single_threaded()
+                .sort();
+            assert_eq!(data, expected);
+
+            let mut data = inputs.clone();
+            let result = data
+                .radix_sort_builder()
+                .with_tuner(&tuner)
+                .with_strict_single_threaded()
+                .try_sort();
+
+            if algorithm.is_multi_threaded() {
+                assert_eq!(result.unwrap_err().algorithm(), *algorithm);
+            } else {
+                assert_eq!(result, Ok(()));
+                assert_eq!(data, expected);
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_single_threaded_equivalent() {
+        for algorithm in Algorithm::ALL {
+            let equivalent = algorithm.single_threaded_equivalent();
+            assert!(!equivalent.is_multi_threaded());
+
+            if !algorithm.is_multi_threaded() {
+                assert_eq!(equivalent, *algorithm);
+            }
+        }
+    }
+
+    /// Picks `decision` for every bucket.
+    struct DecisionTuner {
+        decision: Decision,
+    }
+
+    impl Tuner for DecisionTuner {
+        fn pick_algorithm(&self, _p: &TuningParams, _counts: &[usize]) -> Algorithm {
+            self.decision.algorithm
+        }
+
+        fn decide(&self, _p: &TuningParams, _counts: &[usize]) -> Decision {
+            self.decision
+        }
+    }
+
+    #[test]
+    pub fn test_decision_parameters() {
+        let inputs = block_rand::<u32>(300_000);
+        let mut expected = inputs.clone();
+        expected.sort_unstable();
+
+        for algorithm in Algorithm::ALL {
+            // Out of range values must be clamped rather than used as is
+            for decision in [
+                Decision::new(*algorithm)
+                    .with_tile_size(50_000)
+                    .with_buffer_capacity(16)
+                    .with_count_while_sorting(false),
+                Decision::new(*algorithm).with_threads(3),
+                Decision::new(*algorithm)
+                    .with_tile_size(0)
+                    .with_threads(0)
+                    .with_buffer_capacity(0),
+                Decision::new(*algorithm).with_tile_size(usize::MAX),
+                Decision::new(*algorithm).with_digit_bits(16),
+                Decision::new(*algorithm).with_digit_bits(usize::MAX),
+            ] {
+                let mut data = inputs.clone();
+                data.radix_sort_builder()
+                    .with_tuner(&DecisionTuner { decision })
+                    .sort();
+                assert_eq!(data, expected, "{:?}", decision);
+            }
+        }
+    }
+
+    /// Uses the standard tuning, recording the highest level and the hints it is asked about.
+    #[derive(Default)]
+    struct HintTuner {
+        max_level: AtomicUsize,
+        key_hints: Mutex<Option<KeyHints>>,
+    }
+
+    impl Tuner for HintTuner {
+        fn pick_algorithm(&self, p: &TuningParams, counts: &[usize]) -> Algorithm {
+            self.max_level.fetch_max(p.level, AtomicOrdering::Relaxed);
+            *self.key_hints.lock().unwrap() = Some(p.key_hints);
+
+            StandardTuner.pick_algorithm(p, counts)
+        }
+    }
+
+    #[test]
+    pub fn test_key_hint_range() {
+        let mut data: Vec<u32> = block_rand::<u32>(1_000_000)
+            .into_iter()
+            .map(|v| v % 10_000)
+            .collect();
+        let mut expected = data.clone();
+        expected.sort_unstable();
+
+        let tuner = HintTuner::default();
+        data.radix_sort_builder()
+            .with_tuner(&tuner)
+            .with_key_hint(KeyHint::Range { min: 0, max: 9_999 })
+            .with_key_hint(KeyHint::Range {
+                min: 0,
+                max: 100_000,
+            })
+            .sort();
+        assert_eq!(data, expected);
+
+        // The narrower range is used, and the levels above it are never looked at
+        assert_eq!(tuner.max_level.load(AtomicOrdering::Relaxed), 1);
+        let hints = tuner.key_hints.lock().unwrap().unwrap();
+        assert_eq!(hints.max_level, Some(1));
+    }
+
+    #[test]
+    pub fn test_key_hint_uniform() {
+        // Even when the order isn't checked, sorted and descending inputs must still be sorted
+        for pattern in 0..3 {
+            let mut data = block_rand::<u64>(1_000_000);
+
+            match pattern {
+                1 => data.sort_unstable(),
+                2 => data.sort_unstable_by(|a, b| b.cmp(a)),
+                _ => {}
+            }
+
+            let mut expected = data.clone();
+            expected.sort_unstable();
+
+            let tuner = HintTuner::default();
+            data.radix_sort_builder()
+                .with_tuner(&tuner)
+                .with_key_hint(KeyHint::Uniform)
+                .sort();
+            assert_eq!(data, expected);
+
+            let hints = tuner.key_hints.lock().unwrap().unwrap();
+            assert!(hints.uniform);
+            assert!(hints.skip_order_checks());
+        }
+    }
+
+    #[test]
+    pub fn test_key_hints_reach_tuner() {
+        let mut data = block_rand::<u32>(100_000);
+        let tuner = HintTuner::default();
+
+        data.radix_sort_builder()
+            .with_tuner(&tuner)
+            .with_key_hint(KeyHint::MostlySorted)
+            .with_key_hint(KeyHint::ManyDuplicates)
+            .with_key_hint(KeyHint::Uniform)
+            .sort();
+        assert!(data.windows(2).all(|w| w[0] <= w[1]));
+
+        let hints = tuner.key_hints.lock().unwrap().unwrap();
+        assert!(hints.mostly_sorted);
+        assert!(hints.many_duplicates);
+        assert!(hints.uniform);
+        assert!(!hints.skip_order_checks());
+        assert_eq!(hints.max_level, None);
+    }
+
+    #[test]
+    pub fn test_is_available() {
+        for algorithm in Algorithm::ALL {
+            assert!(algorithm.is_available());
+        }
+
+        assert_eq!(
+            Algorithm::Regions.is_available(),
+            cfg!(feature = "multi-threaded")
+        );
+    }
+
+    #[test]
+    pub fn test_multi_threaded_algorithms_any_features() {
+        // Without the multi-threaded feature, these fall back to single-threaded algorithms
+        for algo in [
+            Algorithm::MtOop,
+            Algorithm::MtLsb,
+            Algorithm::MtStableMsb,
+            Algorithm::Scanning,
+            Algorithm::Recombinating,
+            Algorithm::Regions,
+            Algorithm::Block,
+        ] {
+            let mut data = block_rand::<u32>(300_000);
+            let mut expected = data.clone();
+            expected.sort_unstable();
+
+            data.radix_sort_builder()
+                .with_tuner(&SingleAlgoTuner { algo })
+                .sort();
+            assert_eq!(data, expected);
+        }
+    }
+
     #[test]
     pub fn test_f64_parallel_false_only() {
         let mut data = block_rand::<f64>(10_000_000);
@@ -309,4 +661,83 @@ mod tests {
             })
             .sort();
     }
+
+    struct LevelRangeTuner {
+        algorithm: Algorithm,
+        levels: RangeInclusive<usize>,
+    }
+
+    impl Tuner for LevelRangeTuner {
+        fn pick_algorithm(&self, p: &TuningParams, _counts: &[usize]) -> Algorithm {
+            // Only these levels differ, so no others should be sorted
+            assert!(self.levels.contains(&p.level), "sorted level {}", p.level);
+
+            self.algorithm
+        }
+    }
+
+    #[test]
+    pub fn test_level_range() {
+        let seeds: Vec<u64> = block_rand(300_000);
+
+        // Constant levels at both ends, and only at the bottom, where the level range is found
+        // while counting the top level
+        for (mask, shift, levels) in [(0xFF_FFFF, 16, 2..=4), (0xFFFF_FFFF_FFFF, 16, 2..=7)] {
+            let inputs: Vec<u64> = seeds
+                .iter()
+                .map(|s| 0xAB00_0000_0000_00CD | ((s & mask) << shift))
+                .collect();
+
+            let mut expected = inputs.clone();
+            expected.sort_unstable();
+
+            for algorithm in [
+                Algorithm::Lsb,
+                Algorithm::LrLsb,
+                Algorithm::MtLsb,
+                Algorithm::Ska,
+                Algorithm::MtStableMsb,
+            ] {
+                let mut actual = inputs.clone();
+                actual
+                    .radix_sort_builder()
+                    .with_tuner(&LevelRangeTuner {
+                        algorithm,
+                        levels: levels.clone(),
+                    })
+                    .sort();
+
+                assert_eq!(actual, expected);
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_key_hint_range_level_range() {
+        // The range hint gives the highest level, but the lowest must still be found
+        let inputs: Vec<u64> = block_rand::<u64>(300_000)
+            .iter()
+            .map(|s| 0xCD | ((s & 0xFF_FFFF) << 16))
+            .collect();
+
+        let mut expected = inputs.clone();
+        expected.sort_unstable();
+
+        for algorithm in [Algorithm::Lsb, Algorithm::Ska] {
+            let mut actual = inputs.clone();
+            actual
+                .radix_sort_builder()
+                .with_tuner(&LevelRangeTuner {
+                    algorithm,
+                    levels: 2..=4,
+                })
+                .with_key_hint(KeyHint::Range {
+                    min: 0,
+                    max: 0xFF_FFFF_FFFF,
+                })
+                .sort();
+
+            assert_eq!(actual, expected);
+        }
+    }
 }
@@ -1,14 +1,67 @@
 use crate::sorter::Sorter;
-use crate::tuner::Tuner;
-use crate::tuners::SingleThreadedTuner;
+use crate::tuner::{Algorithm, KeyHints, RegretReport, Tuner};
 #[cfg(feature = "multi-threaded")]
 use crate::tuners::{LowMemoryTuner, StandardTuner};
+use crate::tuners::{RegretRecorder, SingleThreadedTuner};
 use crate::RadixKey;
+use std::error::Error;
+use std::fmt;
+use std::sync::Mutex;
+
+/// Error returned by `RadixSortBuilder::try_sort` when the tuner picks a multi-threaded algorithm
+/// for a strictly single-threaded sort.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SortError {
+    algorithm: Algorithm,
+}
+
+impl SortError {
+    /// `algorithm()` returns the multi-threaded algorithm that the tuner picked.
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+}
+
+impl fmt::Display for SortError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "tuner picked multi-threaded algorithm {} for a strictly single-threaded sort",
+            self.algorithm
+        )
+    }
+}
+
+impl Error for SortError {}
+
+/// `KeyHint` describes the keys being sorted, when something about them is known ahead of time.
+/// See `RadixSortBuilder::with_key_hint`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum KeyHint<T> {
+    /// Every key is between `min` and `max` inclusive. Levels above the highest level at which
+    /// `min` and `max` differ hold the same byte for every key, so they are skipped without
+    /// reading the input.
+    Range { min: T, max: T },
+    /// Most of the keys are already in order. The built-in tuners then pick the algorithms they
+    /// use for nearly sorted input, even where counting finds less order than this.
+    MostlySorted,
+    /// Many of the keys are equal to each other. The built-in tuners then pick the algorithms
+    /// they use for skewed distributions, even where counting finds the keys evenly spread.
+    ManyDuplicates,
+    /// The keys are uniformly random, so they are counted without checking whether they are
+    /// already in order. This is ignored if they are also hinted to be mostly sorted.
+    Uniform,
+}
 
 pub struct RadixSortBuilder<'a, T> {
     data: &'a mut [T],
     multi_threaded: bool,
     tuner: &'a (dyn Tuner + Send + Sync),
+    memory_budget: Option<usize>,
+    indirect: bool,
+    strict: bool,
+    key_hints: KeyHints,
 }
 
 impl<'a, T> RadixSortBuilder<'a, T>
@@ -29,6 +82,10 @@ where
             data,
             multi_threaded,
             tuner,
+            memory_budget: None,
+            indirect: false,
+            strict: false,
+            key_hints: KeyHints::default(),
         }
     }
 
@@ -37,7 +94,8 @@ where
     /// multi-threaded algorithms will get used.
     ///
     /// If you also want the algorithms chosen to be only single-threaded algorithms,
-    /// combine this with `with_single_threaded_tuner()`.
+    /// combine this with `with_single_threaded_tuner()`, or use `with_strict_single_threaded()`
+    /// to guarantee it whichever tuner is used.
     ///
     /// ```
     /// use rdst::RadixSort;
@@ -55,6 +113,26 @@ where
         self
     }
 
+    /// `with_strict_single_threaded()` guarantees that the sort runs entirely on the calling
+    /// thread. Buckets aren't sorted in parallel, as with `with_parallel(false)`, and if the tuner
+    /// picks a multi-threaded algorithm, its single-threaded equivalent is used instead (see
+    /// `Algorithm::single_threaded_equivalent`). Use `try_sort()` to get an error instead.
+    ///
+    /// ```
+    /// use rdst::RadixSort;
+    /// let mut data: Vec<usize> = vec![5, 22, 3, 7, 9];
+    ///
+    /// data
+    ///     .radix_sort_builder()
+    ///     .with_strict_single_threaded()
+    ///     .sort();
+    /// ```
+    pub fn with_strict_single_threaded(mut self) -> Self {
+        self.strict = true;
+
+        self
+    }
+
     /// `with_low_mem_tuner()` configures the sort to use a bunch of algorithms that use less
     /// memory for large inputs than the standard tuning. These algorithms include multi-threaded
     /// algorithms for better performance. In some situations, this tuning will be faster than the
@@ -130,6 +208,109 @@ where
         self
     }
 
+    /// `with_memory_budget()` sets the maximum number of bytes the sort should allocate for
+    /// temporary buffers. This is passed to the tuner, and the built-in tuners will fall back to
+    /// in-place algorithms for any portion of the input whose temporary buffer would exceed it.
+    ///
+    /// This is a hint rather than a hard limit, as small bookkeeping allocations are not included.
+    ///
+    /// ```
+    /// use rdst::RadixSort;
+    /// let mut data: Vec<usize> = vec![5, 22, 3, 7, 9];
+    ///
+    /// data
+    ///     .radix_sort_builder()
+    ///     .with_memory_budget(64 * 1024 * 1024)
+    ///     .sort();
+    /// ```
+    pub fn with_memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = Some(bytes);
+
+        self
+    }
+
+    /// `indirect()` sorts wide items by sorting a compact array of their keys and original
+    /// positions, then moving each item into its sorted position once at the end. This is
+    /// usually much faster for structs that are large compared to their key, and slower for small
+    /// ones. Without this, the tuner decides whether to sort indirectly (see `Tuner::indirect`).
+    ///
+    /// Keys with more than 16 levels are always sorted directly, as are inputs whose compact array
+    /// wouldn't fit in the memory budget (see `with_memory_budget`).
+    ///
+    /// ```
+    /// use rdst::{RadixKey, RadixSort};
+    ///
+    /// #[derive(Clone, Copy)]
+    /// struct Record {
+    ///     key: u32,
+    ///     payload: [u64; 8],
+    /// }
+    ///
+    /// impl RadixKey for Record {
+    ///     const LEVELS: usize = 4;
+    ///
+    ///     #[inline]
+    ///     fn get_level(&self, level: usize) -> u8 {
+    ///         self.key.get_level(level)
+    ///     }
+    /// }
+    ///
+    /// let mut data: Vec<Record> = (0..1000)
+    ///     .map(|i| Record { key: 999 - i, payload: [i as u64; 8] })
+    ///     .collect();
+    ///
+    /// data
+    ///     .radix_sort_builder()
+    ///     .indirect()
+    ///     .sort();
+    ///
+    /// assert!(data.windows(2).all(|w| w[0].key <= w[1].key));
+    /// ```
+    pub fn indirect(mut self) -> Self {
+        self.indirect = true;
+
+        self
+    }
+
+    /// `with_key_hint()` tells the sort something that is already known about the keys, so that it
+    /// doesn't have to find it out again. Hints are passed to the tuner (see
+    /// `TuningParams::key_hints`), and let the sort skip some of its own checks. This can be
+    /// called more than once to give several hints.
+    ///
+    /// Hints are trusted rather than checked, so a wrong hint may leave the data unsorted.
+    ///
+    /// ```
+    /// use rdst::{KeyHint, RadixSort};
+    /// let mut data: Vec<u32> = (0..10_000).rev().collect();
+    ///
+    /// data
+    ///     .radix_sort_builder()
+    ///     .with_key_hint(KeyHint::Range { min: 0, max: 9_999 })
+    ///     .with_key_hint(KeyHint::Uniform)
+    ///     .sort();
+    ///
+    /// assert!(data.windows(2).all(|w| w[0] <= w[1]));
+    /// ```
+    pub fn with_key_hint(mut self, hint: KeyHint<T>) -> Self {
+        match hint {
+            KeyHint::Range { min, max } => {
+                let level = (0..T::LEVELS)
+                    .rev()
+                    .find(|&level| min.get_level(level) != max.get_level(level))
+                    .unwrap_or(0);
+
+                // Both ranges hold, so the narrower one can be used
+                let max_level = self.key_hints.max_level.map_or(level, |l| l.min(level));
+                self.key_hints.max_level = Some(max_level);
+            }
+            KeyHint::MostlySorted => self.key_hints.mostly_sorted = true,
+            KeyHint::ManyDuplicates => self.key_hints.many_duplicates = true,
+            KeyHint::Uniform => self.key_hints.uniform = true,
+        }
+
+        self
+    }
+
     /// `sort()` runs the configured sorting algorithm and consumes the RadixSortBuilder to return
     /// your mutable vec / slice back to you.
     ///
@@ -146,12 +327,105 @@ where
     /// data[0] = 123;
     /// ```
     pub fn sort(self) {
+        self.run(None);
+    }
+
+    /// `try_sort()` sorts in the same way as `sort()`, except that in strict single-threaded mode
+    /// (see `with_strict_single_threaded()`) it fails if the tuner picks a multi-threaded
+    /// algorithm, rather than using its single-threaded equivalent. Sorting stops as soon as that
+    /// happens, leaving the data in an unspecified order.
+    ///
+    /// ```
+    /// use rdst::RadixSort;
+    /// use rdst::tuner::{Algorithm, Tuner, TuningParams};
+    ///
+    /// struct MyTuner;
+    ///
+    /// impl Tuner for MyTuner {
+    ///     fn pick_algorithm(&self, _p: &TuningParams, _counts: &[usize]) -> Algorithm {
+    ///         Algorithm::Lsb
+    ///     }
+    /// }
+    ///
+    /// let mut data: Vec<usize> = (0..1000).rev().collect();
+    ///
+    /// data
+    ///     .radix_sort_builder()
+    ///     .with_strict_single_threaded()
+    ///     .with_tuner(&MyTuner)
+    ///     .try_sort()
+    ///     .expect("only single-threaded algorithms were picked");
+    ///
+    /// assert!(data.windows(2).all(|w| w[0] <= w[1]));
+    /// ```
+    pub fn try_sort(self) -> Result<(), SortError> {
+        let rejected = Mutex::new(None);
+        self.run(Some(&rejected));
+
+        match rejected.into_inner().unwrap() {
+            Some(algorithm) => Err(SortError { algorithm }),
+            None => Ok(()),
+        }
+    }
+
+    fn sorter<'r>(&self, rejected: Option<&'r Mutex<Option<Algorithm>>>) -> Sorter<'r>
+    where
+        'a: 'r,
+    {
+        if self.strict {
+            Sorter::new(false, self.tuner)
+                .with_memory_budget(self.memory_budget)
+                .with_key_hints(self.key_hints)
+                .with_strict(rejected)
+        } else {
+            Sorter::new(self.multi_threaded, self.tuner)
+                .with_memory_budget(self.memory_budget)
+                .with_key_hints(self.key_hints)
+        }
+    }
+
+    fn run(self, rejected: Option<&Mutex<Option<Algorithm>>>) {
         // By definition, this is already sorted
         if self.data.len() <= 1 {
             return;
         }
 
-        let sorter = Sorter::new(self.multi_threaded, self.tuner);
-        sorter.top_level_director(self.data);
+        let sorter = self.sorter(rejected);
+
+        if self.indirect
+            || self
+                .tuner
+                .indirect(&sorter.input_params::<T>(self.data.len()))
+        {
+            sorter.indirect_sort(self.data);
+        } else {
+            sorter.top_level_director(self.data);
+        }
+    }
+
+    /// `sort_with_regret_report()` sorts in the same way as `sort()`, but additionally times
+    /// every algorithm the tuner could have picked at each point where it picks one, and returns a
+    /// report of how the tuner's choices compare to the fastest choices. This is much slower than
+    /// `sort()` and is intended for evaluating tuners.
+    ///
+    /// ```
+    /// use rdst::RadixSort;
+    /// let mut data: Vec<u32> = (0..10_000).rev().collect();
+    ///
+    /// let report = data
+    ///     .radix_sort_builder()
+    ///     .sort_with_regret_report();
+    ///
+    /// println!("{}", report);
+    /// ```
+    pub fn sort_with_regret_report(self) -> RegretReport {
+        let recorder = RegretRecorder::default();
+
+        if self.data.len() > 1 {
+            let sorter = self.sorter(None).with_regret_recorder(&recorder);
+            sorter.top_level_director(self.data);
+        }
+
+        recorder.into_report()
     }
 }
@@ -1,4 +1,5 @@
-use crate::tuner::{Algorithm, Tuner, TuningParams};
+use crate::tuner::{Algorithm, Decision, KeyHints, Tuner, TuningParams};
+use crate::tuners::RegretRecorder;
 use crate::utils::*;
 use crate::RadixKey;
 use arbitrary_chunks::ArbitraryChunks;
@@ -7,10 +8,42 @@ use rayon::current_num_threads;
 #[cfg(feature = "multi-threaded")]
 use rayon::prelude::*;
 use std::cmp::max;
+#[cfg(feature = "multi-threaded")]
+use std::cmp::{min, Reverse};
+use std::mem::size_of;
+use std::sync::Mutex;
+
+/// `ChunkCounts` is everything `Sorter::count_chunk` learns about a chunk: the counts, information
+/// gathered while counting, the tile counts (if tiles were used), the information gathered for
+/// each tile and the tile size.
+type ChunkCounts = (
+    [usize; 256],
+    CountMeta,
+    Option<Vec<[usize; 256]>>,
+    Vec<CountMeta>,
+    usize,
+);
+
+/// `multi_threaded_director` batches small buckets together until each batch holds at least this
+/// many items (or fewer, for smaller inputs), rather than sorting each as a task of its own.
+#[cfg(feature = "multi-threaded")]
+const MAX_BATCH_LEN: usize = 30_000;
 
 pub struct Sorter<'a> {
-    multi_threaded: bool,
+    pub(crate) multi_threaded: bool,
     pub(crate) tuner: &'a (dyn Tuner + Send + Sync),
+    memory_budget: Option<usize>,
+    regret_recorder: Option<&'a RegretRecorder>,
+    /// The lowest level that needs sorting. Every key holds the same byte at the levels below it,
+    /// see `top_level_director`.
+    pub(crate) end_level: usize,
+    /// Whether multi-threaded algorithms must be avoided, see `permitted_algorithm`.
+    strict: bool,
+    /// Where the first multi-threaded algorithm picked in strict mode is recorded, when it should
+    /// be rejected rather than replaced.
+    rejected: Option<&'a Mutex<Option<Algorithm>>>,
+    /// What the caller said about the keys ahead of time, see `RadixSortBuilder::with_key_hint`.
+    key_hints: KeyHints,
 }
 
 impl<'a> Sorter<'a> {
@@ -18,10 +51,91 @@ impl<'a> Sorter<'a> {
         Self {
             multi_threaded,
             tuner,
+            memory_budget: None,
+            regret_recorder: None,
+            end_level: 0,
+            strict: false,
+            rejected: None,
+            key_hints: KeyHints::default(),
+        }
+    }
+
+    pub fn with_memory_budget(mut self, memory_budget: Option<usize>) -> Self {
+        self.memory_budget = memory_budget;
+
+        self
+    }
+
+    pub(crate) fn with_key_hints(mut self, key_hints: KeyHints) -> Self {
+        self.key_hints = key_hints;
+
+        self
+    }
+
+    pub(crate) fn with_regret_recorder(mut self, recorder: &'a RegretRecorder) -> Self {
+        self.regret_recorder = Some(recorder);
+
+        self
+    }
+
+    /// `with_strict()` stops any multi-threaded algorithms from being used, replacing them with
+    /// their single-threaded equivalents. If `rejected` is given, the first one picked is recorded
+    /// there instead and sorting stops early. This should be combined with `multi_threaded` being
+    /// false, so that nothing else is done in parallel either.
+    pub(crate) fn with_strict(mut self, rejected: Option<&'a Mutex<Option<Algorithm>>>) -> Self {
+        self.strict = true;
+        self.rejected = rejected;
+
+        self
+    }
+
+    pub(crate) fn without_regret_recorder(&self) -> Self {
+        Self {
+            multi_threaded: self.multi_threaded,
+            tuner: self.tuner,
+            memory_budget: self.memory_budget,
+            regret_recorder: None,
+            end_level: self.end_level,
+            strict: self.strict,
+            rejected: self.rejected,
+            key_hints: self.key_hints,
+        }
+    }
+
+    pub(crate) fn memory_budget(&self) -> Option<usize> {
+        self.memory_budget
+    }
+
+    /// `with_remaining_budget()` returns a copy of this sorter with a smaller memory budget, for
+    /// sorting while some of the budget is already in use.
+    pub(crate) fn with_remaining_budget(&self, memory_budget: Option<usize>) -> Self {
+        Self {
+            multi_threaded: self.multi_threaded,
+            tuner: self.tuner,
+            memory_budget,
+            regret_recorder: self.regret_recorder,
+            end_level: self.end_level,
+            strict: self.strict,
+            rejected: self.rejected,
+            key_hints: self.key_hints,
+        }
+    }
+
+    fn with_end_level(&self, end_level: usize) -> Self {
+        Self {
+            multi_threaded: self.multi_threaded,
+            tuner: self.tuner,
+            memory_budget: self.memory_budget,
+            regret_recorder: self.regret_recorder,
+            end_level,
+            strict: self.strict,
+            rejected: self.rejected,
+            key_hints: self.key_hints,
         }
     }
 
     #[inline]
+    #[allow(clippy::too_many_arguments)]
     fn run_sort<T>(
         &self,
         level: usize,
@@ -29,23 +143,73 @@ impl<'a> Sorter<'a> {
         counts: &[usize; 256],
         tile_counts: Option<Vec<[usize; 256]>>,
         #[allow(unused)] tile_size: usize,
-        algorithm: Algorithm,
+        decision: Decision,
+        write_combining: bool,
+        level_counts: Option<&[[usize; 256]]>,
     ) where
         T: RadixKey + Copy + Sized + Send + Sync,
     {
+        // Tuners may pick algorithms that weren't compiled in, so fall back to the closest one
+        let algorithm = if decision.algorithm.is_available() {
+            decision.algorithm
+        } else {
+            decision.algorithm.single_threaded_equivalent()
+        };
+        let count_while_sorting = decision.count_while_sorting.unwrap_or(true);
+        let wide = matches!(decision.digit_bits, Some(bits) if bits > 8);
+
+        // LSB sorts stop at `end_level`, so don't need the counts for the levels below it
+        let level_counts = level_counts.map(|c| &c[self.end_level..]);
+
         #[allow(unused)]
         if let Some(tile_counts) = tile_counts {
             match algorithm {
                 #[cfg(feature = "multi-threaded")]
-                Algorithm::Scanning => self.scanning_sort_adapter(bucket, counts, level),
+                Algorithm::Scanning => self.scanning_sort_adapter(
+                    bucket,
+                    counts,
+                    level,
+                    decision.threads,
+                    decision.buffer_capacity,
+                ),
                 #[cfg(feature = "multi-threaded")]
-                Algorithm::Recombinating => {
-                    self.recombinating_sort_adapter(bucket, counts, &tile_counts, tile_size, level)
+                Algorithm::Block => self.block_sort_adapter(bucket, counts, level),
+                #[cfg(feature = "multi-threaded")]
+                Algorithm::Recombinating => self.recombinating_sort_adapter(
+                    bucket,
+                    counts,
+                    &tile_counts,
+                    tile_size,
+                    level,
+                    write_combining,
+                ),
+                Algorithm::Lsb | Algorithm::LrLsb if wide => {
+                    self.wide_lsb_sort_adapter(bucket, counts, self.end_level, level)
                 }
-                Algorithm::LrLsb => self.lsb_sort_adapter(true, bucket, counts, 0, level),
-                Algorithm::Lsb => self.lsb_sort_adapter(false, bucket, counts, 0, level),
+                Algorithm::LrLsb => self.lsb_sort_adapter(
+                    true,
+                    write_combining,
+                    count_while_sorting,
+                    bucket,
+                    counts,
+                    level_counts,
+                    self.end_level,
+                    level,
+                ),
+                Algorithm::Lsb => self.lsb_sort_adapter(
+                    false,
+                    write_combining,
+                    count_while_sorting,
+                    bucket,
+                    counts,
+                    level_counts,
+                    self.end_level,
+                    level,
+                ),
                 Algorithm::Ska => self.ska_sort_adapter(bucket, counts, level),
+                Algorithm::AmericanFlag => self.american_flag_sort_adapter(bucket, counts, level),
                 Algorithm::Comparative => self.comparative_sort(bucket, level),
+                Algorithm::Merge => self.merge_sort_adapter(bucket, counts, level),
                 #[cfg(feature = "multi-threaded")]
                 Algorithm::Regions => {
                     self.regions_sort_adapter(bucket, counts, &tile_counts, tile_size, level)
@@ -55,38 +219,81 @@ impl<'a> Sorter<'a> {
                     self.mt_oop_sort_adapter(bucket, level, counts, &tile_counts, tile_size)
                 }
                 #[cfg(feature = "multi-threaded")]
-                Algorithm::MtLsb => self.mt_lsb_sort_adapter(bucket, 0, level, tile_size),
+                Algorithm::MtLsb => self.mt_lsb_sort_adapter(
+                    bucket,
+                    self.end_level,
+                    level,
+                    tile_size,
+                    write_combining,
+                    level_counts,
+                ),
+                #[cfg(feature = "multi-threaded")]
+                Algorithm::MtStableMsb => {
+                    self.mt_stable_msb_sort_adapter(bucket, level, counts, &tile_counts, tile_size)
+                }
+                #[cfg(not(feature = "multi-threaded"))]
+                e => unreachable!("Unavailable algorithm: {:?}", e),
             }
         } else {
             match algorithm {
                 #[cfg(feature = "multi-threaded")]
-                Algorithm::Scanning => self.scanning_sort_adapter(bucket, counts, level),
-                Algorithm::LrLsb => self.lsb_sort_adapter(true, bucket, counts, 0, level),
-                Algorithm::Lsb => self.lsb_sort_adapter(false, bucket, counts, 0, level),
+                Algorithm::Scanning => self.scanning_sort_adapter(
+                    bucket,
+                    counts,
+                    level,
+                    decision.threads,
+                    decision.buffer_capacity,
+                ),
+                #[cfg(feature = "multi-threaded")]
+                Algorithm::Block => self.block_sort_adapter(bucket, counts, level),
+                Algorithm::Lsb | Algorithm::LrLsb if wide => {
+                    self.wide_lsb_sort_adapter(bucket, counts, self.end_level, level)
+                }
+                Algorithm::LrLsb => self.lsb_sort_adapter(
+                    true,
+                    write_combining,
+                    count_while_sorting,
+                    bucket,
+                    counts,
+                    level_counts,
+                    self.end_level,
+                    level,
+                ),
+                Algorithm::Lsb => self.lsb_sort_adapter(
+                    false,
+                    write_combining,
+                    count_while_sorting,
+                    bucket,
+                    counts,
+                    level_counts,
+                    self.end_level,
+                    level,
+                ),
                 Algorithm::Ska => self.ska_sort_adapter(bucket, counts, level),
+                Algorithm::AmericanFlag => self.american_flag_sort_adapter(bucket, counts, level),
                 Algorithm::Comparative => self.comparative_sort(bucket, level),
-                #[cfg(feature = "multi-threaded")]
+                Algorithm::Merge => self.merge_sort_adapter(bucket, counts, level),
                 e => panic!("Bad algorithm: {:?} for len: {}", e, bucket.len()),
             }
         }
     }
 
-    fn handle_chunk<T>(
+    /// `count_chunk` counts `chunk` for `level`, using tiles where the chunk is large enough to
+    /// benefit from them. Returns the counts, information gathered while counting, the tile counts
+    /// (if tiles were used), the information gathered for each tile and the tile size. If
+    /// `differs` is given, it is set to which levels up to `level` differ anywhere in the chunk,
+    /// gathered in the same pass (see `get_counts_with_differing_levels`).
+    #[inline]
+    fn count_chunk<T>(
         &self,
-        chunk: &mut [T],
+        chunk: &[T],
         level: usize,
-        parent_len: Option<usize>,
         threads: usize,
-    ) where
+        differs: Option<&mut Vec<bool>>,
+    ) -> ChunkCounts
+    where
         T: RadixKey + Sized + Send + Copy + Sync,
     {
-        if chunk.len() <= 1 {
-            return;
-        } else if chunk.len() <= 128 {
-            self.comparative_sort(chunk, level);
-            return;
-        }
-
         let use_tiles =
             cfg!(feature = "multi-threaded") && self.multi_threaded && chunk.len() >= 260_000;
         let tile_size = if use_tiles {
@@ -94,33 +301,295 @@ impl<'a> Sorter<'a> {
         } else {
             chunk.len()
         };
-        let tp = TuningParams {
-            threads,
-            level,
-            total_levels: T::LEVELS,
-            input_len: chunk.len(),
-            parent_len,
-        };
 
-        let mut tile_counts: Option<Vec<[usize; 256]>> = None;
-        let mut already_sorted = false;
+        if let Some(differs) = differs {
+            let first = &chunk[0];
 
-        if use_tiles {
-            let (tc, s) = get_tile_counts(chunk, tile_size, level);
-            tile_counts = Some(tc);
-            already_sorted = s;
+            if use_tiles {
+                let (tile_counts, tile_metas, d) =
+                    get_tile_counts_with_differing_levels(chunk, tile_size, level, first);
+                let counts = aggregate_tile_counts(&tile_counts);
+                let meta = combine_count_meta(&tile_metas);
+                *differs = d;
+
+                return (counts, meta, Some(tile_counts), tile_metas, tile_size);
+            }
+
+            let (counts, meta, d) = get_counts_with_differing_levels(chunk, level, first);
+            *differs = d;
+
+            return (counts, meta, None, vec![meta], tile_size);
         }
 
-        let counts = if let Some(tile_counts) = &tile_counts {
-            aggregate_tile_counts(tile_counts)
+        if self.key_hints.skip_order_checks() {
+            let meta = CountMeta::unordered(chunk.len());
+
+            #[cfg(feature = "multi-threaded")]
+            if use_tiles {
+                let tile_counts = get_plain_tile_counts(chunk, tile_size, level);
+                let counts = aggregate_tile_counts(&tile_counts);
+                let tile_metas = chunk
+                    .chunks(tile_size)
+                    .map(|tile| CountMeta::unordered(tile.len()))
+                    .collect();
+
+                return (counts, meta, Some(tile_counts), tile_metas, tile_size);
+            }
+
+            return (
+                get_plain_counts(chunk, level),
+                meta,
+                None,
+                vec![meta],
+                tile_size,
+            );
+        }
+
+        if use_tiles {
+            let (tile_counts, tile_metas) = get_tile_counts_with_meta(chunk, tile_size, level);
+            let counts = aggregate_tile_counts(&tile_counts);
+            let meta = combine_count_meta(&tile_metas);
+
+            (counts, meta, Some(tile_counts), tile_metas, tile_size)
         } else {
-            let (counts, s) = get_counts(chunk, level);
-            already_sorted = s;
+            let (counts, meta) = get_counts_with_ends(chunk, level);
+
+            (counts, meta, None, vec![meta], tile_size)
+        }
+    }
+
+    /// `reverse_chunk` reverses `chunk` in place, using multiple threads for larger chunks. The
+    /// chunk must be in descending order for this level. Reversing it also reverses the order of
+    /// the items within each bucket, so when the tuner requires a stable sort, each bucket is
+    /// reversed again afterwards to restore their original order.
+    #[inline]
+    fn reverse_chunk<T>(&self, chunk: &mut [T], counts: &[usize; 256])
+    where
+        T: Send,
+    {
+        trace_scope!("reverse", len = chunk.len());
+
+        let stable = self.tuner.stable();
+
+        #[cfg(feature = "multi-threaded")]
+        if self.multi_threaded && chunk.len() >= 100_000 {
+            par_reverse(chunk);
+
+            if stable {
+                chunk
+                    .arbitrary_chunks_mut(counts)
+                    .par_bridge()
+                    .for_each(|bucket| bucket.reverse());
+            }
+
+            return;
+        }
+
+        chunk.reverse();
+
+        if stable {
+            chunk
+                .arbitrary_chunks_mut(counts)
+                .for_each(|bucket| bucket.reverse());
+        }
+    }
 
-            counts
+    /// `reverse_descending` reverses `chunk` if it is in descending order for this level, leaving
+    /// it sorted for this level. Otherwise, any tiles that are in descending order are reversed
+    /// individually, which leaves the counts for each tile unchanged. Returns the updated meta.
+    #[inline]
+    fn reverse_descending<T>(
+        &self,
+        chunk: &mut [T],
+        counts: &[usize; 256],
+        meta: CountMeta,
+        tile_counts: Option<&[[usize; 256]]>,
+        tile_metas: &mut [CountMeta],
+        tile_size: usize,
+    ) -> CountMeta
+    where
+        T: Send,
+    {
+        if meta.already_reversed() {
+            self.reverse_chunk(chunk, counts);
+
+            return meta.reversed();
+        }
+
+        let tile_counts = match tile_counts {
+            Some(tile_counts) if tile_metas.iter().any(|m| m.already_reversed()) => tile_counts,
+            _ => return meta,
         };
+        let stable = self.tuner.stable();
+        let reverse_tile = |tile: &mut [T], tile_counts: &[usize; 256]| {
+            tile.reverse();
+
+            if stable {
+                tile.arbitrary_chunks_mut(tile_counts)
+                    .for_each(|bucket| bucket.reverse());
+            }
+        };
+
+        #[cfg(feature = "multi-threaded")]
+        chunk
+            .par_chunks_mut(tile_size)
+            .zip(tile_counts.par_iter())
+            .zip(tile_metas.par_iter_mut())
+            .filter(|(_, m)| m.already_reversed())
+            .for_each(|((tile, tile_counts), m)| {
+                reverse_tile(tile, tile_counts);
+                *m = m.reversed();
+            });
+
+        #[cfg(not(feature = "multi-threaded"))]
+        chunk
+            .chunks_mut(tile_size)
+            .zip(tile_counts.iter())
+            .zip(tile_metas.iter_mut())
+            .filter(|(_, m)| m.already_reversed())
+            .for_each(|((tile, tile_counts), m)| {
+                reverse_tile(tile, tile_counts);
+                *m = m.reversed();
+            });
+
+        combine_count_meta(tile_metas)
+    }
+
+    /// `decided_tiles` returns the tile counts and tile size for the tiled algorithms, counting
+    /// the tiles again if the tuner asked for a different tile size, either directly or as a number
+    /// of tiles. Tiles of fewer than 256 items would cost more to count than they could save.
+    #[inline]
+    #[cfg(feature = "multi-threaded")]
+    fn decided_tiles<T>(
+        &self,
+        chunk: &[T],
+        counts: &[usize; 256],
+        level: usize,
+        tile_counts: Option<Vec<[usize; 256]>>,
+        tile_size: usize,
+        decision: &Decision,
+    ) -> (Option<Vec<[usize; 256]>>, usize)
+    where
+        T: RadixKey + Sized + Send + Copy + Sync,
+    {
+        match decision.algorithm {
+            Algorithm::MtOop
+            | Algorithm::MtLsb
+            | Algorithm::MtStableMsb
+            | Algorithm::Recombinating
+            | Algorithm::Regions => {}
+            _ => return (tile_counts, tile_size),
+        }
+
+        let requested = decision
+            .tile_size
+            .or_else(|| decision.threads.map(|t| cdiv(chunk.len(), max(1, t))))
+            .map(|s| s.clamp(min(256, chunk.len()), chunk.len()))
+            .filter(|&s| s != tile_size);
+
+        match (requested, tile_counts) {
+            (Some(requested), _) => (Some(get_tile_counts(chunk, requested, level).0), requested),
+            (None, Some(tile_counts)) => (Some(tile_counts), tile_size),
+            // Without tiles, the tile size is the whole chunk
+            (None, None) => (Some(vec![*counts]), tile_size),
+        }
+    }
+
+    #[inline]
+    #[allow(clippy::too_many_arguments)]
+    fn run_algorithm<T>(
+        &self,
+        level: usize,
+        chunk: &mut [T],
+        counts: &[usize; 256],
+        tile_counts: Option<Vec<[usize; 256]>>,
+        tile_size: usize,
+        decision: Decision,
+        write_combining: bool,
+        level_counts: Option<&[[usize; 256]]>,
+    ) where
+        T: RadixKey + Sized + Send + Copy + Sync,
+    {
+        #[cfg(feature = "multi-threaded")]
+        let (tile_counts, tile_size) =
+            self.decided_tiles(chunk, counts, level, tile_counts, tile_size, &decision);
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "sort",
+            level,
+            len = chunk.len(),
+            thread = ?std::thread::current().id(),
+            algorithm = %decision.algorithm
+        )
+        .entered();
+        trace_scope!(
+            decision.algorithm.to_string(),
+            level = level,
+            len = chunk.len()
+        );
+
+        self.run_sort(
+            level,
+            chunk,
+            counts,
+            tile_counts,
+            tile_size,
+            decision,
+            write_combining,
+            level_counts,
+        );
+    }
+
+    pub(crate) fn handle_chunk<T>(
+        &self,
+        chunk: &mut [T],
+        level: usize,
+        parent_len: Option<usize>,
+        threads: usize,
+    ) where
+        T: RadixKey + Sized + Send + Copy + Sync,
+    {
+        if chunk.len() <= 1 {
+            return;
+        } else if chunk.len() <= 128 {
+            if self.tuner.stable() {
+                self.stable_comparative_sort(chunk, level);
+            } else {
+                self.comparative_sort(chunk, level);
+            }
+
+            return;
+        }
+
+        let counted = self.count_chunk(chunk, level, threads, None);
+        self.sort_counted_chunk(chunk, level, parent_len, threads, counted);
+    }
+
+    /// `sort_counted_chunk` is the rest of `handle_chunk`, once `chunk` has been counted for
+    /// `level` by `count_chunk`.
+    #[inline]
+    fn sort_counted_chunk<T>(
+        &self,
+        chunk: &mut [T],
+        level: usize,
+        parent_len: Option<usize>,
+        threads: usize,
+        counted: ChunkCounts,
+    ) where
+        T: RadixKey + Sized + Send + Copy + Sync,
+    {
+        let (counts, meta, tile_counts, mut tile_metas, tile_size) = counted;
+        let meta = self.reverse_descending(
+            chunk,
+            &counts,
+            meta,
+            tile_counts.as_deref(),
+            &mut tile_metas,
+            tile_size,
+        );
 
-        if already_sorted || (chunk.len() >= 30_000 && is_homogenous_bucket(&counts)) {
+        if meta.already_sorted || (chunk.len() >= 30_000 && is_homogenous_bucket(&counts)) {
             if level != 0 {
                 self.director(chunk, &counts, level - 1);
             }
@@ -128,24 +597,180 @@ impl<'a> Sorter<'a> {
             return;
         }
 
-        let algorithm = self.tuner.pick_algorithm(&tp, &counts);
+        let mut tp =
+            self.tuning_params::<T>(chunk.len(), level, parent_len, threads, &counts, &meta);
+        self.count_all_levels(chunk, &counts, &mut tp);
 
-        // Ensure tile_counts is always set when it is required
-        if tile_counts.is_none() {
-            tile_counts = match algorithm {
-                #[cfg(feature = "multi-threaded")]
-                Algorithm::MtOop
-                | Algorithm::MtLsb
-                | Algorithm::Recombinating
-                | Algorithm::Regions => Some(vec![counts]),
-                _ => None,
-            };
+        let mut decision = self.tuner.decide(&tp, &counts);
+        let Some(algorithm) = self.permitted_algorithm(decision.algorithm) else {
+            return;
+        };
+        decision.algorithm = algorithm;
+        let write_combining = self.tuner.write_combining(&tp, algorithm);
+
+        if let Some(recorder) = self.regret_recorder {
+            self.record_regret(recorder, chunk, level, threads, decision);
+        }
+
+        self.run_algorithm(
+            level,
+            chunk,
+            &counts,
+            tile_counts,
+            tile_size,
+            decision,
+            write_combining,
+            (!tp.level_counts.is_empty()).then_some(&tp.level_counts),
+        );
+    }
+
+    /// `permitted_algorithm` returns the algorithm to run in place of the one the tuner picked. In
+    /// strict mode, multi-threaded algorithms are replaced with their single-threaded equivalent,
+    /// or rejected if a place to record that is set, in which case `None` is returned and the
+    /// chunk is left unsorted.
+    #[inline]
+    fn permitted_algorithm(&self, algorithm: Algorithm) -> Option<Algorithm> {
+        if self.is_permitted(algorithm) {
+            return Some(algorithm);
+        }
+
+        match self.rejected {
+            Some(rejected) => {
+                rejected.lock().unwrap().get_or_insert(algorithm);
+                None
+            }
+            None => Some(algorithm.single_threaded_equivalent()),
+        }
+    }
+
+    /// `is_permitted` returns whether `algorithm` can be run as-is, i.e. it isn't multi-threaded in
+    /// strict mode.
+    #[inline]
+    pub(crate) fn is_permitted(&self, algorithm: Algorithm) -> bool {
+        !self.strict || !algorithm.is_multi_threaded()
+    }
+
+    /// `count_all_levels` fills in `tp.level_counts` for `chunk` if the tuner asks for it. `counts`
+    /// are the counts for `tp.level`, so only the levels below it need to be read.
+    #[inline]
+    fn count_all_levels<T>(&self, chunk: &[T], counts: &[usize; 256], tp: &mut TuningParams)
+    where
+        T: RadixKey + Sized + Send + Sync,
+    {
+        if !self.tuner.count_all_levels(tp) {
+            return;
+        }
+
+        let mut level_counts = if tp.level == 0 {
+            Vec::with_capacity(1)
+        } else {
+            #[cfg(feature = "multi-threaded")]
+            if self.multi_threaded {
+                par_get_all_counts(chunk, 0, tp.level - 1)
+            } else {
+                get_all_counts(chunk, 0, tp.level - 1)
+            }
+
+            #[cfg(not(feature = "multi-threaded"))]
+            get_all_counts(chunk, 0, tp.level - 1)
+        };
+
+        level_counts.push(*counts);
+        tp.level_counts = level_counts;
+    }
+
+    /// `tuning_params` describes a chunk of `len` items at `level` for the tuner.
+    #[inline]
+    fn tuning_params<T>(
+        &self,
+        len: usize,
+        level: usize,
+        parent_len: Option<usize>,
+        threads: usize,
+        counts: &[usize; 256],
+        meta: &CountMeta,
+    ) -> TuningParams
+    where
+        T: RadixKey,
+    {
+        let (non_empty_buckets, max_bucket_share, entropy) = get_distribution_stats(counts, len);
+
+        TuningParams {
+            threads,
+            level,
+            total_levels: T::LEVELS,
+            input_len: len,
+            parent_len,
+            element_size: size_of::<T>(),
+            non_empty_buckets,
+            max_bucket_share,
+            entropy,
+            sortedness: meta.sortedness(len),
+            runs: meta.runs(),
+            reverse_sortedness: meta.reverse_sortedness(len),
+            parallel: self.multi_threaded,
+            memory_budget: self.memory_budget,
+            level_counts: Vec::new(),
+            key_hints: self.key_hints,
+        }
+    }
+
+    /// `input_params` describes a whole input of `len` items for the tuner before any of it has
+    /// been read, so only the fields that don't depend on the data are set.
+    pub(crate) fn input_params<T>(&self, len: usize) -> TuningParams
+    where
+        T: RadixKey,
+    {
+        #[cfg(feature = "multi-threaded")]
+        let threads = current_num_threads();
+
+        #[cfg(not(feature = "multi-threaded"))]
+        let threads = 1;
+
+        TuningParams {
+            threads,
+            total_levels: T::LEVELS,
+            input_len: len,
+            element_size: size_of::<T>(),
+            parallel: self.multi_threaded,
+            memory_budget: self.memory_budget,
+            key_hints: self.key_hints,
+            ..Default::default()
+        }
+    }
+
+    /// `sort_with_decision` sorts `chunk` from `level` downwards in the same way as
+    /// `handle_chunk`, except that `decision` is used for this level rather than asking the
+    /// tuner. Deeper levels are tuned as normal.
+    pub(crate) fn sort_with_decision<T>(
+        &self,
+        chunk: &mut [T],
+        level: usize,
+        threads: usize,
+        decision: Decision,
+    ) where
+        T: RadixKey + Sized + Send + Copy + Sync,
+    {
+        if chunk.len() <= 1 {
+            return;
         }
 
-        #[cfg(feature = "work_profiles")]
-        println!("({}) PAR: {:?}", level, algorithm);
+        let (counts, meta, tile_counts, _, tile_size) =
+            self.count_chunk(chunk, level, threads, None);
+        let mut tp = self.tuning_params::<T>(chunk.len(), level, None, threads, &counts, &meta);
+        self.count_all_levels(chunk, &counts, &mut tp);
+        let write_combining = self.tuner.write_combining(&tp, decision.algorithm);
 
-        self.run_sort(level, chunk, &counts, tile_counts, tile_size, algorithm);
+        self.run_algorithm(
+            level,
+            chunk,
+            &counts,
+            tile_counts,
+            tile_size,
+            decision,
+            write_combining,
+            (!tp.level_counts.is_empty()).then_some(&tp.level_counts),
+        );
     }
 
     #[inline]
@@ -159,11 +784,54 @@ impl<'a> Sorter<'a> {
         #[cfg(not(feature = "multi-threaded"))]
         let threads = 1;
 
-        let level = T::LEVELS - 1;
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "top_level_director",
+            len = bucket.len(),
+            thread = ?std::thread::current().id(),
+            threads
+        )
+        .entered();
+
+        // Levels above a hinted range are known to be constant, so there's no need to look at them
+        let level = match self.key_hints.max_level {
+            Some(max_level) => max_level.min(T::LEVELS - 1),
+            None => T::LEVELS - 1,
+        };
+
+        if bucket.len() <= 1 {
+            return;
+        } else if level == 0 || bucket.len() <= 128 {
+            // Too few levels or items for the level range to be worth finding
+            self.handle_chunk(bucket, level, None, threads);
+            return;
+        }
+
+        // Count the highest level, finding which levels differ at all in the same pass
+        let mut differs = Vec::new();
+        let counted = self.count_chunk(bucket, level, threads, Some(&mut differs));
 
-        self.handle_chunk(bucket, level, None, threads);
+        // Starting at the highest level that differs skips counting the constant leading levels
+        // one at a time, and stopping at the lowest saves LSB sorts from passes that wouldn't move
+        // anything
+        let Some((start_level, end_level)) = get_level_range(&differs) else {
+            // Every key is the same
+            return;
+        };
+        let sorter = self.with_end_level(end_level);
+
+        if start_level == level {
+            sorter.sort_counted_chunk(bucket, level, None, threads, counted);
+        } else {
+            sorter.handle_chunk(bucket, start_level, None, threads);
+        }
     }
 
+    /// `multi_threaded_director` sorts the buckets of `bucket` in parallel, largest first, so that
+    /// a large bucket isn't left until last while the other threads run out of work. Each larger
+    /// bucket is a task of its own and is told every thread is available, so it can be tiled and
+    /// sorted with a multi-threaded algorithm. Smaller buckets are sorted together in batches, one
+    /// after another on a single thread, to save the overhead of a task for each.
     #[inline]
     #[cfg(feature = "multi-threaded")]
     pub fn multi_threaded_director<T>(&self, bucket: &mut [T], counts: &[usize; 256], level: usize)
@@ -172,19 +840,71 @@ impl<'a> Sorter<'a> {
     {
         let parent_len = Some(bucket.len());
         let threads = current_num_threads();
+        // Enough batches to keep every thread busy, but no bigger than is worth splitting up
+        let batch_len = min(MAX_BATCH_LEN, cdiv(bucket.len(), threads * 8));
+
+        let mut chunks: Vec<&mut [T]> = bucket
+            .arbitrary_chunks_mut(counts)
+            .filter(|chunk| chunk.len() > 1)
+            .collect();
+        chunks.sort_unstable_by_key(|chunk| Reverse(chunk.len()));
+
+        // Each task is the total length of its buckets, and the buckets themselves
+        let mut tasks: Vec<(usize, Vec<&mut [T]>)> = Vec::new();
+
+        for chunk in chunks {
+            match tasks.last_mut() {
+                Some((total, batch)) if *total < batch_len => {
+                    *total += chunk.len();
+                    batch.push(chunk);
+                }
+                _ => tasks.push((chunk.len(), vec![chunk])),
+            }
+        }
+
+        tasks
+            .into_par_iter()
+            .with_max_len(1)
+            .for_each(|(_, batch)| {
+                let threads = if batch.len() == 1 { threads } else { 1 };
+
+                for chunk in batch {
+                    self.handle_chunk(chunk, level, parent_len, threads);
+                }
+            });
+    }
+
+    #[inline]
+    pub fn single_threaded_director<T>(&self, bucket: &mut [T], counts: &[usize; 256], level: usize)
+    where
+        T: RadixKey + Send + Sync + Copy,
+    {
+        let parent_len = Some(bucket.len());
+        let threads = 1;
 
         bucket
             .arbitrary_chunks_mut(counts)
-            .par_bridge()
             .for_each(|chunk| self.handle_chunk(chunk, level, parent_len, threads));
     }
 
+    /// `recording_director` handles each chunk one at a time, as `single_threaded_director` does,
+    /// so that decisions being timed don't compete with each other for threads. Unlike
+    /// `single_threaded_director`, the tuner is still told how many threads are available.
     #[inline]
-    pub fn single_threaded_director<T>(&self, bucket: &mut [T], counts: &[usize; 256], level: usize)
+    fn recording_director<T>(&self, bucket: &mut [T], counts: &[usize; 256], level: usize)
     where
         T: RadixKey + Send + Sync + Copy,
     {
         let parent_len = Some(bucket.len());
+
+        #[cfg(feature = "multi-threaded")]
+        let threads = if self.multi_threaded {
+            current_num_threads()
+        } else {
+            1
+        };
+
+        #[cfg(not(feature = "multi-threaded"))]
         let threads = 1;
 
         bucket
@@ -197,7 +917,31 @@ impl<'a> Sorter<'a> {
     where
         T: RadixKey + Send + Sync + Copy,
     {
-        if cfg!(feature = "multi-threaded") && self.multi_threaded {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "director",
+            level,
+            len = bucket.len(),
+            thread = ?std::thread::current().id(),
+            parallel = self.multi_threaded
+        )
+        .entered();
+        trace_scope!("director", level = level, len = bucket.len());
+
+        if level < self.end_level {
+            return;
+        }
+
+        if let Some(rejected) = self.rejected {
+            if rejected.lock().unwrap().is_some() {
+                // The sort has already failed, so there's no point continuing
+                return;
+            }
+        }
+
+        if self.regret_recorder.is_some() {
+            self.recording_director(bucket, counts, level);
+        } else if cfg!(feature = "multi-threaded") && self.multi_threaded {
             #[cfg(feature = "multi-threaded")]
             self.multi_threaded_director(bucket, counts, level);
         } else {
@@ -0,0 +1,151 @@
+//! `american_flag_sort` is a single-threaded, in-place algorithm described by McIlroy, Bostic and
+//! McIlroy.
+//!
+//! <https://www.usenix.org/legacy/publications/compsys/1993/win_mcilroy.pdf>
+//!
+//! Items are permuted into their buckets by following cycles. An item is picked up from the first
+//! unsorted position of a bucket, then repeatedly exchanged with the item at the next free
+//! position of its destination bucket until an item belonging to the starting bucket is found to
+//! fill the original position. Every misplaced item is written exactly once, directly into its
+//! final position.
+//!
+//! ## Characteristics
+//!
+//!  * in-place
+//!  * memory efficient
+//!  * unstable
+//!  * single-threaded
+//!
+//! ## Performance
+//!
+//! `ska_sort` uses a full swap to move each item, which writes it twice. This algorithm holds the
+//! item being moved aside instead, so it writes fewer bytes in total. That matters most for large
+//! types, where each move is expensive. For small types, `ska_sort` is generally faster as it
+//! skips over the largest bucket.
+
+use crate::sorter::Sorter;
+use crate::utils::*;
+use crate::RadixKey;
+
+pub fn american_flag_sort<T>(
+    bucket: &mut [T],
+    prefix_sums: &mut [usize; 256],
+    end_offsets: &[usize; 256],
+    level: usize,
+) where
+    T: RadixKey + Sized + Send + Copy + Sync,
+{
+    for b in 0..256 {
+        while prefix_sums[b] < end_offsets[b] {
+            let mut held = bucket[prefix_sums[b]];
+            let mut dest = held.get_level(level) as usize;
+
+            // Follow the cycle until an item belonging to bucket `b` is found
+            while dest != b {
+                std::mem::swap(&mut held, &mut bucket[prefix_sums[dest]]);
+                prefix_sums[dest] += 1;
+                dest = held.get_level(level) as usize;
+            }
+
+            bucket[prefix_sums[b]] = held;
+            prefix_sums[b] += 1;
+        }
+    }
+}
+
+impl<'a> Sorter<'a> {
+    pub(crate) fn american_flag_sort_adapter<T>(
+        &self,
+        bucket: &mut [T],
+        counts: &[usize; 256],
+        level: usize,
+    ) where
+        T: RadixKey + Sized + Send + Copy + Sync,
+    {
+        if bucket.len() < 2 {
+            return;
+        }
+
+        let mut prefix_sums = get_prefix_sums(counts);
+        let end_offsets = get_end_offsets(counts, &prefix_sums);
+
+        american_flag_sort(bucket, &mut prefix_sums, &end_offsets, level);
+
+        if level == 0 {
+            return;
+        }
+
+        self.director(bucket, counts, level - 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sorter::Sorter;
+    use crate::tuner::Algorithm;
+    use crate::tuners::StandardTuner;
+    use crate::utils::get_counts;
+    use crate::utils::test_utils::{
+        sort_comparison_suite, sort_single_algorithm, validate_u32_patterns, NumericTest,
+    };
+    use crate::RadixKey;
+
+    fn test_american_flag_sort_adapter<T>(shift: T)
+    where
+        T: NumericTest<T>,
+    {
+        let sorter = Sorter::new(true, &StandardTuner);
+
+        sort_comparison_suite(shift, |inputs| {
+            let (counts, _) = get_counts(inputs, T::LEVELS - 1);
+
+            sorter.american_flag_sort_adapter(inputs, &counts, T::LEVELS - 1);
+        });
+    }
+
+    #[test]
+    pub fn test_u8() {
+        test_american_flag_sort_adapter(0u8);
+    }
+
+    #[test]
+    pub fn test_u16() {
+        test_american_flag_sort_adapter(8u16);
+    }
+
+    #[test]
+    pub fn test_u32() {
+        test_american_flag_sort_adapter(16u32);
+    }
+
+    #[test]
+    pub fn test_u64() {
+        test_american_flag_sort_adapter(32u64);
+    }
+
+    #[test]
+    pub fn test_u128() {
+        test_american_flag_sort_adapter(64u128);
+    }
+
+    #[test]
+    pub fn test_usize() {
+        test_american_flag_sort_adapter(32usize);
+    }
+
+    #[test]
+    pub fn test_basic_integration() {
+        sort_single_algorithm::<u32>(1_000_000, Algorithm::AmericanFlag);
+    }
+
+    #[test]
+    pub fn test_u32_patterns() {
+        let sorter = Sorter::new(true, &StandardTuner);
+
+        validate_u32_patterns(|inputs| {
+            let (counts, _) = get_counts(inputs, u32::LEVELS - 1);
+
+            sorter.american_flag_sort_adapter(inputs, &counts, u32::LEVELS - 1);
+        });
+    }
+}
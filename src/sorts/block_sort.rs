@@ -0,0 +1,562 @@
+//! `block_sort`
+//!
+//! Based on:
+//! Michael Axtmann, Sascha Witt, Daniel Ferizovic, and Peter Sanders.
+//! Engineering In-place (Shared-memory) Sorting Algorithms.
+//! ACM Transactions on Parallel Computing, 2022. (IPS²Ra)
+//!
+//! Summary:
+//!
+//! ```text
+//! 1. Split into one stripe per thread
+//! 2. Local classification (for each stripe, in parallel):
+//!     2.1 Move each item into a small buffer block for its bucket
+//!     2.2 When a buffer block is full, write it back over the already-read start of the stripe
+//! 3. Block permutation:
+//!     3.1 Give each bucket the block-aligned slots that start within it
+//!     3.2 Move the full blocks within each bucket's slots to the front of them (in parallel)
+//!     3.3 Each thread repeatedly takes an unmoved block from a bucket, and swaps it into the next
+//!         slot of its destination bucket until it lands in an empty slot (in parallel)
+//! 4. Cleanup (for each bucket, in parallel):
+//!     4.1 Fill the gaps at either end of the bucket with the items left in the buffer blocks, and
+//!         with the part of the bucket's last block that overlaps the start of the next bucket
+//! ```
+//!
+//! ## Characteristics
+//!
+//!  * mostly in-place (a buffer block per bucket, per thread)
+//!  * multi-threaded
+//!  * unstable
+//!
+//! ## Performance
+//!
+//! Classification reads and writes each item once, in sequential order within each stripe, and
+//! the permutation then moves whole blocks rather than individual items. This gives throughput
+//! much closer to the out-of-place algorithms than `regions_sort`, while only needing a few
+//! hundred KB of buffers per thread regardless of the input size. It is intended for very large
+//! inputs where a full-size temporary buffer isn't affordable.
+//!
+//! ## Notes
+//!
+//! As in IPS²Ra, each bucket has a read pointer and a write pointer for the block permutation,
+//! which threads advance atomically to claim a slot. A thread only waits when writing to an empty
+//! slot that another thread may still be reading from.
+
+use crate::sorter::Sorter;
+use crate::utils::*;
+use crate::RadixKey;
+use arbitrary_chunks::ArbitraryChunks;
+use rayon::current_num_threads;
+use rayon::prelude::*;
+use std::cmp::{max, min};
+use std::hint;
+use std::mem::size_of;
+use std::ptr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Target size of each block in bytes.
+const BLOCK_BYTES: usize = 2_048;
+
+/// Stripe holds what is left of a stripe after local classification.
+struct Stripe<T> {
+    /// Number of full blocks written back to the start of the stripe
+    blocks: usize,
+    /// Number of full blocks written back for each bucket
+    block_counts: [usize; 256],
+    /// Items left over in each bucket's buffer block
+    buffers: Vec<Vec<T>>,
+}
+
+/// classify_stripe moves every item of `stripe` into its bucket's buffer block, writing each full
+/// buffer block back to the start of the stripe. As at least a full block of items must have been
+/// read before a block can be written, only items that have already been read are overwritten.
+fn classify_stripe<T>(stripe: &mut [T], block: usize, level: usize) -> Stripe<T>
+where
+    T: RadixKey + Copy,
+{
+    trace_scope!("block_classify", level = level, len = stripe.len());
+
+    let mut buffers: Vec<Vec<T>> = (0..256).map(|_| Vec::with_capacity(block)).collect();
+    let mut block_counts = [0usize; 256];
+    let mut write = 0;
+
+    for read in 0..stripe.len() {
+        let item = stripe[read];
+        let b = item.get_level(level) as usize;
+        let buffer = &mut buffers[b];
+        buffer.push(item);
+
+        if buffer.len() == block {
+            stripe[write..write + block].copy_from_slice(buffer);
+            buffer.clear();
+            block_counts[b] += 1;
+            write += block;
+        }
+    }
+
+    Stripe {
+        blocks: write / block,
+        block_counts,
+        buffers,
+    }
+}
+
+/// Slots shares the bucket being sorted between the threads permuting its blocks, as a sequence
+/// of block-sized slots. The last slot may be cut short by the end of the bucket. A slot is only
+/// ever accessed by the thread which claimed it from `BucketPointers`.
+struct Slots<T> {
+    ptr: *mut T,
+    len: usize,
+    block: usize,
+}
+
+// Safety: Threads only access slots they have claimed, so no two threads access the same items
+// at the same time.
+unsafe impl<T: Send> Send for Slots<T> {}
+unsafe impl<T: Send> Sync for Slots<T> {}
+
+impl<T: Copy> Slots<T> {
+    /// read copies the full block in `slot` into `held`.
+    ///
+    /// Safety: The caller must have claimed `slot`, and it must hold a full block.
+    #[inline]
+    unsafe fn read(&self, slot: usize, held: &mut [T]) {
+        ptr::copy_nonoverlapping(
+            self.ptr.add(slot * self.block),
+            held.as_mut_ptr(),
+            self.block,
+        );
+    }
+
+    /// swap exchanges the full block in `slot` with `held`.
+    ///
+    /// Safety: The caller must have claimed `slot`, and it must hold a full block.
+    #[inline]
+    unsafe fn swap(&self, slot: usize, held: &mut [T]) {
+        ptr::swap_nonoverlapping(
+            self.ptr.add(slot * self.block),
+            held.as_mut_ptr(),
+            self.block,
+        );
+    }
+
+    /// write copies as much of `held` into `slot` as fits before the end of the bucket, and
+    /// returns the rest.
+    ///
+    /// Safety: The caller must have claimed `slot`.
+    #[inline]
+    unsafe fn write<'b>(&self, slot: usize, held: &'b [T]) -> &'b [T] {
+        let start = slot * self.block;
+        let fits = min(self.block, self.len - start);
+        ptr::copy_nonoverlapping(held.as_ptr(), self.ptr.add(start), fits);
+
+        &held[fits..]
+    }
+}
+
+/// BucketPointers tracks which of a bucket's slots have been written and read during the block
+/// permutation. Slots before the write pointer hold this bucket's blocks, slots from there up to
+/// the read pointer hold blocks that haven't been moved yet, and any after that are empty. Both
+/// pointers are packed into one atomic, with the write pointer in the upper 32 bits, so that a
+/// slot can be claimed for reading or writing in a single step.
+struct BucketPointers {
+    pointers: AtomicU64,
+    /// Number of threads that are reading, or trying to read, a block from this bucket
+    reading: AtomicUsize,
+}
+
+impl BucketPointers {
+    fn new(write: usize, read: usize) -> Self {
+        Self {
+            pointers: AtomicU64::new(((write as u64) << 32) | read as u64),
+            reading: AtomicUsize::new(0),
+        }
+    }
+
+    /// claim_read claims the last slot holding a block that hasn't been moved yet, or returns
+    /// `None` if there are none left. `finish_read` must be called once the block has been read.
+    #[inline]
+    fn claim_read(&self) -> Option<usize> {
+        self.reading.fetch_add(1, Ordering::SeqCst);
+        let mut current = self.pointers.load(Ordering::SeqCst);
+
+        loop {
+            let (write, read) = (current >> 32, current & u32::MAX as u64);
+
+            if read <= write {
+                self.reading.fetch_sub(1, Ordering::SeqCst);
+                return None;
+            }
+
+            match self.pointers.compare_exchange_weak(
+                current,
+                current - 1,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => return Some(read as usize - 1),
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    #[inline]
+    fn finish_read(&self) {
+        self.reading.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// claim_write claims the next slot to write to, and returns it along with whether it still
+    /// holds a block that hasn't been moved yet. If it doesn't, it may still be being read by
+    /// another thread, so `wait_for_reads` must be called before writing to it.
+    #[inline]
+    fn claim_write(&self) -> (usize, bool) {
+        let previous = self.pointers.fetch_add(1 << 32, Ordering::SeqCst);
+        let (write, read) = (previous >> 32, previous & u32::MAX as u64);
+
+        (write as usize, write < read)
+    }
+
+    #[inline]
+    fn wait_for_reads(&self) {
+        while self.reading.load(Ordering::SeqCst) != 0 {
+            hint::spin_loop();
+        }
+    }
+}
+
+/// compact_blocks moves the full blocks among a bucket's slots to the front of them, so that the
+/// bucket's unmoved blocks are contiguous for the block permutation. Returns the number of full
+/// blocks.
+fn compact_blocks<T>(slots: &mut [T], occupied: &[bool], block: usize) -> usize
+where
+    T: Copy,
+{
+    let mut front = 0;
+    let mut back = occupied.len();
+
+    loop {
+        while front < back && occupied[front] {
+            front += 1;
+        }
+
+        while back > front && !occupied[back - 1] {
+            back -= 1;
+        }
+
+        if front == back {
+            return front;
+        }
+
+        // The slot at `front` is empty and the one at `back - 1` is full, so they're different
+        back -= 1;
+        slots.copy_within(back * block..(back + 1) * block, front * block);
+        front += 1;
+    }
+}
+
+/// permute_blocks repeatedly reads a block that hasn't been moved yet from a bucket, starting with
+/// `first_bucket`, and moves it into the next slot of its destination bucket. If that slot holds a
+/// block that hasn't been moved yet, the two are swapped and the new block is moved in the same
+/// way, until a block is written to an empty slot. The part of a block that doesn't fit before the
+/// end of the bucket is written to `overflow`.
+fn permute_blocks<T>(
+    slots: &Slots<T>,
+    pointers: &[BucketPointers],
+    first_bucket: usize,
+    level: usize,
+    overflow: &Mutex<Vec<T>>,
+) where
+    T: RadixKey + Copy,
+{
+    trace_scope!("block_permute", level = level, first_bucket = first_bucket);
+
+    let mut held: Vec<T> = get_tmp_bucket(slots.block);
+
+    for b in (first_bucket..256).chain(0..first_bucket) {
+        while let Some(slot) = pointers[b].claim_read() {
+            // Safety: The slot was claimed for reading, and holds a full block as it was before
+            // the bucket's read pointer.
+            unsafe { slots.read(slot, &mut held) };
+            pointers[b].finish_read();
+
+            loop {
+                let dst = &pointers[held[0].get_level(level) as usize];
+                let (slot, full) = dst.claim_write();
+
+                if full {
+                    // Safety: The slot was claimed for writing, and holds a full block that
+                    // hasn't been read.
+                    unsafe { slots.swap(slot, &mut held) };
+                    continue;
+                }
+
+                dst.wait_for_reads();
+
+                // Safety: The slot was claimed for writing, and any read from it has finished.
+                let rest = unsafe { slots.write(slot, &held) };
+
+                if !rest.is_empty() {
+                    overflow.lock().unwrap().extend_from_slice(rest);
+                }
+
+                break;
+            }
+        }
+    }
+}
+
+pub fn block_sort<T>(bucket: &mut [T], counts: &[usize; 256], level: usize)
+where
+    T: RadixKey + Sized + Send + Copy + Sync,
+{
+    // Blocks are made larger for enormous inputs, so that slots can be indexed with 32 bits
+    let block = max(
+        max(1, BLOCK_BYTES / max(1, size_of::<T>())),
+        cdiv(bucket.len(), u32::MAX as usize),
+    );
+    let threads = current_num_threads();
+    let stripe_len = cdiv(cdiv(bucket.len(), threads), block) * block;
+
+    let stripes: Vec<Stripe<T>> = bucket
+        .par_chunks_mut(stripe_len)
+        .map(|stripe| classify_stripe(stripe, block, level))
+        .collect();
+
+    let starts = get_prefix_sums(counts);
+    let ends = get_end_offsets(counts, &starts);
+    let num_slots = cdiv(bucket.len(), block);
+    let mut first_slots = [0usize; 256];
+    let mut block_counts = [0usize; 256];
+    let mut occupied = vec![false; num_slots];
+
+    for (first, start) in first_slots.iter_mut().zip(starts.iter()) {
+        *first = cdiv(*start, block);
+    }
+
+    for (i, stripe) in stripes.iter().enumerate() {
+        let first = i * stripe_len / block;
+        occupied[first..first + stripe.blocks].fill(true);
+
+        for (total, c) in block_counts.iter_mut().zip(stripe.block_counts.iter()) {
+            *total += c;
+        }
+    }
+
+    // Each bucket owns the slots that start within it, which is enough for all of its full
+    // blocks as they only hold items from this bucket. Every slot is owned by exactly one bucket.
+    let slot_counts: Vec<usize> = (0..256)
+        .map(|b| first_slots.get(b + 1).copied().unwrap_or(num_slots) - first_slots[b])
+        .collect();
+    let slot_lens: Vec<usize> = (0..256)
+        .map(|b| {
+            min((first_slots[b] + slot_counts[b]) * block, bucket.len()) - first_slots[b] * block
+        })
+        .collect();
+
+    let mut full_blocks = [0usize; 256];
+
+    {
+        trace_scope!("block_compact", level = level);
+
+        let compacted: Vec<(usize, usize)> = bucket
+            .arbitrary_chunks_mut(&slot_lens)
+            .enumerate()
+            .par_bridge()
+            .map(|(b, slots)| {
+                let occupied = &occupied[first_slots[b]..first_slots[b] + slot_counts[b]];
+                (b, compact_blocks(slots, occupied, block))
+            })
+            .collect();
+
+        for (b, full) in compacted {
+            full_blocks[b] = full;
+        }
+    }
+
+    let pointers: Vec<BucketPointers> = (0..256)
+        .map(|b| BucketPointers::new(first_slots[b], first_slots[b] + full_blocks[b]))
+        .collect();
+    let slots = Slots {
+        ptr: bucket.as_mut_ptr(),
+        len: bucket.len(),
+        block,
+    };
+    let overflow = Mutex::new(Vec::new());
+
+    // Each thread starts with a different bucket, so that they don't all compete for the same
+    // pointers
+    (0..threads).into_par_iter().for_each(|t| {
+        permute_blocks(&slots, &pointers, t * 256 / threads, level, &overflow);
+    });
+
+    let overflow = overflow.into_inner().unwrap();
+
+    // The last block of each bucket may overlap the start of the next bucket (or the end of the
+    // slice, in which case the rest is in `overflow`). Copy those items out before any of the
+    // buckets are filled.
+    let overlaps: Vec<Vec<T>> = (0..256)
+        .map(|b| {
+            let blocks_end = (first_slots[b] + block_counts[b]) * block;
+
+            if block_counts[b] == 0 || blocks_end <= ends[b] {
+                return Vec::new();
+            }
+
+            let in_bucket = min(blocks_end, bucket.len());
+            let mut items = bucket[ends[b]..in_bucket].to_vec();
+            items.extend_from_slice(&overflow[..blocks_end - in_bucket]);
+
+            items
+        })
+        .collect();
+
+    trace_scope!("block_cleanup", level = level);
+
+    bucket
+        .arbitrary_chunks_mut(counts)
+        .enumerate()
+        .par_bridge()
+        .for_each(|(b, chunk)| {
+            let start = starts[b];
+            let (head, tail) = if block_counts[b] == 0 {
+                (chunk.len(), chunk.len())
+            } else {
+                let blocks_start = first_slots[b] * block - start;
+                (
+                    blocks_start,
+                    min(blocks_start + block_counts[b] * block, chunk.len()),
+                )
+            };
+
+            let gaps = (0..head).chain(tail..chunk.len());
+            let items = overlaps[b]
+                .iter()
+                .chain(stripes.iter().flat_map(|s| s.buffers[b].iter()));
+
+            for (i, item) in gaps.zip(items) {
+                chunk[i] = *item;
+            }
+        });
+}
+
+impl<'a> Sorter<'a> {
+    pub(crate) fn block_sort_adapter<T>(
+        &self,
+        bucket: &mut [T],
+        counts: &[usize; 256],
+        level: usize,
+    ) where
+        T: RadixKey + Sized + Send + Copy + Sync,
+    {
+        if bucket.len() < 2 {
+            return;
+        }
+
+        block_sort(bucket, counts, level);
+
+        if level == 0 {
+            return;
+        }
+
+        self.director(bucket, counts, level - 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sorter::Sorter;
+    use crate::tuner::Algorithm;
+    use crate::tuners::StandardTuner;
+    use crate::utils::get_counts;
+    use crate::utils::test_utils::{
+        sort_comparison_suite, sort_single_algorithm, validate_u32_patterns, NumericTest,
+    };
+    use crate::RadixKey;
+
+    fn test_block_sort_adapter<T>(shift: T)
+    where
+        T: NumericTest<T>,
+    {
+        let sorter = Sorter::new(true, &StandardTuner);
+
+        sort_comparison_suite(shift, |inputs| {
+            let (counts, _) = get_counts(inputs, T::LEVELS - 1);
+
+            sorter.block_sort_adapter(inputs, &counts, T::LEVELS - 1);
+        });
+    }
+
+    #[test]
+    pub fn test_u8() {
+        test_block_sort_adapter(0u8);
+    }
+
+    #[test]
+    pub fn test_u16() {
+        test_block_sort_adapter(8u16);
+    }
+
+    #[test]
+    pub fn test_u32() {
+        test_block_sort_adapter(16u32);
+    }
+
+    #[test]
+    pub fn test_u64() {
+        test_block_sort_adapter(32u64);
+    }
+
+    #[test]
+    pub fn test_u128() {
+        test_block_sort_adapter(64u128);
+    }
+
+    #[test]
+    pub fn test_usize() {
+        test_block_sort_adapter(32usize);
+    }
+
+    #[test]
+    pub fn test_basic_integration() {
+        sort_single_algorithm::<u32>(1_000_000, Algorithm::Block);
+    }
+
+    #[test]
+    pub fn test_u32_patterns() {
+        let sorter = Sorter::new(true, &StandardTuner);
+
+        validate_u32_patterns(|inputs| {
+            let (counts, _) = get_counts(inputs, u32::LEVELS - 1);
+
+            sorter.block_sort_adapter(inputs, &counts, u32::LEVELS - 1);
+        });
+    }
+
+    #[test]
+    pub fn test_multiple_stripes() {
+        let sorter = Sorter::new(true, &StandardTuner);
+
+        // Many threads permuting blocks at once
+        for threads in [5, 16] {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+
+            pool.install(|| {
+                sort_comparison_suite(32u64, |inputs| {
+                    let (counts, _) = get_counts(inputs, u64::LEVELS - 1);
+
+                    sorter.block_sort_adapter(inputs, &counts, u64::LEVELS - 1);
+                });
+
+                validate_u32_patterns(|inputs| {
+                    let (counts, _) = get_counts(inputs, u32::LEVELS - 1);
+
+                    sorter.block_sort_adapter(inputs, &counts, u32::LEVELS - 1);
+                });
+            });
+        }
+    }
+}
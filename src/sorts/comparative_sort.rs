@@ -12,7 +12,7 @@
 //! ## Characteristics
 //!
 //!  * in-place
-//!  * unstable
+//!  * unstable (see `stable_comparative_sort` for a stable version)
 //!  * single-threaded
 //!
 //! ## Performance
@@ -25,6 +25,25 @@ use crate::sorter::Sorter;
 use crate::RadixKey;
 use std::cmp::Ordering;
 
+/// `cmp_levels` compares two keys level by level, from `start_level` down to level 0.
+#[inline]
+pub(crate) fn cmp_levels<T>(a: &T, b: &T, start_level: usize) -> Ordering
+where
+    T: RadixKey,
+{
+    let mut level = start_level;
+    loop {
+        let cmp = a.get_level(level).cmp(&b.get_level(level));
+
+        if level != 0 && cmp == Ordering::Equal {
+            level -= 1;
+            continue;
+        }
+
+        return cmp;
+    }
+}
+
 impl<'a> Sorter<'a> {
     pub(crate) fn comparative_sort<T>(&self, bucket: &mut [T], start_level: usize)
     where
@@ -34,19 +53,20 @@ impl<'a> Sorter<'a> {
             return;
         }
 
-        bucket.sort_unstable_by(|a, b| -> Ordering {
-            let mut level = start_level;
-            loop {
-                let cmp = a.get_level(level).cmp(&b.get_level(level));
+        bucket.sort_unstable_by(|a, b| cmp_levels(a, b, start_level));
+    }
 
-                if level != 0 && cmp == Ordering::Equal {
-                    level -= 1;
-                    continue;
-                }
+    /// `stable_comparative_sort` is the same as `comparative_sort`, except that items with equal
+    /// keys keep their original relative order. It is a little slower.
+    pub(crate) fn stable_comparative_sort<T>(&self, bucket: &mut [T], start_level: usize)
+    where
+        T: RadixKey + Sized + Send + Copy + Sync,
+    {
+        if bucket.len() < 2 {
+            return;
+        }
 
-                return cmp;
-            }
-        });
+        bucket.sort_by(|a, b| cmp_levels(a, b, start_level));
     }
 }
 
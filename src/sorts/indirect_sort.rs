@@ -0,0 +1,340 @@
+//! `indirect_sort` sorts wide items by their keys alone. The key bytes of each item are copied
+//! into a compact array alongside the item's original index, that array is sorted with the
+//! regular tuned algorithms, and finally the items are moved into their sorted positions by
+//! following the cycles of the resulting permutation.
+//!
+//! ## Characteristics
+//!
+//!  * out-of-place for the keys, in-place for the items
+//!  * stable if the tuner is (see `Tuner::stable`)
+//!  * multi-threaded (when allowed)
+//!
+//! ## Performance
+//!
+//! Every pass of a radix sort reads and writes each item. For items much wider than their keys,
+//! such as a struct with a `u32` sort key and 40 bytes of payload, most of that memory traffic is
+//! payload. Sorting 8 to 20 byte key / index pairs instead, and moving each item only once at the
+//! end, can be much faster. For items not much wider than the key / index pair, this just adds
+//! work.
+//!
+//! Keys with more than 16 levels can't be packed into the compact array, so are always sorted
+//! directly, as are inputs whose compact array wouldn't fit in the memory budget.
+
+use crate::permutation::permute_in_place;
+use crate::sorter::Sorter;
+use crate::RadixKey;
+#[cfg(feature = "multi-threaded")]
+use rayon::prelude::*;
+
+/// `KeyIndex` is the compact form of an item, holding its first `L` key levels packed into `K`
+/// and the item's original position. It is packed so that, for example, a `u64` key with a `u32`
+/// index takes 12 bytes rather than 16.
+#[derive(Clone, Copy)]
+#[repr(C, packed(4))]
+struct KeyIndex<K, I, const L: usize> {
+    key: K,
+    index: I,
+}
+
+impl<K, I, const L: usize> RadixKey for KeyIndex<K, I, L>
+where
+    K: RadixKey + Copy,
+{
+    const LEVELS: usize = L;
+
+    #[inline]
+    fn get_level(&self, level: usize) -> u8 {
+        let key = self.key;
+        key.get_level(level)
+    }
+}
+
+/// `PackedKey` is an unsigned integer that the levels of a key can be packed into.
+trait PackedKey: RadixKey + Copy + Send + Sync {
+    fn pack<T: RadixKey>(item: &T) -> Self;
+}
+
+macro_rules! impl_packed_key {
+    ($t:ty) => {
+        impl PackedKey for $t {
+            #[inline]
+            fn pack<T: RadixKey>(item: &T) -> Self {
+                let mut key = 0;
+
+                for level in 0..T::LEVELS {
+                    key |= (item.get_level(level) as $t) << (level * 8);
+                }
+
+                key
+            }
+        }
+    };
+}
+
+impl_packed_key!(u32);
+impl_packed_key!(u64);
+impl_packed_key!(u128);
+
+/// `PackedIndex` is an unsigned integer that the original position of an item is stored as.
+trait PackedIndex: Copy + Send + Sync {
+    fn from_usize(i: usize) -> Self;
+    fn to_usize(self) -> usize;
+}
+
+impl PackedIndex for u32 {
+    #[inline]
+    fn from_usize(i: usize) -> Self {
+        i as u32
+    }
+
+    #[inline]
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+}
+
+impl PackedIndex for usize {
+    #[inline]
+    fn from_usize(i: usize) -> Self {
+        i
+    }
+
+    #[inline]
+    fn to_usize(self) -> usize {
+        self
+    }
+}
+
+/// `can_sort_indirect` returns whether items of type `T` can be sorted by `indirect_sort`.
+#[inline]
+fn can_sort_indirect<T: RadixKey>() -> bool {
+    T::LEVELS <= 16
+}
+
+impl<'a> Sorter<'a> {
+    /// `indirect_sort` sorts `bucket` via a compact array of keys and indexes. Falls back to a
+    /// regular sort for keys that don't fit, see `can_sort_indirect`.
+    pub(crate) fn indirect_sort<T>(&self, bucket: &mut [T])
+    where
+        T: RadixKey + Sized + Send + Copy + Sync,
+    {
+        if bucket.len() <= 1 {
+            return;
+        } else if !can_sort_indirect::<T>() {
+            self.top_level_director(bucket);
+            return;
+        }
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("indirect_sort", len = bucket.len()).entered();
+        trace_scope!("indirect_sort", len = bucket.len());
+
+        let small_index = bucket.len() <= u32::MAX as usize;
+
+        // The number of levels is a const generic so that no more levels are sorted than the key
+        // actually has, even though the packed integer may be wider.
+        macro_rules! dispatch {
+            ($($levels:literal => $key:ty),* $(,)?) => {
+                match (T::LEVELS, small_index) {
+                    $(
+                        ($levels, true) => self.sort_key_indexes::<T, $key, u32, $levels>(bucket),
+                        ($levels, false) => self.sort_key_indexes::<T, $key, usize, $levels>(bucket),
+                    )*
+                    _ => unreachable!(),
+                }
+            };
+        }
+
+        dispatch!(
+            1 => u32, 2 => u32, 3 => u32, 4 => u32,
+            5 => u64, 6 => u64, 7 => u64, 8 => u64,
+            9 => u128, 10 => u128, 11 => u128, 12 => u128,
+            13 => u128, 14 => u128, 15 => u128, 16 => u128,
+        );
+    }
+
+    fn sort_key_indexes<T, K, I, const L: usize>(&self, bucket: &mut [T])
+    where
+        T: RadixKey + Sized + Send + Copy + Sync,
+        K: PackedKey,
+        I: PackedIndex,
+    {
+        // Sort directly if the compact array doesn't fit in the budget, otherwise the keys can only
+        // use what is left of it
+        let key_bytes = bucket.len() * size_of::<KeyIndex<K, I, L>>();
+        let remaining_budget = match self.memory_budget() {
+            Some(budget) if budget < key_bytes => {
+                self.top_level_director(bucket);
+                return;
+            }
+            Some(budget) => Some(budget - key_bytes),
+            None => None,
+        };
+        let sorter = self.with_remaining_budget(remaining_budget);
+
+        let pack = |(i, item): (usize, &T)| KeyIndex::<K, I, L> {
+            key: K::pack(item),
+            index: I::from_usize(i),
+        };
+
+        #[cfg(feature = "multi-threaded")]
+        let mut keys: Vec<KeyIndex<K, I, L>> = if self.multi_threaded {
+            bucket.par_iter().enumerate().map(pack).collect()
+        } else {
+            bucket.iter().enumerate().map(pack).collect()
+        };
+
+        #[cfg(not(feature = "multi-threaded"))]
+        let mut keys: Vec<KeyIndex<K, I, L>> = bucket.iter().enumerate().map(pack).collect();
+
+        sorter.top_level_director(&mut keys);
+
+        // Follow the cycles of the permutation to move each item into place, without a second
+        // copy of the items
+        permute_in_place(bucket, |i| {
+            let index = keys[i].index;
+            index.to_usize()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sorter::Sorter;
+    use crate::tuner::{Algorithm, Tuner, TuningParams};
+    use crate::tuners::StandardTuner;
+    use crate::utils::test_utils::{sort_comparison_suite, validate_u32_patterns, NumericTest};
+    use crate::{RadixKey, RadixSort};
+    use block_pseudorand::block_rand;
+
+    fn test_indirect_sort<T>(shift: T)
+    where
+        T: NumericTest<T>,
+    {
+        for multi_threaded in [false, true] {
+            let sorter = Sorter::new(multi_threaded, &StandardTuner);
+
+            sort_comparison_suite(shift, |inputs| sorter.indirect_sort(inputs));
+        }
+    }
+
+    #[test]
+    pub fn test_u8() {
+        test_indirect_sort(0u8);
+    }
+
+    #[test]
+    pub fn test_u16() {
+        test_indirect_sort(8u16);
+    }
+
+    #[test]
+    pub fn test_u32() {
+        test_indirect_sort(16u32);
+    }
+
+    #[test]
+    pub fn test_u64() {
+        test_indirect_sort(32u64);
+    }
+
+    #[test]
+    pub fn test_u128() {
+        test_indirect_sort(64u128);
+    }
+
+    #[test]
+    pub fn test_usize() {
+        test_indirect_sort(32usize);
+    }
+
+    #[test]
+    pub fn test_u32_patterns() {
+        let sorter = Sorter::new(true, &StandardTuner);
+
+        validate_u32_patterns(|inputs| sorter.indirect_sort(inputs));
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct Record<const L: usize> {
+        key: [u8; L],
+        index: u32,
+        payload: [u64; 4],
+    }
+
+    impl<const L: usize> RadixKey for Record<L> {
+        const LEVELS: usize = L;
+
+        #[inline]
+        fn get_level(&self, level: usize) -> u8 {
+            self.key[L - 1 - level]
+        }
+    }
+
+    struct StableTuner;
+
+    impl Tuner for StableTuner {
+        fn pick_algorithm(&self, p: &TuningParams, _counts: &[usize]) -> Algorithm {
+            if p.input_len <= 128 {
+                Algorithm::LrLsb
+            } else {
+                Algorithm::MtLsb
+            }
+        }
+
+        fn stable(&self) -> bool {
+            true
+        }
+    }
+
+    fn validate_records<const L: usize>(len: usize, modulo: u8, memory_budget: Option<usize>) {
+        let keys: Vec<u8> = block_rand(len * L);
+        let mut inputs: Vec<Record<L>> = keys
+            .chunks_exact(L)
+            .enumerate()
+            .map(|(i, k)| Record {
+                key: std::array::from_fn(|j| k[j] % modulo),
+                index: i as u32,
+                payload: [i as u64; 4],
+            })
+            .collect();
+
+        let mut expected = inputs.clone();
+        expected.sort_by_key(|r| r.key);
+
+        let builder = inputs
+            .radix_sort_builder()
+            .with_tuner(&StableTuner)
+            .indirect();
+
+        match memory_budget {
+            Some(bytes) => builder.with_memory_budget(bytes).sort(),
+            None => builder.sort(),
+        }
+
+        assert_eq!(inputs, expected);
+    }
+
+    #[test]
+    pub fn test_records() {
+        for len in [0, 1, 200, 100_000] {
+            for modulo in [3, 255] {
+                // Each width of packed key, and a key too long to pack
+                validate_records::<3>(len, modulo, None);
+                validate_records::<7>(len, modulo, None);
+                validate_records::<12>(len, modulo, None);
+                validate_records::<16>(len, modulo, None);
+                validate_records::<20>(len, modulo, None);
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_memory_budget() {
+        // No room for the compact array, room for it but not for sorting it out-of-place, and
+        // room for both
+        for memory_budget in [0, 1_300_000, 10_000_000] {
+            validate_records::<7>(100_000, 255, Some(memory_budget));
+        }
+    }
+}
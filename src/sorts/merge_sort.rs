@@ -0,0 +1,352 @@
+//! `merge_sort` is a natural merge sort for inputs made up of a small number of long sorted runs,
+//! such as several sorted logs appended to each other. Rather than radix sorting every item, it
+//! finds the runs that already exist and merges them.
+//!
+//! Runs are found by comparing whole keys from the current level downwards. Any part of the input
+//! that isn't covered by a long run is radix sorted as its own bucket first, so a mostly sorted
+//! input with some unsorted items mixed in still benefits. The sorted runs are then merged
+//! pairwise in a balanced tree, so each item is moved `log2(runs)` times.
+//!
+//! When multi-threaded, the merges within each round of the tree happen in parallel and large
+//! merges are split into independent halves by binary searching for a split point, so even the
+//! final merge uses every thread.
+//!
+//! If there are no long runs at all, this falls back to `ska_sort`.
+//!
+//! ## Characteristics
+//!
+//!  * out-of-place
+//!  * unstable
+//!  * multi-threaded (optional)
+//!
+//! ## Performance
+//!
+//! This is only fast when there are very few runs relative to the length of the input, as every
+//! item is compared and moved once per round of merging. Comparisons also look at the whole key
+//! rather than a single level, which is slower for large keys.
+
+use crate::sorter::Sorter;
+use crate::sorts::cmp_levels;
+use crate::utils::*;
+use crate::RadixKey;
+use arbitrary_chunks::ArbitraryChunks;
+#[cfg(feature = "multi-threaded")]
+use rayon::current_num_threads;
+#[cfg(feature = "multi-threaded")]
+use rayon::prelude::*;
+use std::cmp::Ordering;
+
+/// Runs shorter than this are radix sorted along with their neighbours rather than merged.
+const MIN_RUN: usize = 4_096;
+
+/// Merges with fewer items than this are not split any further between threads.
+#[cfg(feature = "multi-threaded")]
+const MIN_PAR_MERGE: usize = 65_536;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Segment {
+    len: usize,
+    sorted: bool,
+}
+
+/// `find_segments` splits `bucket` into sorted runs of at least `MIN_RUN` items, and unsorted
+/// segments made up of any shorter runs between them.
+fn find_segments<T>(bucket: &[T], level: usize) -> Vec<Segment>
+where
+    T: RadixKey,
+{
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut push = |len: usize| {
+        let sorted = len >= MIN_RUN;
+
+        match segments.last_mut() {
+            Some(s) if !s.sorted && !sorted => s.len += len,
+            _ => segments.push(Segment { len, sorted }),
+        }
+    };
+
+    let mut start = 0;
+    for i in 1..bucket.len() {
+        if cmp_levels(&bucket[i - 1], &bucket[i], level) == Ordering::Greater {
+            push(i - start);
+            start = i;
+        }
+    }
+    push(bucket.len() - start);
+
+    segments
+}
+
+/// `merge` merges the sorted slices `a` and `b` into `out`.
+#[inline]
+fn merge<T>(a: &[T], b: &[T], out: &mut [T], level: usize)
+where
+    T: RadixKey + Copy,
+{
+    if a.is_empty() || b.is_empty() || cmp_levels(a.last().unwrap(), &b[0], level).is_le() {
+        // The runs are already in order relative to each other
+        out[..a.len()].copy_from_slice(a);
+        out[a.len()..].copy_from_slice(b);
+        return;
+    }
+
+    let mut i = 0;
+    let mut j = 0;
+    let mut k = 0;
+
+    while i < a.len() && j < b.len() {
+        let take_a = cmp_levels(&a[i], &b[j], level).is_le();
+        out[k] = if take_a { a[i] } else { b[j] };
+        i += take_a as usize;
+        j += !take_a as usize;
+        k += 1;
+    }
+
+    out[k..k + a.len() - i].copy_from_slice(&a[i..]);
+    out[k + a.len() - i..].copy_from_slice(&b[j..]);
+}
+
+/// `par_merge` merges the sorted slices `a` and `b` into `out`, recursively splitting the merge
+/// in half around the middle item of the longer slice.
+#[cfg(feature = "multi-threaded")]
+fn par_merge<T>(a: &[T], b: &[T], out: &mut [T], level: usize)
+where
+    T: RadixKey + Copy + Send + Sync,
+{
+    if out.len() < MIN_PAR_MERGE {
+        merge(a, b, out, level);
+        return;
+    }
+
+    let (a, b) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mid = a.len() / 2;
+    let split = b.partition_point(|v| cmp_levels(v, &a[mid], level).is_lt());
+    let (out_l, out_r) = out.split_at_mut(mid + split);
+
+    rayon::join(
+        || par_merge(&a[..mid], &b[..split], out_l, level),
+        || par_merge(&a[mid..], &b[split..], out_r, level),
+    );
+}
+
+impl<'a> Sorter<'a> {
+    /// `merge_round` merges each pair of neighbouring runs in `src` into `dst`. `ends` holds the
+    /// end offset of each run. Returns the end offsets of the merged runs.
+    fn merge_round<T>(&self, src: &[T], dst: &mut [T], ends: &[usize], level: usize) -> Vec<usize>
+    where
+        T: RadixKey + Copy + Send + Sync,
+    {
+        trace_scope!("merge_round", runs = ends.len(), len = src.len());
+
+        let mut merges = Vec::with_capacity(ends.len().div_ceil(2));
+        let mut src = src;
+        let mut dst = dst;
+        let mut start = 0;
+
+        for pair in ends.chunks(2) {
+            let end = *pair.last().unwrap();
+            let (s, src_rest) = src.split_at(end - start);
+            let (d, dst_rest) = dst.split_at_mut(end - start);
+
+            merges.push((s.split_at(pair[0] - start), d));
+            src = src_rest;
+            dst = dst_rest;
+            start = end;
+        }
+
+        #[cfg(feature = "multi-threaded")]
+        if self.multi_threaded {
+            merges
+                .into_par_iter()
+                .for_each(|((a, b), out)| par_merge(a, b, out, level));
+
+            return ends.chunks(2).map(|p| *p.last().unwrap()).collect();
+        }
+
+        merges
+            .into_iter()
+            .for_each(|((a, b), out)| merge(a, b, out, level));
+
+        ends.chunks(2).map(|p| *p.last().unwrap()).collect()
+    }
+
+    pub(crate) fn merge_sort_adapter<T>(
+        &self,
+        bucket: &mut [T],
+        counts: &[usize; 256],
+        level: usize,
+    ) where
+        T: RadixKey + Sized + Send + Copy + Sync,
+    {
+        if bucket.len() < 2 {
+            return;
+        }
+
+        let segments = find_segments(bucket, level);
+
+        if segments.len() == 1 {
+            if !segments[0].sorted {
+                // There are no long runs to take advantage of
+                self.ska_sort_adapter(bucket, counts, level);
+            }
+
+            return;
+        }
+
+        #[cfg(feature = "multi-threaded")]
+        let threads = if self.multi_threaded {
+            current_num_threads()
+        } else {
+            1
+        };
+
+        #[cfg(not(feature = "multi-threaded"))]
+        let threads = 1;
+
+        let parent_len = Some(bucket.len());
+        let lens: Vec<usize> = segments.iter().map(|s| s.len).collect();
+        let unsorted = bucket
+            .arbitrary_chunks_mut(&lens)
+            .zip(segments.iter())
+            .filter(|(_, s)| !s.sorted)
+            .map(|(chunk, _)| chunk);
+
+        #[cfg(feature = "multi-threaded")]
+        if self.multi_threaded {
+            unsorted
+                .par_bridge()
+                .for_each(|chunk| self.handle_chunk(chunk, level, parent_len, threads));
+        } else {
+            unsorted.for_each(|chunk| self.handle_chunk(chunk, level, parent_len, threads));
+        }
+
+        #[cfg(not(feature = "multi-threaded"))]
+        unsorted.for_each(|chunk| self.handle_chunk(chunk, level, parent_len, threads));
+
+        let mut ends: Vec<usize> = lens
+            .iter()
+            .scan(0, |end, len| {
+                *end += len;
+                Some(*end)
+            })
+            .collect();
+
+        let mut tmp_bucket = get_tmp_bucket(bucket.len());
+        let mut in_tmp = false;
+
+        while ends.len() > 1 {
+            ends = if in_tmp {
+                self.merge_round(&tmp_bucket, bucket, &ends, level)
+            } else {
+                self.merge_round(bucket, &mut tmp_bucket, &ends, level)
+            };
+            in_tmp = !in_tmp;
+        }
+
+        if in_tmp {
+            bucket.copy_from_slice(&tmp_bucket);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sorter::Sorter;
+    use crate::tuner::Algorithm;
+    use crate::tuners::StandardTuner;
+    use crate::utils::get_counts;
+    use crate::utils::test_utils::{
+        sort_comparison_suite, sort_single_algorithm, validate_u32_patterns, NumericTest,
+    };
+    use crate::RadixKey;
+    use block_pseudorand::block_rand;
+
+    fn test_merge_sort_adapter<T>(shift: T)
+    where
+        T: NumericTest<T>,
+    {
+        for multi_threaded in [true, false] {
+            let sorter = Sorter::new(multi_threaded, &StandardTuner);
+
+            sort_comparison_suite(shift, |inputs| {
+                let (counts, _) = get_counts(inputs, T::LEVELS - 1);
+
+                sorter.merge_sort_adapter(inputs, &counts, T::LEVELS - 1);
+            });
+        }
+    }
+
+    #[test]
+    pub fn test_u8() {
+        test_merge_sort_adapter(0u8);
+    }
+
+    #[test]
+    pub fn test_u16() {
+        test_merge_sort_adapter(8u16);
+    }
+
+    #[test]
+    pub fn test_u32() {
+        test_merge_sort_adapter(16u32);
+    }
+
+    #[test]
+    pub fn test_u64() {
+        test_merge_sort_adapter(32u64);
+    }
+
+    #[test]
+    pub fn test_u128() {
+        test_merge_sort_adapter(64u128);
+    }
+
+    #[test]
+    pub fn test_usize() {
+        test_merge_sort_adapter(32usize);
+    }
+
+    #[test]
+    pub fn test_basic_integration() {
+        sort_single_algorithm::<u32>(1_000_000, Algorithm::Merge);
+    }
+
+    #[test]
+    pub fn test_u32_patterns() {
+        let sorter = Sorter::new(true, &StandardTuner);
+
+        validate_u32_patterns(|inputs| {
+            let (counts, _) = get_counts(inputs, u32::LEVELS - 1);
+
+            sorter.merge_sort_adapter(inputs, &counts, u32::LEVELS - 1);
+        });
+    }
+
+    #[test]
+    pub fn test_natural_runs() {
+        // Sorted runs of varying lengths, with some unsorted items appended
+        for multi_threaded in [true, false] {
+            let sorter = Sorter::new(multi_threaded, &StandardTuner);
+
+            for runs in [2, 3, 7, 16] {
+                let mut inputs: Vec<u64> = Vec::new();
+
+                for r in 0..runs {
+                    let mut run: Vec<u64> = block_rand(50_000 + r * 10_007);
+                    run.sort_unstable();
+                    inputs.extend(run);
+                }
+
+                inputs.extend(block_rand::<u64>(30_000));
+
+                let mut expected = inputs.clone();
+                expected.sort_unstable();
+
+                let (counts, _) = get_counts(&inputs, u64::LEVELS - 1);
+                sorter.merge_sort_adapter(&mut inputs, &counts, u64::LEVELS - 1);
+
+                assert_eq!(inputs, expected);
+            }
+        }
+    }
+}
@@ -1,7 +1,14 @@
+mod american_flag_sort;
+#[cfg(feature = "multi-threaded")]
+mod block_sort;
 mod comparative_sort;
+mod indirect_sort;
 mod lsb_sort;
+mod merge_sort;
 #[cfg(feature = "multi-threaded")]
 mod mt_lsb_sort;
+#[cfg(feature = "multi-threaded")]
+mod mt_stable_msb_sort;
 mod out_of_place_sort;
 #[cfg(feature = "multi-threaded")]
 mod recombinating_sort;
@@ -10,3 +17,5 @@ mod regions_sort;
 #[cfg(feature = "multi-threaded")]
 mod scanning_sort;
 mod ska_sort;
+
+pub(crate) use comparative_sort::cmp_levels;
@@ -22,27 +22,27 @@
 //!
 //! ## Optimizations
 //!
-//! This shares pretty much all optimizations implemented for LsbSort.
+//! This shares pretty much all optimizations implemented for LsbSort, including write-combining
+//! (see `buffered_mt_lsb_sort`) and skipping homogenous levels when all levels were counted up
+//! front. Tiles still need to be counted for each level that is sorted.
 //!
 //! ## `mt_oop_sort` Variant
 //!
 //! This variant uses the same algorithm as `mt_lsb_sort` but uses it in msb-first order.
 
 use crate::sorter::Sorter;
+use crate::sorts::out_of_place_sort::WriteCombiningBuffers;
 use crate::utils::*;
 use crate::RadixKey;
 use arbitrary_chunks::ArbitraryChunks;
 use rayon::prelude::*;
 
-pub fn mt_lsb_sort<T>(
-    src_bucket: &mut [T],
-    dst_bucket: &mut [T],
+/// `collate_chunks` splits `dst_bucket` into the output slice for each bucket of each tile, grouped
+/// by tile.
+fn collate_chunks<'a, T>(
+    dst_bucket: &'a mut [T],
     tile_counts: &[[usize; 256]],
-    tile_size: usize,
-    level: usize,
-) where
-    T: RadixKey + Sized + Send + Copy + Sync,
-{
+) -> Vec<Vec<&'a mut [T]>> {
     let tiles = tile_counts.len();
     let mut minor_counts = Vec::with_capacity(256 * tiles);
 
@@ -64,6 +64,20 @@ pub fn mt_lsb_sort<T>(
         }
     }
 
+    collated_chunks
+}
+
+pub fn mt_lsb_sort<T>(
+    src_bucket: &mut [T],
+    dst_bucket: &mut [T],
+    tile_counts: &[[usize; 256]],
+    tile_size: usize,
+    level: usize,
+) where
+    T: RadixKey + Sized + Send + Copy + Sync,
+{
+    let collated_chunks = collate_chunks(dst_bucket, tile_counts);
+
     collated_chunks
         .into_par_iter()
         .zip(src_bucket.par_chunks(tile_size))
@@ -134,13 +148,58 @@ pub fn mt_lsb_sort<T>(
         });
 }
 
+/// `buffered_mt_lsb_sort` is the same as `mt_lsb_sort`, except that each tile is scattered through
+/// write-combining buffers. See `buffered_out_of_place_sort`.
+pub fn buffered_mt_lsb_sort<T>(
+    src_bucket: &mut [T],
+    dst_bucket: &mut [T],
+    tile_counts: &[[usize; 256]],
+    tile_size: usize,
+    level: usize,
+) where
+    T: RadixKey + Sized + Send + Copy + Sync,
+{
+    if src_bucket.is_empty() {
+        return;
+    } else if !WriteCombiningBuffers::<T>::supported() {
+        mt_lsb_sort(src_bucket, dst_bucket, tile_counts, tile_size, level);
+        return;
+    }
+
+    let fill = src_bucket[0];
+    let collated_chunks = collate_chunks(dst_bucket, tile_counts);
+
+    collated_chunks
+        .into_par_iter()
+        .zip(src_bucket.par_chunks(tile_size))
+        .for_each(|(mut buckets, bucket)| {
+            let mut buffers = WriteCombiningBuffers::new(fill);
+            let mut offsets = [0usize; 256];
+            let mut flush = |b: usize, items: &[T]| {
+                buckets[b][offsets[b]..offsets[b] + items.len()].copy_from_slice(items);
+                offsets[b] += items.len();
+            };
+
+            for item in bucket.iter() {
+                buffers.push(item.get_level(level) as usize, *item, &mut flush);
+            }
+
+            buffers.flush_all(&mut flush);
+        });
+}
+
 impl<'a> Sorter<'a> {
+    /// `mt_lsb_sort_adapter` sorts `bucket` from `start_level` up to `end_level`. If
+    /// `level_counts` is given, it must hold the counts for every level from `start_level` to
+    /// `end_level`, and is used to skip homogenous levels without counting their tiles.
     pub(crate) fn mt_lsb_sort_adapter<T>(
         &self,
         bucket: &mut [T],
         start_level: usize,
         end_level: usize,
         tile_size: usize,
+        write_combining: bool,
+        level_counts: Option<&[[usize; 256]]>,
     ) where
         T: RadixKey + Sized + Send + Copy + Sync,
     {
@@ -148,8 +207,17 @@ impl<'a> Sorter<'a> {
             return;
         }
 
+        let mut levels: Vec<usize> = (start_level..=end_level).collect();
+
+        if let Some(level_counts) = level_counts {
+            levels.retain(|l| !is_homogenous_bucket(&level_counts[l - start_level]));
+
+            if levels.is_empty() {
+                return;
+            }
+        }
+
         let mut tmp_bucket = get_tmp_bucket(bucket.len());
-        let levels: Vec<usize> = (start_level..=end_level).collect();
         let mut invert = false;
 
         for level in levels {
@@ -163,10 +231,19 @@ impl<'a> Sorter<'a> {
                 continue;
             }
 
-            if invert {
-                mt_lsb_sort(&mut tmp_bucket, bucket, &tile_counts, tile_size, level)
-            } else {
-                mt_lsb_sort(bucket, &mut tmp_bucket, &tile_counts, tile_size, level)
+            match (write_combining, invert) {
+                (true, true) => {
+                    buffered_mt_lsb_sort(&mut tmp_bucket, bucket, &tile_counts, tile_size, level)
+                }
+                (true, false) => {
+                    buffered_mt_lsb_sort(bucket, &mut tmp_bucket, &tile_counts, tile_size, level)
+                }
+                (false, true) => {
+                    mt_lsb_sort(&mut tmp_bucket, bucket, &tile_counts, tile_size, level)
+                }
+                (false, false) => {
+                    mt_lsb_sort(bucket, &mut tmp_bucket, &tile_counts, tile_size, level)
+                }
             };
 
             invert = !invert;
@@ -208,6 +285,10 @@ impl<'a> Sorter<'a> {
 
         drop(tmp_bucket);
 
+        if level == 0 {
+            return;
+        }
+
         self.director(bucket, counts, level - 1);
     }
 }
@@ -217,10 +298,10 @@ mod tests {
     use crate::sorter::Sorter;
     use crate::tuner::Algorithm;
     use crate::tuners::StandardTuner;
-    use crate::utils::cdiv;
     use crate::utils::test_utils::{
         sort_comparison_suite, sort_single_algorithm, validate_u32_patterns, NumericTest,
     };
+    use crate::utils::{cdiv, get_all_counts};
     use crate::RadixKey;
     use rayon::current_num_threads;
 
@@ -230,15 +311,25 @@ mod tests {
     {
         let sorter = Sorter::new(true, &StandardTuner);
 
-        sort_comparison_suite(shift, |inputs| {
-            if inputs.len() == 0 {
-                return;
-            }
-
-            let tile_size = cdiv(inputs.len(), current_num_threads());
+        for (write_combining, count_all) in [(false, false), (true, false), (false, true)] {
+            sort_comparison_suite(shift, |inputs| {
+                if inputs.is_empty() {
+                    return;
+                }
 
-            sorter.mt_lsb_sort_adapter(inputs, 0, T::LEVELS - 1, tile_size);
-        });
+                let tile_size = cdiv(inputs.len(), current_num_threads());
+                let level_counts = get_all_counts(inputs, 0, T::LEVELS - 1);
+
+                sorter.mt_lsb_sort_adapter(
+                    inputs,
+                    0,
+                    T::LEVELS - 1,
+                    tile_size,
+                    write_combining,
+                    count_all.then_some(&level_counts[..]),
+                );
+            });
+        }
     }
 
     #[test]
@@ -286,14 +377,35 @@ mod tests {
     #[test]
     pub fn test_u32_patterns() {
         validate_u32_patterns(|inputs| {
-            if inputs.len() == 0 {
+            if inputs.is_empty() {
                 return;
             }
 
             let sorter = Sorter::new(true, &StandardTuner);
             let tile_size = cdiv(inputs.len(), current_num_threads());
 
-            sorter.mt_lsb_sort_adapter(inputs, 0, u32::LEVELS - 1, tile_size);
+            sorter.mt_lsb_sort_adapter(inputs, 0, u32::LEVELS - 1, tile_size, false, None);
+        });
+    }
+
+    #[test]
+    pub fn test_write_combining_tiles() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+        let sorter = Sorter::new(true, &StandardTuner);
+
+        pool.install(|| {
+            sort_comparison_suite(32u64, |inputs| {
+                if inputs.is_empty() {
+                    return;
+                }
+
+                let tile_size = cdiv(inputs.len(), current_num_threads());
+
+                sorter.mt_lsb_sort_adapter(inputs, 0, u64::LEVELS - 1, tile_size, true, None);
+            });
         });
     }
 }
@@ -0,0 +1,299 @@
+//! `mt_stable_msb_sort` is a stable, multi-threaded Most-Significant Bit first radix sort. Each
+//! level is sorted with the same tiled, out-of-place pass as `mt_lsb_sort` (see the `mt_oop_sort`
+//! variant), which keeps items in their original relative order within each bucket. The buckets are
+//! then sorted in parallel, recursing into the next level.
+//!
+//! Unlike the other MSB algorithms, this does not hand buckets back to the tuner, as most of the
+//! algorithms it could pick are unstable. Instead:
+//!
+//!  * buckets with a single item are finished
+//!  * buckets of up to 128 items are sorted with a stable comparison sort
+//!  * buckets small enough to fit in a single tile are sorted with a single-threaded stable LSB
+//!    sort if there are only a few levels left, otherwise they continue to be MSB sorted
+//!  * larger buckets are counted in tiles again and MSB sorted
+//!
+//! ## Characteristics
+//!
+//!  * out-of-place
+//!  * multi-threaded
+//!  * stable
+//!  * msb-first
+//!
+//! ## Performance
+//!
+//! `mt_lsb_sort` must process every level of the key, even when the input is already fully
+//! separated by the first few levels. This stops recursing as soon as buckets are small, so for
+//! long keys such as `u128` or `[u8; 32]` it only pays for the levels that are actually needed. For
+//! short keys, `mt_lsb_sort` is generally faster.
+
+use crate::sorter::Sorter;
+use crate::sorts::mt_lsb_sort::mt_lsb_sort;
+use crate::utils::*;
+use crate::RadixKey;
+use arbitrary_chunks::ArbitraryChunks;
+use rayon::current_num_threads;
+use rayon::prelude::*;
+use std::cmp::max;
+
+/// Buckets with at most this many remaining levels are sorted with LSB once they fit in a tile.
+const MAX_LSB_LEVELS: usize = 4;
+
+/// Buckets smaller than this are sorted as a single tile.
+const MIN_TILE_SIZE: usize = 30_000;
+
+impl<'a> Sorter<'a> {
+    /// `stable_director` sorts each bucket of `bucket` at `level`, in parallel where possible.
+    fn stable_director<T>(&self, bucket: &mut [T], counts: &[usize; 256], level: usize)
+    where
+        T: RadixKey + Sized + Send + Copy + Sync,
+    {
+        if level < self.end_level {
+            return;
+        }
+
+        if self.multi_threaded {
+            let threads = current_num_threads();
+
+            bucket
+                .arbitrary_chunks_mut(counts)
+                .par_bridge()
+                .for_each(|chunk| self.stable_handle_chunk(chunk, level, threads));
+        } else {
+            bucket
+                .arbitrary_chunks_mut(counts)
+                .for_each(|chunk| self.stable_handle_chunk(chunk, level, 1));
+        }
+    }
+
+    fn stable_handle_chunk<T>(&self, chunk: &mut [T], level: usize, threads: usize)
+    where
+        T: RadixKey + Sized + Send + Copy + Sync,
+    {
+        if chunk.len() <= 1 {
+            return;
+        } else if chunk.len() <= 128 {
+            self.stable_comparative_sort(chunk, level);
+            return;
+        }
+
+        if chunk.len() < MIN_TILE_SIZE && level < MAX_LSB_LEVELS {
+            let (counts, _) = get_counts(chunk, level);
+            self.lsb_sort_adapter(
+                true,
+                false,
+                true,
+                chunk,
+                &counts,
+                None,
+                self.end_level,
+                level,
+            );
+            return;
+        }
+
+        let tile_size = if chunk.len() < MIN_TILE_SIZE {
+            chunk.len()
+        } else {
+            max(MIN_TILE_SIZE, cdiv(chunk.len(), threads))
+        };
+
+        let (tile_counts, already_sorted) = get_tile_counts(chunk, tile_size, level);
+        let counts = aggregate_tile_counts(&tile_counts);
+
+        if already_sorted {
+            if level != 0 {
+                self.stable_director(chunk, &counts, level - 1);
+            }
+
+            return;
+        }
+
+        self.mt_stable_msb_sort_adapter(chunk, level, &counts, &tile_counts, tile_size);
+    }
+
+    pub(crate) fn mt_stable_msb_sort_adapter<T>(
+        &self,
+        bucket: &mut [T],
+        level: usize,
+        counts: &[usize; 256],
+        tile_counts: &[[usize; 256]],
+        tile_size: usize,
+    ) where
+        T: RadixKey + Sized + Send + Copy + Sync,
+    {
+        if bucket.len() <= 1 {
+            return;
+        }
+
+        let mut tmp_bucket = get_tmp_bucket(bucket.len());
+        mt_lsb_sort(bucket, &mut tmp_bucket, tile_counts, tile_size, level);
+
+        bucket
+            .par_chunks_mut(tile_size)
+            .zip(tmp_bucket.par_chunks(tile_size))
+            .for_each(|(chunk, tmp_chunk)| {
+                chunk.copy_from_slice(tmp_chunk);
+            });
+
+        drop(tmp_bucket);
+
+        if level == 0 {
+            return;
+        }
+
+        self.stable_director(bucket, counts, level - 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sorter::Sorter;
+    use crate::tuner::{Algorithm, Tuner, TuningParams};
+    use crate::tuners::StandardTuner;
+    use crate::utils::test_utils::{
+        sort_comparison_suite, sort_single_algorithm, validate_u32_patterns, NumericTest,
+    };
+    use crate::utils::{cdiv, get_tile_counts};
+    use crate::{RadixKey, RadixSort};
+    use block_pseudorand::block_rand;
+    use rayon::current_num_threads;
+
+    fn sort<T>(sorter: &Sorter, inputs: &mut [T])
+    where
+        T: RadixKey + Sized + Send + Copy + Sync,
+    {
+        if inputs.is_empty() {
+            return;
+        }
+
+        let tile_size = cdiv(inputs.len(), current_num_threads());
+        let (tile_counts, _) = get_tile_counts(inputs, tile_size, T::LEVELS - 1);
+        let counts = crate::utils::aggregate_tile_counts(&tile_counts);
+
+        sorter.mt_stable_msb_sort_adapter(inputs, T::LEVELS - 1, &counts, &tile_counts, tile_size);
+    }
+
+    fn test_mt_stable_msb_sort_adapter<T>(shift: T)
+    where
+        T: NumericTest<T>,
+    {
+        let sorter = Sorter::new(true, &StandardTuner);
+
+        sort_comparison_suite(shift, |inputs| sort(&sorter, inputs));
+    }
+
+    #[test]
+    pub fn test_u8() {
+        test_mt_stable_msb_sort_adapter(0u8);
+    }
+
+    #[test]
+    pub fn test_u16() {
+        test_mt_stable_msb_sort_adapter(8u16);
+    }
+
+    #[test]
+    pub fn test_u32() {
+        test_mt_stable_msb_sort_adapter(16u32);
+    }
+
+    #[test]
+    pub fn test_u64() {
+        test_mt_stable_msb_sort_adapter(32u64);
+    }
+
+    #[test]
+    pub fn test_u128() {
+        test_mt_stable_msb_sort_adapter(64u128);
+    }
+
+    #[test]
+    pub fn test_usize() {
+        test_mt_stable_msb_sort_adapter(32usize);
+    }
+
+    #[test]
+    pub fn test_basic_integration() {
+        sort_single_algorithm::<u32>(1_000_000, Algorithm::MtStableMsb);
+    }
+
+    #[test]
+    pub fn test_u32_patterns() {
+        let sorter = Sorter::new(true, &StandardTuner);
+
+        validate_u32_patterns(|inputs| sort(&sorter, inputs));
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct Keyed {
+        key: u128,
+        index: u32,
+    }
+
+    impl RadixKey for Keyed {
+        const LEVELS: usize = 16;
+
+        #[inline]
+        fn get_level(&self, level: usize) -> u8 {
+            self.key.get_level(level)
+        }
+    }
+
+    struct StableTuner;
+
+    impl Tuner for StableTuner {
+        fn pick_algorithm(&self, p: &TuningParams, _counts: &[usize]) -> Algorithm {
+            if p.input_len <= 128 {
+                Algorithm::LrLsb
+            } else {
+                Algorithm::MtStableMsb
+            }
+        }
+
+        fn stable(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    pub fn test_stability() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+
+        pool.install(|| {
+            for len in [100, 5_000, 100_000, 1_000_000] {
+                let seeds: Vec<u16> = block_rand(len);
+                let patterns: [Box<dyn Fn(usize, u16) -> u128>; 4] = [
+                    // Few distinct keys spread across the high and low levels
+                    Box::new(|_, s| ((s as u128 % 16) << 120) | (s as u128 % 3)),
+                    // Keys which only differ in the lowest levels
+                    Box::new(|_, s| s as u128 % 1_000),
+                    // Descending, with runs of equal keys
+                    Box::new(move |i, _| ((len - i) as u128 / 7) << 64),
+                    // Mostly unique keys, with a few duplicates
+                    Box::new(|i, s| ((s as u128) << 100) | (i as u128 / 2)),
+                ];
+
+                for pattern in patterns.iter() {
+                    let mut inputs: Vec<Keyed> = seeds
+                        .iter()
+                        .enumerate()
+                        .map(|(i, s)| Keyed {
+                            key: pattern(i, *s),
+                            index: i as u32,
+                        })
+                        .collect();
+
+                    let mut expected = inputs.clone();
+                    expected.sort_by_key(|v| v.key);
+
+                    inputs.radix_sort_builder().with_tuner(&StableTuner).sort();
+
+                    assert_eq!(inputs, expected);
+                }
+            }
+        });
+    }
+}
@@ -37,6 +37,24 @@
 //! As with the other with_counts variant, this combines the left-right optimization with counting
 //! the next level.
 //!
+//! ### buffered_out_of_place_sort
+//!
+//! This variant stages items in a small write-combining buffer for each bucket rather than writing
+//! them straight to the output array, and copies each buffer out in one go once it is full. With
+//! 256 output streams, writing directly touches a different cache line (and often a different
+//! page) for almost every item, which thrashes the cache and TLB on large arrays. The buffers are
+//! cache-line aligned and small enough to stay in L1/L2, so the output array only sees bulk writes.
+//!
+//! The buffers are flushed in order, so this is stable. It only helps once the output array is
+//! much larger than the cache, so it is only used when the tuner asks for it.
+//!
+//! ### wide_out_of_place_sort
+//!
+//! This variant sorts by a 16-bit digit made of two adjacent levels, with 65,536 buckets, so an
+//! LSB sort needs half as many passes. It counts its own digit first, as counts for single levels
+//! can't be combined into counts for pairs. It only pays off once the input is large enough that
+//! the extra buckets are mostly full, so it is only used when the tuner asks for it.
+//!
 //! ## Characteristics
 //!
 //!  * out-of-place
@@ -45,6 +63,123 @@
 
 use crate::utils::*;
 use crate::RadixKey;
+use std::mem::size_of;
+
+/// Size in bytes of each bucket's write-combining buffer.
+const WC_BUFFER_BYTES: usize = 256;
+
+/// Alignment in bytes of the write-combining buffers.
+const CACHE_LINE_BYTES: usize = 64;
+
+/// `WriteCombiningBuffers` holds a small buffer of items for each of the 256 buckets. Items are
+/// pushed into their bucket's buffer, and each buffer is passed to a flush function when it fills.
+/// Each buffer is a whole number of cache lines for primitive types.
+pub struct WriteCombiningBuffers<T> {
+    items: Vec<T>,
+    start: usize,
+    per_bucket: usize,
+    lens: [usize; 256],
+}
+
+impl<T> WriteCombiningBuffers<T>
+where
+    T: Copy,
+{
+    /// `supported` returns whether `T` is small enough for buffering to be worthwhile.
+    #[inline]
+    pub fn supported() -> bool {
+        WC_BUFFER_BYTES / size_of::<T>().max(1) >= 2
+    }
+
+    /// `new` creates a set of buffers, using `fill` to initialize them. `supported()` must be true.
+    pub fn new(fill: T) -> Self {
+        let per_bucket = WC_BUFFER_BYTES / size_of::<T>().max(1);
+
+        // Over-allocate by a cache line so the first buffer can start on a cache line boundary
+        let slack = CACHE_LINE_BYTES / size_of::<T>().max(1);
+        let items = vec![fill; 256 * per_bucket + slack];
+        let start = match items.as_ptr().align_offset(CACHE_LINE_BYTES) {
+            offset if offset <= slack => offset,
+            _ => 0,
+        };
+
+        Self {
+            items,
+            start,
+            per_bucket,
+            lens: [0; 256],
+        }
+    }
+
+    /// `push` adds `item` to the buffer for bucket `b`, passing the buffer to `flush` if it is
+    /// now full.
+    #[inline(always)]
+    pub fn push<F>(&mut self, b: usize, item: T, flush: &mut F)
+    where
+        F: FnMut(usize, &[T]),
+    {
+        let offset = self.start + b * self.per_bucket;
+        let len = self.lens[b];
+
+        self.items[offset + len] = item;
+
+        if len + 1 == self.per_bucket {
+            flush(b, &self.items[offset..offset + self.per_bucket]);
+            self.lens[b] = 0;
+        } else {
+            self.lens[b] = len + 1;
+        }
+    }
+
+    /// `flush_all` passes every non-empty buffer to `flush`, in bucket order, and empties them.
+    #[inline]
+    pub fn flush_all<F>(&mut self, flush: &mut F)
+    where
+        F: FnMut(usize, &[T]),
+    {
+        for b in 0..256 {
+            if self.lens[b] > 0 {
+                let offset = self.start + b * self.per_bucket;
+                flush(b, &self.items[offset..offset + self.lens[b]]);
+                self.lens[b] = 0;
+            }
+        }
+    }
+}
+
+#[inline]
+pub fn buffered_out_of_place_sort<T>(
+    src_bucket: &[T],
+    dst_bucket: &mut [T],
+    counts: &[usize; 256],
+    level: usize,
+) where
+    T: RadixKey + Sized + Send + Copy + Sync,
+{
+    if src_bucket.len() < 2 {
+        dst_bucket.copy_from_slice(src_bucket);
+        return;
+    }
+
+    if !WriteCombiningBuffers::<T>::supported() {
+        out_of_place_sort(src_bucket, dst_bucket, counts, level);
+        return;
+    }
+
+    let mut buffers = WriteCombiningBuffers::new(src_bucket[0]);
+    let mut prefix_sums = get_prefix_sums(counts);
+    let mut flush = |b: usize, items: &[T]| {
+        let start = prefix_sums[b];
+        dst_bucket[start..start + items.len()].copy_from_slice(items);
+        prefix_sums[b] += items.len();
+    };
+
+    for item in src_bucket.iter() {
+        buffers.push(item.get_level(level) as usize, *item, &mut flush);
+    }
+
+    buffers.flush_all(&mut flush);
+}
 
 #[inline]
 pub fn out_of_place_sort<T>(
@@ -100,6 +235,42 @@ pub fn out_of_place_sort<T>(
     });
 }
 
+/// `wide_out_of_place_sort` sorts `src_bucket` into `dst_bucket` by the 16-bit digit made of
+/// `level + 1` (the high byte) and `level` (the low byte). Returns false, without writing
+/// anything, if every item has the same digit.
+#[inline]
+pub fn wide_out_of_place_sort<T>(src_bucket: &[T], dst_bucket: &mut [T], level: usize) -> bool
+where
+    T: RadixKey + Sized + Send + Copy + Sync,
+{
+    let digit =
+        |item: &T| ((item.get_level(level + 1) as usize) << 8) | item.get_level(level) as usize;
+
+    let mut prefix_sums = vec![0usize; 65_536];
+    for item in src_bucket.iter() {
+        prefix_sums[digit(item)] += 1;
+    }
+
+    if prefix_sums.contains(&src_bucket.len()) {
+        return false;
+    }
+
+    let mut sum = 0;
+    for c in prefix_sums.iter_mut() {
+        let count = *c;
+        *c = sum;
+        sum += count;
+    }
+
+    for item in src_bucket.iter() {
+        let d = digit(item);
+        dst_bucket[prefix_sums[d]] = *item;
+        prefix_sums[d] += 1;
+    }
+
+    true
+}
+
 #[inline]
 pub fn out_of_place_sort_with_counts<T>(
     src_bucket: &[T],
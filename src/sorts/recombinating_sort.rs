@@ -23,7 +23,7 @@
 //! this sort, and eventually the extra allocation and freeing required eats away at the performance.
 
 use crate::sorter::Sorter;
-use crate::sorts::out_of_place_sort::out_of_place_sort;
+use crate::sorts::out_of_place_sort::{buffered_out_of_place_sort, out_of_place_sort};
 use crate::utils::*;
 use crate::RadixKey;
 use arbitrary_chunks::ArbitraryChunks;
@@ -35,6 +35,7 @@ pub fn recombinating_sort<T>(
     tile_counts: &[[usize; 256]],
     tile_size: usize,
     level: usize,
+    write_combining: bool,
 ) where
     T: RadixKey + Sized + Send + Copy + Sync,
 {
@@ -46,7 +47,12 @@ pub fn recombinating_sort<T>(
         .zip(tmp_bucket.par_chunks_mut(tile_size))
         .zip(tile_counts.into_par_iter())
         .map(|((chunk, tmp_chunk), counts)| {
-            out_of_place_sort(chunk, tmp_chunk, counts, level);
+            trace_scope!("recombinating_tile_sort", level = level, len = chunk.len());
+            if write_combining {
+                buffered_out_of_place_sort(chunk, tmp_chunk, counts, level);
+            } else {
+                out_of_place_sort(chunk, tmp_chunk, counts, level);
+            }
 
             let sums = get_prefix_sums(counts);
 
@@ -59,6 +65,11 @@ pub fn recombinating_sort<T>(
         .enumerate()
         .par_bridge()
         .for_each(|(index, global_chunk)| {
+            trace_scope!(
+                "recombinating_merge",
+                bucket = index,
+                len = global_chunk.len()
+            );
             let mut read_offset = 0;
             let mut write_offset = 0;
 
@@ -84,6 +95,7 @@ impl<'a> Sorter<'a> {
         tile_counts: &[[usize; 256]],
         tile_size: usize,
         level: usize,
+        write_combining: bool,
     ) where
         T: RadixKey + Sized + Send + Copy + Sync,
     {
@@ -91,7 +103,14 @@ impl<'a> Sorter<'a> {
             return;
         }
 
-        recombinating_sort(bucket, counts, tile_counts, tile_size, level);
+        recombinating_sort(
+            bucket,
+            counts,
+            tile_counts,
+            tile_size,
+            level,
+            write_combining,
+        );
 
         if level == 0 {
             return;
@@ -119,25 +138,28 @@ mod tests {
     {
         let sorter = Sorter::new(true, &StandardTuner);
 
-        sort_comparison_suite(shift, |inputs| {
-            let level = T::LEVELS - 1;
-            let tile_size = cdiv(inputs.len(), current_num_threads());
-
-            if inputs.len() == 0 {
-                return;
-            }
-
-            let (tile_counts, _) = get_tile_counts(inputs, tile_size, level);
-            let counts = aggregate_tile_counts(&tile_counts);
-
-            sorter.recombinating_sort_adapter(
-                inputs,
-                &counts,
-                &tile_counts,
-                tile_size,
-                T::LEVELS - 1,
-            )
-        });
+        for write_combining in [false, true] {
+            sort_comparison_suite(shift, |inputs| {
+                let level = T::LEVELS - 1;
+                let tile_size = cdiv(inputs.len(), current_num_threads());
+
+                if inputs.is_empty() {
+                    return;
+                }
+
+                let (tile_counts, _) = get_tile_counts(inputs, tile_size, level);
+                let counts = aggregate_tile_counts(&tile_counts);
+
+                sorter.recombinating_sort_adapter(
+                    inputs,
+                    &counts,
+                    &tile_counts,
+                    tile_size,
+                    T::LEVELS - 1,
+                    write_combining,
+                )
+            });
+        }
     }
 
     #[test]
@@ -179,18 +201,27 @@ mod tests {
     pub fn test_u32_patterns() {
         let sorter = Sorter::new(true, &StandardTuner);
 
-        validate_u32_patterns(|inputs| {
-            let level = u32::LEVELS - 1;
-            let tile_size = cdiv(inputs.len(), current_num_threads());
-
-            if inputs.len() == 0 {
-                return;
-            }
-
-            let (tile_counts, _) = get_tile_counts(inputs, tile_size, level);
-            let counts = aggregate_tile_counts(&tile_counts);
-
-            sorter.recombinating_sort_adapter(inputs, &counts, &tile_counts, tile_size, level)
-        });
+        for write_combining in [false, true] {
+            validate_u32_patterns(|inputs| {
+                let level = u32::LEVELS - 1;
+                let tile_size = cdiv(inputs.len(), current_num_threads());
+
+                if inputs.is_empty() {
+                    return;
+                }
+
+                let (tile_counts, _) = get_tile_counts(inputs, tile_size, level);
+                let counts = aggregate_tile_counts(&tile_counts);
+
+                sorter.recombinating_sort_adapter(
+                    inputs,
+                    &counts,
+                    &tile_counts,
+                    tile_size,
+                    level,
+                    write_combining,
+                )
+            });
+        }
     }
 }
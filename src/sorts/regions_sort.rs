@@ -217,6 +217,7 @@ pub fn regions_sort<T>(
         .par_chunks_mut(tile_size)
         .zip(tile_counts.par_iter())
         .for_each(|(chunk, counts)| {
+            trace_scope!("regions_tile_sort", level = level, len = chunk.len());
             let mut prefix_sums = get_prefix_sums(counts);
             let end_offsets = get_end_offsets(counts, &prefix_sums);
             ska_sort(chunk, &mut prefix_sums, &end_offsets, level);
@@ -232,10 +233,13 @@ pub fn regions_sort<T>(
         }
 
         // List out all the operations that need to be executed in this pass
-        for country in 0..256 {
-            let (new_outbounds, mut new_ops) = list_operations(country, outbounds);
-            outbounds = new_outbounds;
-            operations.append(&mut new_ops);
+        {
+            trace_scope!("regions_plan", outbounds = outbounds.len());
+            for country in 0..256 {
+                let (new_outbounds, mut new_ops) = list_operations(country, outbounds);
+                outbounds = new_outbounds;
+                operations.append(&mut new_ops);
+            }
         }
 
         if operations.is_empty() {
@@ -245,6 +249,7 @@ pub fn regions_sort<T>(
         // Execute all operations, swapping the paired slices (inbound/outbound edges)
         let chunk_size = (operations.len() / threads) + 1;
         operations.par_chunks_mut(chunk_size).for_each(|chunk| {
+            trace_scope!("regions_swap", operations = chunk.len());
             for Operation(o, i) in chunk {
                 i.slice.swap_with_slice(o.slice)
             }
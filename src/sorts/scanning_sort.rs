@@ -19,7 +19,7 @@
 //! Once all buckets are completed, and all workers have exited, the sort is finished.
 //!
 //! Thread-local buffers can hold up to 128 values for each radix, or 32,768 values in total. There's one per thread, so the total amount of memory can add up to quite a lot.
-//! 128 values was chosen based upon performance numbers from benchmarking, and is not currently configurable.
+//! 128 values was chosen based upon performance numbers from benchmarking. A tuner can choose a different capacity, and a different number of workers, with `Decision`.
 //!
 //! ## Characteristics
 //!
@@ -43,6 +43,9 @@ use rayon::prelude::*;
 use std::cmp::{max, min};
 use std::sync::Mutex;
 
+/// Number of items each scanner can hold for each radix, unless the tuner chooses otherwise.
+const DEFAULT_BUFFER_CAPACITY: usize = 128;
+
 struct ScannerBucketInner<'a, T> {
     write_head: usize,
     read_head: usize,
@@ -93,12 +96,17 @@ fn scanner_thread<T>(
     scanner_buckets: &[ScannerBucket<T>],
     level: usize,
     scanner_read_size: isize,
+    buffer_capacity: usize,
     uniform_threshold: usize,
 ) where
     T: RadixKey + Copy,
 {
+    trace_scope!("scanner_thread", level = level);
+    #[cfg(feature = "chrome_trace")]
+    let mut lock_failures = 0u64;
+
     let mut stash: Vec<Vec<T>> = Vec::with_capacity(256);
-    stash.resize(256, Vec::with_capacity(128));
+    stash.resize(256, Vec::with_capacity(buffer_capacity));
     let mut finished_count = 0;
     let mut finished_map = [false; 256];
 
@@ -118,6 +126,7 @@ fn scanner_thread<T>(
         };
 
         if !guard.locally_partitioned {
+            trace_scope!("scanner_partition", bucket = m.index, len = m.len);
             guard.locally_partitioned = true;
 
             let index = m.index as u8;
@@ -136,8 +145,16 @@ fn scanner_thread<T>(
 
             let mut guard = match m.inner.try_lock() {
                 Ok(g) => g,
-                Err(_) => continue,
+                Err(_) => {
+                    #[cfg(feature = "chrome_trace")]
+                    {
+                        lock_failures += 1;
+                    }
+
+                    continue;
+                }
             };
+            trace_scope!("scanner_lock", bucket = m.index);
 
             if guard.write_head >= m.len as usize {
                 finished_count += 1;
@@ -219,26 +236,35 @@ fn scanner_thread<T>(
             }
         }
     }
+
+    trace_counter!("scanner_lock_failures", lock_failures);
 }
 
-pub fn scanning_sort<T>(bucket: &mut [T], counts: &[usize; 256], level: usize)
-where
+/// `scanning_sort` sorts `bucket` for `level` with up to `threads` scanners, each buffering up to
+/// `buffer_capacity` items for each radix.
+pub fn scanning_sort<T>(
+    bucket: &mut [T],
+    counts: &[usize; 256],
+    level: usize,
+    threads: usize,
+    buffer_capacity: usize,
+) where
     T: RadixKey + Sized + Send + Copy + Sync,
 {
     let len = bucket.len();
-    let threads = current_num_threads();
     let uniform_threshold = ((len / threads) as f64 * 1.4) as usize;
     let prefix_sums = get_prefix_sums(counts);
     let scanner_buckets = get_scanner_buckets(counts, &prefix_sums, bucket);
     let threads = min(threads, scanner_buckets.len());
     let scaling_factor = max(1, (threads as f32).log2().ceil() as isize) as usize;
-    let scanner_read_size = (32768 / scaling_factor) as isize;
+    let scanner_read_size = max(1, buffer_capacity * 256 / scaling_factor) as isize;
 
     (0..threads).into_par_iter().for_each(|_| {
         scanner_thread(
             &scanner_buckets,
             level,
             scanner_read_size,
+            buffer_capacity,
             uniform_threshold,
         );
     });
@@ -247,12 +273,15 @@ where
 impl<'a> Sorter<'a> {
     // scanning_radix_sort does a parallel MSB-first sort. Following this, depending on the number of
     // elements remaining in each bucket, it will either do an MSB-sort or an LSB-sort, making this
-    // a dynamic hybrid sort.
+    // a dynamic hybrid sort. `threads` and `buffer_capacity` default to a scanner per thread and
+    // DEFAULT_BUFFER_CAPACITY respectively.
     pub(crate) fn scanning_sort_adapter<T>(
         &self,
         bucket: &mut [T],
         counts: &[usize; 256],
         level: usize,
+        threads: Option<usize>,
+        buffer_capacity: Option<usize>,
     ) where
         T: RadixKey + Sized + Send + Copy + Sync,
     {
@@ -260,7 +289,9 @@ impl<'a> Sorter<'a> {
             return;
         }
 
-        scanning_sort(bucket, counts, level);
+        let threads = threads.map_or_else(current_num_threads, |t| max(1, t));
+        let buffer_capacity = buffer_capacity.map_or(DEFAULT_BUFFER_CAPACITY, |c| max(1, c));
+        scanning_sort(bucket, counts, level, threads, buffer_capacity);
 
         if level == 0 {
             return;
@@ -290,7 +321,7 @@ mod tests {
         sort_comparison_suite(shift, |inputs| {
             let (counts, _) = par_get_counts(inputs, T::LEVELS - 1);
 
-            sorter.scanning_sort_adapter(inputs, &counts, T::LEVELS - 1)
+            sorter.scanning_sort_adapter(inputs, &counts, T::LEVELS - 1, None, None)
         });
     }
 
@@ -336,7 +367,26 @@ mod tests {
         validate_u32_patterns(|inputs| {
             let (counts, _) = par_get_counts(inputs, u32::LEVELS - 1);
 
-            sorter.scanning_sort_adapter(inputs, &counts, u32::LEVELS - 1)
+            sorter.scanning_sort_adapter(inputs, &counts, u32::LEVELS - 1, None, None)
         });
     }
+
+    #[test]
+    pub fn test_u32_patterns_with_parameters() {
+        let sorter = Sorter::new(true, &StandardTuner);
+
+        for (threads, buffer_capacity) in [(1, 1), (3, 7), (64, 1024)] {
+            validate_u32_patterns(|inputs| {
+                let (counts, _) = par_get_counts(inputs, u32::LEVELS - 1);
+
+                sorter.scanning_sort_adapter(
+                    inputs,
+                    &counts,
+                    u32::LEVELS - 1,
+                    Some(threads),
+                    Some(buffer_capacity),
+                )
+            });
+        }
+    }
 }
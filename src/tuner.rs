@@ -1,35 +1,445 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+pub use crate::tuners::{
+    CalibratingTuner, CalibrationTable, ParseProfileError, ProfileTuner, RegretReport,
+    RegretSample, RegretSummary,
+};
+
+/// `TuningParams` describes the bucket being sorted, for a tuner to pick an algorithm with. More
+/// fields may be added in future, so build it with `..Default::default()` rather than a full
+/// struct literal.
 #[derive(Clone)]
+#[non_exhaustive]
 pub struct TuningParams {
     pub threads: usize,
     pub level: usize,
     pub total_levels: usize,
     pub input_len: usize,
     pub parent_len: Option<usize>,
+    /// Size in bytes of a single item being sorted, i.e. `size_of::<T>()`
+    pub element_size: usize,
+    /// Number of buckets (out of 256) that contain at least one item for this level
+    pub non_empty_buckets: usize,
+    /// Fraction of the input (0.0 to 1.0) that falls into the largest bucket for this level
+    pub max_bucket_share: f64,
+    /// Shannon entropy of the counts for this level, in bits (0.0 to 8.0). Uniformly random
+    /// data is close to 8.0, while data with very few distinct values is close to 0.0.
+    pub entropy: f64,
+    /// Fraction of adjacent items (0.0 to 1.0) that are already in ascending order for this level.
+    /// When the keys are hinted to be uniform (see `KeyHints::uniform`), this and the other
+    /// measures of order are the values expected of random keys rather than being measured.
+    pub sortedness: f64,
+    /// Number of ascending runs for this level, i.e. one more than the number of adjacent items in
+    /// descending order. This only looks at the current level, so it is a lower bound on the number
+    /// of runs of whole keys.
+    pub runs: usize,
+    /// Fraction of adjacent items (0.0 to 1.0) that are already in descending order for this level.
+    /// Buckets that are entirely in descending order are reversed before the tuner is consulted.
+    pub reverse_sortedness: f64,
+    /// Whether buckets are allowed to be sorted in parallel with each other.
+    /// See `RadixSortBuilder::with_parallel`.
+    pub parallel: bool,
+    /// Maximum number of bytes that may be allocated for temporary buffers, if limited.
+    /// See `RadixSortBuilder::with_memory_budget`.
+    pub memory_budget: Option<usize>,
+    /// Counts for every level from 0 up to and including `level`, indexed by level. This is only
+    /// filled in when `Tuner::count_all_levels` returns true, and is empty otherwise.
+    pub level_counts: Vec<[usize; 256]>,
+    /// What the caller said about the keys ahead of time. See `RadixSortBuilder::with_key_hint`.
+    pub key_hints: KeyHints,
+}
+
+/// `KeyHints` is what the caller said about the keys being sorted ahead of time, gathered from
+/// every `KeyHint` passed to `RadixSortBuilder::with_key_hint`. These are taken on trust rather
+/// than checked.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct KeyHints {
+    /// Highest level at which keys can differ, from `KeyHint::Range`. Every key has the same byte
+    /// at each level above it, so those levels are skipped.
+    pub max_level: Option<usize>,
+    /// Most of the keys are already in order, from `KeyHint::MostlySorted`. See
+    /// `TuningParams::is_nearly_sorted()`.
+    pub mostly_sorted: bool,
+    /// Many of the keys are equal to each other, from `KeyHint::ManyDuplicates`. See
+    /// `TuningParams::is_skewed()`.
+    pub many_duplicates: bool,
+    /// The keys are uniformly random, from `KeyHint::Uniform`. Unless they are also mostly
+    /// sorted, they aren't checked for being in order while counting.
+    pub uniform: bool,
+}
+
+impl KeyHints {
+    /// `skip_order_checks()` returns whether counting can skip looking at the order of the keys,
+    /// as they're random enough that it will never find them already sorted.
+    #[inline]
+    pub(crate) fn skip_order_checks(&self) -> bool {
+        self.uniform && !self.mostly_sorted
+    }
+}
+
+impl Default for TuningParams {
+    /// An empty, single-level input on one thread, with nothing measured about it.
+    fn default() -> Self {
+        Self {
+            threads: 1,
+            level: 0,
+            total_levels: 1,
+            input_len: 0,
+            parent_len: None,
+            element_size: 0,
+            non_empty_buckets: 0,
+            max_bucket_share: 0.0,
+            entropy: 0.0,
+            sortedness: 0.0,
+            runs: 0,
+            reverse_sortedness: 0.0,
+            parallel: false,
+            memory_budget: None,
+            level_counts: Vec::new(),
+            key_hints: KeyHints::default(),
+        }
+    }
 }
 
+impl TuningParams {
+    /// `weighted_len()` is the input length scaled up for items larger than 16 bytes. Moving wide
+    /// items costs proportionally more, so thresholds measured with primitive types can be applied
+    /// to this instead of `input_len`.
+    #[inline]
+    pub fn weighted_len(&self) -> usize {
+        if self.element_size > 16 {
+            self.input_len.saturating_mul(self.element_size / 16)
+        } else {
+            self.input_len
+        }
+    }
+
+    /// `mean_run_len()` is the average length of the ascending runs counted in `runs`. Since
+    /// `runs` is a lower bound, so is this an upper bound.
+    #[inline]
+    pub fn mean_run_len(&self) -> usize {
+        self.input_len / self.runs.max(1)
+    }
+
+    /// `indirect_item_size()` is the size in bytes of the packed key and index that each item is
+    /// replaced with when sorting indirectly, see `Tuner::indirect`.
+    #[inline]
+    pub fn indirect_item_size(&self) -> usize {
+        let key_size = match self.total_levels {
+            0..=4 => 4,
+            5..=8 => 8,
+            _ => 16,
+        };
+        let index_size = if self.input_len <= u32::MAX as usize {
+            4
+        } else {
+            8
+        };
+
+        key_size + index_size
+    }
+
+    /// `fits_indirect_memory_budget()` returns whether the compact array of keys and indexes used
+    /// when sorting indirectly can be allocated within the memory budget.
+    #[inline]
+    pub fn fits_indirect_memory_budget(&self) -> bool {
+        match self.memory_budget {
+            Some(budget) => self.input_len.saturating_mul(self.indirect_item_size()) <= budget,
+            None => true,
+        }
+    }
+
+    /// `is_nearly_sorted()` returns whether most items are already in order for this level, as
+    /// measured by `sortedness` or hinted by `KeyHint::MostlySorted`.
+    #[inline]
+    pub fn is_nearly_sorted(&self) -> bool {
+        self.sortedness >= 0.95 || self.key_hints.mostly_sorted
+    }
+
+    /// `is_skewed()` returns whether items are unevenly spread across the buckets, as measured by
+    /// `max_bucket_share` or hinted by `KeyHint::ManyDuplicates`.
+    #[inline]
+    pub fn is_skewed(&self) -> bool {
+        self.max_bucket_share >= 2.0 / 256.0 || self.key_hints.many_duplicates
+    }
+
+    /// `fits_memory_budget()` returns whether a full-size temporary buffer, as used by the
+    /// out-of-place algorithms, can be allocated within the memory budget.
+    #[inline]
+    pub fn fits_memory_budget(&self) -> bool {
+        match self.memory_budget {
+            Some(budget) => self.input_len.saturating_mul(self.element_size) <= budget,
+            None => true,
+        }
+    }
+}
+
+/// `Algorithm` is a sorting algorithm that a tuner can pick. Every variant exists whichever
+/// features are enabled, so tuners compile the same way everywhere. Multi-threaded algorithms
+/// aren't available without the `multi-threaded` feature (see `is_available()`), and are replaced
+/// with their single-threaded equivalent if picked.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
-#[cfg(feature = "multi-threaded")]
+#[non_exhaustive]
 pub enum Algorithm {
     MtOop,
     MtLsb,
+    MtStableMsb,
     Scanning,
     Recombinating,
     Comparative,
     LrLsb,
     Lsb,
     Regions,
+    Block,
     Ska,
+    AmericanFlag,
+    Merge,
+}
+
+impl Algorithm {
+    /// All algorithms available with the currently enabled features.
+    #[cfg(feature = "multi-threaded")]
+    pub const ALL: &'static [Algorithm] = &[
+        Algorithm::MtOop,
+        Algorithm::MtLsb,
+        Algorithm::MtStableMsb,
+        Algorithm::Scanning,
+        Algorithm::Recombinating,
+        Algorithm::Comparative,
+        Algorithm::LrLsb,
+        Algorithm::Lsb,
+        Algorithm::Regions,
+        Algorithm::Block,
+        Algorithm::Ska,
+        Algorithm::AmericanFlag,
+        Algorithm::Merge,
+    ];
+
+    /// All algorithms available with the currently enabled features.
+    #[cfg(not(feature = "multi-threaded"))]
+    pub const ALL: &'static [Algorithm] = &[
+        Algorithm::Comparative,
+        Algorithm::LrLsb,
+        Algorithm::Lsb,
+        Algorithm::Ska,
+        Algorithm::AmericanFlag,
+        Algorithm::Merge,
+    ];
+
+    /// `is_multi_threaded()` returns whether the algorithm spreads its own work across threads.
+    /// These are used even when buckets aren't sorted in parallel with each other, unless the
+    /// sort is strictly single-threaded (see `RadixSortBuilder::with_strict_single_threaded`).
+    #[inline]
+    pub fn is_multi_threaded(&self) -> bool {
+        matches!(
+            self,
+            Algorithm::MtOop
+                | Algorithm::MtLsb
+                | Algorithm::MtStableMsb
+                | Algorithm::Scanning
+                | Algorithm::Recombinating
+                | Algorithm::Regions
+                | Algorithm::Block
+        )
+    }
+
+    /// `is_stable()` returns whether the algorithm preserves the relative order of items with
+    /// equal keys, so that it can be picked by a tuner whose `stable()` returns true.
+    #[inline]
+    pub fn is_stable(&self) -> bool {
+        matches!(
+            self,
+            Algorithm::Lsb | Algorithm::LrLsb | Algorithm::MtLsb | Algorithm::MtStableMsb
+        )
+    }
+
+    /// `is_available()` returns whether the algorithm can be run with the currently enabled
+    /// features, i.e. it is in `ALL`. Multi-threaded algorithms need the `multi-threaded` feature.
+    #[inline]
+    pub fn is_available(&self) -> bool {
+        cfg!(feature = "multi-threaded") || !self.is_multi_threaded()
+    }
+
+    /// `single_threaded_equivalent()` returns the closest single-threaded algorithm. Out-of-place
+    /// algorithms become `Lsb`, which is stable like `MtLsb` and `MtStableMsb`, and in-place
+    /// algorithms become `Ska`, so that neither stability nor memory use get any worse.
+    /// Single-threaded algorithms are returned unchanged.
+    #[inline]
+    pub fn single_threaded_equivalent(&self) -> Algorithm {
+        match self {
+            Algorithm::MtOop
+            | Algorithm::MtLsb
+            | Algorithm::MtStableMsb
+            | Algorithm::Recombinating => Algorithm::Lsb,
+            Algorithm::Scanning | Algorithm::Regions | Algorithm::Block => Algorithm::Ska,
+            a => *a,
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Error returned when a string does not name an `Algorithm` available with the currently
+/// enabled features.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseAlgorithmError {
+    name: String,
+}
+
+impl fmt::Display for ParseAlgorithmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown or unavailable algorithm: {}", self.name)
+    }
+}
+
+impl Error for ParseAlgorithmError {}
+
+impl FromStr for Algorithm {
+    type Err = ParseAlgorithmError;
+
+    /// Parses an algorithm from its name, e.g. `"Lsb"` or `"Recombinating"`, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim();
+
+        Algorithm::ALL
+            .iter()
+            .find(|a| a.to_string().eq_ignore_ascii_case(name))
+            .copied()
+            .ok_or_else(|| ParseAlgorithmError {
+                name: name.to_string(),
+            })
+    }
 }
 
+/// `Decision` is an algorithm picked by a tuner, along with optional parameters for it (see
+/// `Tuner::decide`). Parameters left as `None` use the sorter's defaults, and parameters that don't
+/// apply to the algorithm are ignored. Values are clamped to what is valid for the chunk being
+/// sorted, so a tuner doesn't need to check them itself.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
-#[cfg(not(feature = "multi-threaded"))]
-pub enum Algorithm {
-    Comparative,
-    LrLsb,
-    Lsb,
-    Ska,
+#[non_exhaustive]
+pub struct Decision {
+    pub algorithm: Algorithm,
+    /// Number of items in each tile for `MtOop`, `MtLsb`, `MtStableMsb`, `Recombinating` and
+    /// `Regions`. By default, chunks of 260,000 items or more are split into a tile per thread of
+    /// at least 30,000 items each, and smaller chunks are a single tile.
+    pub tile_size: Option<usize>,
+    /// Number of pieces to split the chunk into. For the tiled algorithms, this is the number of
+    /// tiles if `tile_size` isn't set. For `Scanning`, this is the number of scanners, which
+    /// defaults to one per thread in the pool. Either way, the pieces are run as tasks on the
+    /// thread pool, so this doesn't limit how many threads are used, and is ignored by the other
+    /// algorithms.
+    pub threads: Option<usize>,
+    /// Number of items each scanner in `Scanning` can hold for each bucket before writing them
+    /// out. Defaults to 128.
+    pub buffer_capacity: Option<usize>,
+    /// Whether `Lsb` and `LrLsb` count the next level while sorting the current one, rather than
+    /// reading it again separately. Defaults to true. This has no effect with write-combining, or
+    /// when every level has already been counted (see `Tuner::count_all_levels`).
+    pub count_while_sorting: Option<bool>,
+    /// Width in bits of the digit `Lsb` and `LrLsb` sort by in each pass. Defaults to 8, a level
+    /// at a time. Any wider value sorts by 16-bit digits, two levels at a time with 65,536
+    /// buckets, which halves the number of passes but only pays off for large inputs. Digits
+    /// are always whole levels, so there are no widths in between. 16-bit passes don't use
+    /// write-combining, left-right writes or counting while sorting.
+    pub digit_bits: Option<usize>,
+}
+
+impl Decision {
+    pub fn new(algorithm: Algorithm) -> Self {
+        Self {
+            algorithm,
+            tile_size: None,
+            threads: None,
+            buffer_capacity: None,
+            count_while_sorting: None,
+            digit_bits: None,
+        }
+    }
+
+    pub fn with_tile_size(mut self, tile_size: usize) -> Self {
+        self.tile_size = Some(tile_size);
+        self
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    pub fn with_buffer_capacity(mut self, buffer_capacity: usize) -> Self {
+        self.buffer_capacity = Some(buffer_capacity);
+        self
+    }
+
+    pub fn with_count_while_sorting(mut self, count_while_sorting: bool) -> Self {
+        self.count_while_sorting = Some(count_while_sorting);
+        self
+    }
+
+    pub fn with_digit_bits(mut self, digit_bits: usize) -> Self {
+        self.digit_bits = Some(digit_bits);
+        self
+    }
+}
+
+impl From<Algorithm> for Decision {
+    fn from(algorithm: Algorithm) -> Self {
+        Decision::new(algorithm)
+    }
 }
 
 pub trait Tuner {
     fn pick_algorithm(&self, p: &TuningParams, counts: &[usize]) -> Algorithm;
+
+    /// `decide()` returns the algorithm to use for the chunk described by `p`, along with any
+    /// parameters for it. By default, this is the algorithm from `pick_algorithm()` with every
+    /// parameter left to the sorter. Tuners that override this should still return the same
+    /// algorithm from `pick_algorithm()`, as tuners that wrap others may only call that.
+    fn decide(&self, p: &TuningParams, counts: &[usize]) -> Decision {
+        self.pick_algorithm(p, counts).into()
+    }
+
+    /// `stable()` returns whether the relative order of items with equal keys must be preserved.
+    /// When true, the sorter's own handling of very small and descending buckets is done in a
+    /// stable way, at a small cost. The tuner must then only pick stable algorithms, i.e. `Lsb`,
+    /// `LrLsb`, `MtLsb` or `MtStableMsb` (see `Algorithm::is_stable`).
+    fn stable(&self) -> bool {
+        false
+    }
+
+    /// `write_combining()` returns whether `algorithm`, as picked for `p`, should stage items in
+    /// small per-bucket buffers rather than scattering them directly into the output. This only
+    /// affects `Lsb`, `LrLsb`, `MtLsb` and `Recombinating`. It is usually faster once the input is
+    /// much larger than the CPU cache, and slower for smaller inputs.
+    fn write_combining(&self, _p: &TuningParams, _algorithm: Algorithm) -> bool {
+        false
+    }
+
+    /// `count_all_levels()` returns whether every level of the chunk described by `p` should be
+    /// counted in a single pass before an algorithm is picked. The counts are then available in
+    /// `TuningParams::level_counts`, and `Lsb`, `LrLsb` and `MtLsb` use them to skip levels where
+    /// every item is in the same bucket without moving any data. This costs an extra read of the
+    /// chunk, which is wasted if the tuner then picks an MSB algorithm.
+    fn count_all_levels(&self, _p: &TuningParams) -> bool {
+        false
+    }
+
+    /// `indirect()` returns whether the whole input described by `p` should be sorted
+    /// indirectly. This sorts a compact array of keys and indexes with this tuner, then moves each
+    /// item into place, which saves memory traffic when items are much wider than their keys.
+    /// The input hasn't been read yet, so only `input_len`, `element_size`, `total_levels`,
+    /// `threads`, `parallel`, `memory_budget` and `key_hints` are set. Keys with more than 16
+    /// levels, and inputs whose compact array doesn't fit the memory budget (see
+    /// `TuningParams::fits_indirect_memory_budget()`), are always sorted directly. See
+    /// `RadixSortBuilder::indirect`.
+    fn indirect(&self, _p: &TuningParams) -> bool {
+        false
+    }
 }
@@ -0,0 +1,490 @@
+//! `CalibratingTuner` is a tuner that measures each algorithm in the current environment, rather
+//! than relying upon thresholds measured on somebody else's machine.
+//!
+//! Calibration sorts synthetic `u32` inputs of several sizes using every available algorithm, with
+//! both uniform and skewed distributions. Each input is measured both as the last level to be
+//! sorted, and as the top level of a full sort where the remaining levels are tuned by the results
+//! gathered so far (sizes are measured smallest first). The fastest algorithm for each measurement
+//! is recorded, and crossover points are placed at the geometric mean of neighbouring sizes.
+//!
+//! Algorithms that can't win are skipped: `Comparative` is only measured for inputs of up to
+//! 5,000 items, and `Merge` is never measured, as it relies on sorted runs that the synthetic
+//! inputs don't have. Even so, every other algorithm is sorted 3 times for each of 4 measurements
+//! at each size, up to 2,000,000 items by default. This takes several seconds, and more on slow
+//! machines.
+//!
+//! Calibration runs on first use, inside whichever sort first consults the tuner, unless
+//! `calibrate()` has already been called. Call `calibrate()` ahead of time, or save the resulting
+//! `CalibrationTable` and `load()` it on startup so production processes don't pay that cost. Use
+//! `with_sizes()` to calibrate for smaller inputs only.
+//!
+//! CalibratingTuner algorithm choice is:
+//!  * measured in the current environment
+//!  * multi-threaded (when the `multi-threaded` feature is enabled)
+//!  * aware of basic count distributions
+//!  * dynamic msb / lsb
+
+use crate::sorter::Sorter;
+use crate::tuner::{Algorithm, Tuner, TuningParams};
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Write};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+const DEFAULT_SIZES: [usize; 6] = [1_000, 5_000, 20_000, 100_000, 400_000, 2_000_000];
+const RUNS: usize = 3;
+const MAX_COMPARATIVE_LEN: usize = 5_000;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct CalibrationRow {
+    skewed: bool,
+    last_level: bool,
+    max_len: usize,
+    algorithm: Algorithm,
+}
+
+/// `CalibrationTable` holds the algorithm crossover points measured by `CalibratingTuner`. It can
+/// be saved and loaded again later, and can also be used directly as a `Tuner`.
+///
+/// ```
+/// use rdst::RadixSort;
+/// use rdst::tuner::CalibratingTuner;
+///
+/// let tuner = CalibratingTuner::new().with_sizes(&[1_000, 10_000]);
+///
+/// let mut saved = Vec::new();
+/// tuner.calibrate().save(&mut saved).unwrap();
+///
+/// // Later, perhaps in another process...
+/// let tuner = CalibratingTuner::load(&saved[..]).unwrap();
+///
+/// let mut data: Vec<usize> = vec![5, 22, 3, 7, 9];
+/// data.radix_sort_builder().with_tuner(&tuner).sort();
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct CalibrationTable {
+    rows: Vec<CalibrationRow>,
+}
+
+impl CalibrationTable {
+    /// `measure()` benchmarks every available algorithm at each of the given input sizes and
+    /// builds a table from the results.
+    pub fn measure(sizes: &[usize]) -> Self {
+        let mut sizes: Vec<usize> = sizes.iter().copied().filter(|s| *s > 128).collect();
+        sizes.sort_unstable();
+        sizes.dedup();
+
+        let mut samples = Vec::new();
+        let mut table = CalibrationTable::default();
+
+        for size in sizes {
+            let uniform = gen_calibration_input(size, 0);
+            let skewed = gen_calibration_input(size, size / 2);
+
+            for (is_skewed, input) in [(false, &uniform), (true, &skewed)] {
+                for last_level in [true, false] {
+                    let level = if last_level { 0 } else { 3 };
+                    let algorithm = fastest_algorithm(&table, input, level);
+
+                    samples.push((is_skewed, last_level, size, algorithm));
+                }
+            }
+
+            // Rebuild the table so larger sizes can use these results for deeper levels
+            table = CalibrationTable::from_samples(&samples);
+        }
+
+        table
+    }
+
+    fn from_samples(samples: &[(bool, bool, usize, Algorithm)]) -> Self {
+        let mut rows = Vec::new();
+
+        for skewed in [false, true] {
+            for last_level in [true, false] {
+                let mut group: Vec<(usize, Algorithm)> = samples
+                    .iter()
+                    .filter(|s| s.0 == skewed && s.1 == last_level)
+                    .map(|s| (s.2, s.3))
+                    .collect();
+                group.sort_unstable_by_key(|s| s.0);
+
+                for (i, (size, algorithm)) in group.iter().enumerate() {
+                    let max_len = match group.get(i + 1) {
+                        Some((next, _)) => ((*size as f64) * (*next as f64)).sqrt() as usize,
+                        None => usize::MAX,
+                    };
+
+                    match rows.last_mut() {
+                        Some(CalibrationRow {
+                            skewed: s,
+                            last_level: l,
+                            max_len: m,
+                            algorithm: a,
+                        }) if *s == skewed && *l == last_level && *a == *algorithm => {
+                            *m = max_len;
+                        }
+                        _ => rows.push(CalibrationRow {
+                            skewed,
+                            last_level,
+                            max_len,
+                            algorithm: *algorithm,
+                        }),
+                    }
+                }
+            }
+        }
+
+        Self { rows }
+    }
+
+    /// `save()` writes the table in a simple line-based text format that can be read back
+    /// with `load()`.
+    pub fn save<W: Write>(&self, mut w: W) -> std::io::Result<()> {
+        writeln!(w, "# rdst calibration table")?;
+        writeln!(w, "# distribution level max_len algorithm")?;
+
+        for row in self.rows.iter() {
+            let max_len = if row.max_len == usize::MAX {
+                "max".to_string()
+            } else {
+                row.max_len.to_string()
+            };
+
+            writeln!(
+                w,
+                "{} {} {} {}",
+                if row.skewed { "skewed" } else { "uniform" },
+                if row.last_level { "last" } else { "upper" },
+                max_len,
+                row.algorithm
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// `load()` reads a table previously written by `save()`. Tables that name an algorithm not
+    /// available with the currently enabled features are rejected.
+    pub fn load<R: Read>(r: R) -> std::io::Result<Self> {
+        let mut rows = Vec::new();
+
+        for (i, line) in BufReader::new(r).lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = |msg: String| {
+                Error::new(ErrorKind::InvalidData, format!("line {}: {}", i + 1, msg))
+            };
+            let fields: Vec<&str> = line.split_whitespace().collect();
+
+            if fields.len() != 4 {
+                return Err(invalid(format!(
+                    "expected 4 fields, found {}",
+                    fields.len()
+                )));
+            }
+
+            let skewed = match fields[0] {
+                "uniform" => false,
+                "skewed" => true,
+                f => return Err(invalid(format!("unknown distribution: {}", f))),
+            };
+
+            let last_level = match fields[1] {
+                "last" => true,
+                "upper" => false,
+                f => return Err(invalid(format!("unknown level: {}", f))),
+            };
+
+            let max_len = match fields[2] {
+                "max" => usize::MAX,
+                f => f
+                    .parse()
+                    .map_err(|_| invalid(format!("invalid max_len: {}", f)))?,
+            };
+
+            let algorithm = fields[3].parse().map_err(|e| invalid(format!("{}", e)))?;
+
+            rows.push(CalibrationRow {
+                skewed,
+                last_level,
+                max_len,
+                algorithm,
+            });
+        }
+
+        Ok(Self { rows })
+    }
+}
+
+impl Tuner for CalibrationTable {
+    #[inline]
+    fn pick_algorithm(&self, p: &TuningParams, _counts: &[usize]) -> Algorithm {
+        if p.input_len <= 128 {
+            return Algorithm::Comparative;
+        }
+
+        let skewed = p.max_bucket_share >= 2.0 / 256.0;
+        let last_level = p.level == 0;
+        let len = p.weighted_len();
+        let mut fallback = None;
+
+        for row in self.rows.iter() {
+            if row.skewed != skewed || row.last_level != last_level {
+                continue;
+            }
+
+            if len <= row.max_len {
+                return row.algorithm;
+            }
+
+            fallback = Some(row.algorithm);
+        }
+
+        fallback.unwrap_or(Algorithm::Lsb)
+    }
+}
+
+/// `CalibratingTuner` picks algorithms based upon a `CalibrationTable` measured in the current
+/// environment. See the module documentation for details.
+///
+/// ```
+/// use rdst::RadixSort;
+/// use rdst::tuner::CalibratingTuner;
+///
+/// // Smaller sizes than the defaults, to keep this example quick
+/// let tuner = CalibratingTuner::new().with_sizes(&[1_000, 10_000]);
+/// tuner.calibrate();
+///
+/// let mut data: Vec<usize> = vec![5, 22, 3, 7, 9];
+/// data.radix_sort_builder().with_tuner(&tuner).sort();
+/// ```
+pub struct CalibratingTuner {
+    sizes: Vec<usize>,
+    table: OnceLock<CalibrationTable>,
+}
+
+impl CalibratingTuner {
+    pub fn new() -> Self {
+        Self {
+            sizes: DEFAULT_SIZES.to_vec(),
+            table: OnceLock::new(),
+        }
+    }
+
+    /// `from_table()` creates a tuner from a previously measured table, skipping calibration.
+    pub fn from_table(table: CalibrationTable) -> Self {
+        Self {
+            sizes: DEFAULT_SIZES.to_vec(),
+            table: OnceLock::from(table),
+        }
+    }
+
+    /// `load()` creates a tuner from a table previously written by `CalibrationTable::save()`.
+    pub fn load<R: Read>(r: R) -> std::io::Result<Self> {
+        Ok(Self::from_table(CalibrationTable::load(r)?))
+    }
+
+    /// `with_sizes()` sets the input sizes to measure when calibrating. Larger sizes make
+    /// calibration slower, but sizes beyond the largest measured will all be treated the same.
+    pub fn with_sizes(mut self, sizes: &[usize]) -> Self {
+        self.sizes = sizes.to_vec();
+
+        self
+    }
+
+    /// `calibrate()` measures each algorithm if that hasn't already happened, and returns the
+    /// resulting table.
+    pub fn calibrate(&self) -> &CalibrationTable {
+        self.table.get_or_init(|| calibrate_isolated(&self.sizes))
+    }
+
+    /// `table()` returns the calibration table, if calibration has already happened.
+    pub fn table(&self) -> Option<&CalibrationTable> {
+        self.table.get()
+    }
+}
+
+impl Default for CalibratingTuner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tuner for CalibratingTuner {
+    #[inline]
+    fn pick_algorithm(&self, p: &TuningParams, counts: &[usize]) -> Algorithm {
+        self.calibrate().pick_algorithm(p, counts)
+    }
+}
+
+/// calibrate_isolated runs calibration on its own thread pool. Calibration may be triggered from
+/// within a sort running on the global pool, and the threads of that pool may be blocked waiting
+/// for calibration to finish.
+#[cfg(feature = "multi-threaded")]
+fn calibrate_isolated(sizes: &[usize]) -> CalibrationTable {
+    let threads = rayon::current_num_threads();
+
+    std::thread::scope(|s| {
+        s.spawn(
+            || match rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
+                Ok(pool) => pool.install(|| CalibrationTable::measure(sizes)),
+                Err(_) => CalibrationTable::measure(sizes),
+            },
+        )
+        .join()
+        .unwrap()
+    })
+}
+
+#[cfg(not(feature = "multi-threaded"))]
+fn calibrate_isolated(sizes: &[usize]) -> CalibrationTable {
+    CalibrationTable::measure(sizes)
+}
+
+fn fastest_algorithm(table: &CalibrationTable, input: &[u32], level: usize) -> Algorithm {
+    #[cfg(feature = "multi-threaded")]
+    let (threads, multi_threaded) = (rayon::current_num_threads(), true);
+    #[cfg(not(feature = "multi-threaded"))]
+    let (threads, multi_threaded) = (1, false);
+
+    let sorter = Sorter::new(multi_threaded, table);
+    let mut best = (Duration::MAX, Algorithm::Lsb);
+
+    for algorithm in Algorithm::ALL.iter().copied() {
+        if !worth_measuring(algorithm, input.len()) {
+            continue;
+        }
+
+        let mut elapsed = Duration::MAX;
+
+        for _ in 0..RUNS {
+            let mut data = input.to_vec();
+            let start = Instant::now();
+            sorter.sort_with_decision(&mut data, level, threads, algorithm.into());
+            elapsed = elapsed.min(start.elapsed());
+        }
+
+        if elapsed < best.0 {
+            best = (elapsed, algorithm);
+        }
+    }
+
+    best.1
+}
+
+/// worth_measuring returns whether `algorithm` could plausibly be the fastest for `len` random
+/// items. Skipping the rest keeps calibration from spending most of its time on sure losers.
+fn worth_measuring(algorithm: Algorithm, len: usize) -> bool {
+    match algorithm {
+        Algorithm::Comparative => len <= MAX_COMPARATIVE_LEN,
+        // Merging only pays off for inputs made of long sorted runs
+        Algorithm::Merge => false,
+        _ => true,
+    }
+}
+
+/// gen_calibration_input generates pseudo-random values, where the first `skewed` values are
+/// given the same first and last byte so they fall in the same bucket at either end.
+fn gen_calibration_input(n: usize, skewed: usize) -> Vec<u32> {
+    let mut state = 0x9E37_79B9_7F4A_7C15u64;
+
+    (0..n)
+        .map(|i| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let v = (state >> 16) as u32;
+
+            if i < skewed {
+                v & 0x00FF_FF00
+            } else {
+                v
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tuner::{Algorithm, Tuner, TuningParams};
+    use crate::tuners::calibrating_tuner::worth_measuring;
+    use crate::tuners::{CalibratingTuner, CalibrationTable};
+    use crate::utils::test_utils::validate_u32_patterns;
+    use crate::RadixSort;
+
+    #[test]
+    pub fn test_calibration_save_load() {
+        let table = CalibrationTable::measure(&[500, 5_000]);
+
+        let mut saved = Vec::new();
+        table.save(&mut saved).unwrap();
+        let loaded = CalibrationTable::load(&saved[..]).unwrap();
+
+        assert_eq!(table, loaded);
+        assert!(!table.rows.is_empty());
+    }
+
+    #[test]
+    pub fn test_load_rejects_invalid_tables() {
+        assert!(CalibrationTable::load(&b"uniform last max Lsb\n"[..]).is_ok());
+        assert!(CalibrationTable::load(&b"uniform last max NotAnAlgorithm\n"[..]).is_err());
+        assert!(CalibrationTable::load(&b"uniform last 12x Lsb\n"[..]).is_err());
+        assert!(CalibrationTable::load(&b"sideways last max Lsb\n"[..]).is_err());
+        assert!(CalibrationTable::load(&b"uniform last max\n"[..]).is_err());
+    }
+
+    #[test]
+    pub fn test_table_crossovers() {
+        let table = CalibrationTable::load(
+            &b"# comment\nuniform upper 1000 Lsb\nuniform upper max Ska\nskewed upper max LrLsb\n"
+                [..],
+        )
+        .unwrap();
+        let mut p = TuningParams {
+            threads: 1,
+            level: 3,
+            total_levels: 4,
+            input_len: 500,
+            element_size: 4,
+            non_empty_buckets: 256,
+            max_bucket_share: 1.0 / 256.0,
+            entropy: 8.0,
+            sortedness: 0.5,
+            runs: 250,
+            ..Default::default()
+        };
+
+        assert_eq!(table.pick_algorithm(&p, &[]), Algorithm::Lsb);
+        p.input_len = 1_001;
+        assert_eq!(table.pick_algorithm(&p, &[]), Algorithm::Ska);
+        p.max_bucket_share = 0.5;
+        assert_eq!(table.pick_algorithm(&p, &[]), Algorithm::LrLsb);
+        p.level = 0;
+        assert_eq!(table.pick_algorithm(&p, &[]), Algorithm::Lsb);
+    }
+
+    #[test]
+    pub fn test_skips_sure_losers() {
+        assert!(worth_measuring(Algorithm::Comparative, 5_000));
+        assert!(!worth_measuring(Algorithm::Comparative, 5_001));
+        assert!(!worth_measuring(Algorithm::Merge, 1_000));
+        assert!(worth_measuring(Algorithm::Lsb, 10_000_000));
+    }
+
+    #[test]
+    pub fn test_u32_patterns() {
+        let tuner = CalibratingTuner::new().with_sizes(&[500, 5_000, 50_000]);
+        assert!(tuner.table().is_none());
+
+        validate_u32_patterns(|inputs| {
+            inputs.radix_sort_builder().with_tuner(&tuner).sort();
+        });
+
+        assert!(tuner.table().is_some());
+    }
+}
@@ -6,38 +6,101 @@
 //!  * multi-threaded
 //!  * low-memory / in-place algorithms preferred
 //!  * aware of basic count distributions
+//!  * aware of element size and memory budget
+//!  * aware of the mostly sorted and many duplicates key hints
 //!  * dynamic msb / lsb
+//!  * american flag sort for in-place sorts of large items
 
 use crate::tuner::{Algorithm, Tuner, TuningParams};
 
 pub struct LowMemoryTuner;
 impl Tuner for LowMemoryTuner {
     #[inline]
-    fn pick_algorithm(&self, p: &TuningParams, counts: &[usize]) -> Algorithm {
+    fn pick_algorithm(&self, p: &TuningParams, _counts: &[usize]) -> Algorithm {
         if p.input_len <= 128 {
             return Algorithm::Comparative;
         }
 
-        if p.input_len >= 5_000 {
-            let distribution_threshold = (p.input_len / 256) * 2;
-
-            for c in counts {
-                if *c >= distribution_threshold {
-                    return match p.input_len {
-                        0..=50_000 => Algorithm::LrLsb,
-                        50_001..=1_000_000 => Algorithm::Ska,
-                        1_000_001..=usize::MAX => Algorithm::Regions,
-                        _ => Algorithm::LrLsb,
-                    };
-                }
-            }
+        let len = p.weighted_len();
+        // Inputs of more than 50,000 items are always sorted in place, and so are smaller ones that
+        // are hinted to be nearly sorted, as there is very little for Ska to move
+        let in_place_only = !p.fits_memory_budget() || p.key_hints.mostly_sorted;
+
+        // Holding one item aside rather than swapping writes each item once, which pays off once
+        // moving an item is expensive
+        let in_place = if p.element_size >= 32 {
+            Algorithm::AmericanFlag
+        } else {
+            Algorithm::Ska
+        };
+
+        if p.input_len >= 5_000 && p.is_skewed() {
+            return match len {
+                0..=50_000 if !in_place_only => Algorithm::LrLsb,
+                0..=1_000_000 => in_place,
+                1_000_001..=5_000_000 => Algorithm::Regions,
+                _ => Algorithm::Block,
+            };
         }
 
-        match p.input_len {
-            0..=50_000 => Algorithm::Lsb,
-            50_001..=1_000_000 => Algorithm::Ska,
-            1_000_001..=usize::MAX => Algorithm::Regions,
-            _ => Algorithm::Lsb,
+        match len {
+            0..=50_000 if !in_place_only => Algorithm::Lsb,
+            0..=1_000_000 => in_place,
+            1_000_001..=5_000_000 => Algorithm::Regions,
+            _ => Algorithm::Block,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::tuner::{Algorithm, Tuner, TuningParams};
+    use crate::tuners::LowMemoryTuner;
+
+    #[test]
+    pub fn test_picks_american_flag_for_large_items() {
+        let mut p = TuningParams {
+            level: 3,
+            total_levels: 4,
+            input_len: 100_000,
+            element_size: 64,
+            non_empty_buckets: 256,
+            max_bucket_share: 1.0 / 256.0,
+            entropy: 8.0,
+            sortedness: 0.5,
+            runs: 50_000,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            LowMemoryTuner.pick_algorithm(&p, &[]),
+            Algorithm::AmericanFlag
+        );
+        p.element_size = 8;
+        assert_eq!(LowMemoryTuner.pick_algorithm(&p, &[]), Algorithm::Ska);
+    }
+
+    #[test]
+    pub fn test_key_hints() {
+        let mut p = TuningParams {
+            level: 3,
+            total_levels: 4,
+            input_len: 30_000,
+            element_size: 4,
+            non_empty_buckets: 256,
+            max_bucket_share: 1.0 / 256.0,
+            entropy: 8.0,
+            sortedness: 0.5,
+            runs: 15_000,
+            ..Default::default()
+        };
+
+        assert_eq!(LowMemoryTuner.pick_algorithm(&p, &[]), Algorithm::Lsb);
+
+        p.key_hints.many_duplicates = true;
+        assert_eq!(LowMemoryTuner.pick_algorithm(&p, &[]), Algorithm::LrLsb);
+
+        p.key_hints.mostly_sorted = true;
+        assert_eq!(LowMemoryTuner.pick_algorithm(&p, &[]), Algorithm::Ska);
+    }
+}
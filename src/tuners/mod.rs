@@ -1,11 +1,18 @@
+mod calibrating_tuner;
 #[cfg(feature = "multi-threaded")]
 mod low_memory_tuner;
+mod profile_tuner;
+mod regret;
 mod single_threaded_tuner;
 #[cfg(feature = "multi-threaded")]
 mod standard_tuner;
 
+pub use calibrating_tuner::{CalibratingTuner, CalibrationTable};
 #[cfg(feature = "multi-threaded")]
 pub use low_memory_tuner::LowMemoryTuner;
+pub use profile_tuner::{ParseProfileError, ProfileTuner};
+pub(crate) use regret::RegretRecorder;
+pub use regret::{RegretReport, RegretSample, RegretSummary};
 pub use single_threaded_tuner::SingleThreadedTuner;
 #[cfg(feature = "multi-threaded")]
 pub use standard_tuner::StandardTuner;
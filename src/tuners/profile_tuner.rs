@@ -0,0 +1,506 @@
+//! `ProfileTuner` is a tuner driven by a declarative rule table, so tuning can be shipped per
+//! deployment target without recompiling.
+//!
+//! A profile is a list of rules, one per line. Each rule is a set of conditions followed by `->`
+//! and the algorithm to use when all of those conditions match. Rules are checked in order and the
+//! first match wins, so the final rule must have no conditions to act as a catch-all. Anything
+//! after a `#` is a comment.
+//!
+//! ```text
+//! input_len=..128 -> Comparative
+//! skewed=true depth=0 len=..200000 -> LrLsb
+//! depth=1.. len=200001..800000 -> Ska
+//! -> Lsb
+//! ```
+//!
+//! Ranges are inclusive at both ends and either end may be omitted. Available conditions are:
+//!  * `len` - `TuningParams::weighted_len()`
+//!  * `input_len` - `TuningParams::input_len`
+//!  * `depth` - number of levels above this one, i.e. `0` at the top level
+//!  * `elem_size` - `TuningParams::element_size`
+//!  * `sortedness` - `TuningParams::sortedness`
+//!  * `runs` - `TuningParams::runs`
+//!  * `run_len` - `TuningParams::mean_run_len()`
+//!  * `skewed` - `true` when the largest bucket holds at least 2/256 of the input
+//!  * `fits_budget` - `TuningParams::fits_memory_budget()`
+//!
+//! Profiles naming an algorithm that isn't available with the currently enabled features are
+//! rejected when loaded.
+//!
+//! ProfileTuner algorithm choice is:
+//!  * defined at runtime
+//!  * multi-threaded (if the profile chooses multi-threaded algorithms)
+//!  * dynamic msb / lsb
+
+use crate::tuner::{Algorithm, Tuner, TuningParams};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Bounds<T> {
+    min: Option<T>,
+    max: Option<T>,
+}
+
+impl<T: PartialOrd + Copy> Bounds<T> {
+    #[inline]
+    fn contains(&self, v: T) -> bool {
+        self.min.is_none_or(|min| v >= min) && self.max.is_none_or(|max| v <= max)
+    }
+}
+
+impl<T: FromStr + Copy> FromStr for Bounds<T> {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |s: &str| -> Result<Option<T>, ()> {
+            if s.is_empty() {
+                Ok(None)
+            } else {
+                s.parse().map(Some).map_err(|_| ())
+            }
+        };
+
+        match s.split_once("..") {
+            Some((min, max)) => Ok(Self {
+                min: parse(min)?,
+                max: parse(max)?,
+            }),
+            None if !s.is_empty() => {
+                let v = parse(s)?;
+
+                Ok(Self { min: v, max: v })
+            }
+            None => Err(()),
+        }
+    }
+}
+
+impl<T: fmt::Display + PartialEq> fmt::Display for Bounds<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.min, &self.max) {
+            (Some(min), Some(max)) if min == max => write!(f, "{}", min),
+            (min, max) => {
+                if let Some(min) = min {
+                    write!(f, "{}", min)?;
+                }
+                write!(f, "..")?;
+                if let Some(max) = max {
+                    write!(f, "{}", max)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    Len(Bounds<usize>),
+    InputLen(Bounds<usize>),
+    Depth(Bounds<usize>),
+    ElemSize(Bounds<usize>),
+    Sortedness(Bounds<f64>),
+    Runs(Bounds<usize>),
+    RunLen(Bounds<usize>),
+    Skewed(bool),
+    FitsBudget(bool),
+}
+
+impl Condition {
+    fn key(&self) -> &'static str {
+        match self {
+            Condition::Len(_) => "len",
+            Condition::InputLen(_) => "input_len",
+            Condition::Depth(_) => "depth",
+            Condition::ElemSize(_) => "elem_size",
+            Condition::Sortedness(_) => "sortedness",
+            Condition::Runs(_) => "runs",
+            Condition::RunLen(_) => "run_len",
+            Condition::Skewed(_) => "skewed",
+            Condition::FitsBudget(_) => "fits_budget",
+        }
+    }
+
+    #[inline]
+    fn matches(&self, p: &TuningParams) -> bool {
+        match self {
+            Condition::Len(b) => b.contains(p.weighted_len()),
+            Condition::InputLen(b) => b.contains(p.input_len),
+            Condition::Depth(b) => b.contains(p.total_levels - p.level - 1),
+            Condition::ElemSize(b) => b.contains(p.element_size),
+            Condition::Sortedness(b) => b.contains(p.sortedness),
+            Condition::Runs(b) => b.contains(p.runs),
+            Condition::RunLen(b) => b.contains(p.mean_run_len()),
+            Condition::Skewed(v) => (p.max_bucket_share >= 2.0 / 256.0) == *v,
+            Condition::FitsBudget(v) => p.fits_memory_budget() == *v,
+        }
+    }
+
+    fn parse(key: &str, value: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid value for {}: {}", key, value);
+
+        let condition = match key {
+            "len" => Condition::Len(value.parse().map_err(|_| invalid())?),
+            "input_len" => Condition::InputLen(value.parse().map_err(|_| invalid())?),
+            "depth" => Condition::Depth(value.parse().map_err(|_| invalid())?),
+            "elem_size" => Condition::ElemSize(value.parse().map_err(|_| invalid())?),
+            "sortedness" => Condition::Sortedness(value.parse().map_err(|_| invalid())?),
+            "runs" => Condition::Runs(value.parse().map_err(|_| invalid())?),
+            "run_len" => Condition::RunLen(value.parse().map_err(|_| invalid())?),
+            "skewed" => Condition::Skewed(value.parse().map_err(|_| invalid())?),
+            "fits_budget" => Condition::FitsBudget(value.parse().map_err(|_| invalid())?),
+            _ => return Err(format!("unknown condition: {}", key)),
+        };
+
+        Ok(condition)
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}=", self.key())?;
+
+        match self {
+            Condition::Len(b)
+            | Condition::InputLen(b)
+            | Condition::Depth(b)
+            | Condition::ElemSize(b)
+            | Condition::Runs(b)
+            | Condition::RunLen(b) => write!(f, "{}", b),
+            Condition::Sortedness(b) => write!(f, "{}", b),
+            Condition::Skewed(v) | Condition::FitsBudget(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    conditions: Vec<Condition>,
+    algorithm: Algorithm,
+}
+
+/// Error returned when a profile cannot be parsed, or names an algorithm that isn't available
+/// with the currently enabled features.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseProfileError {
+    line: usize,
+    message: String,
+}
+
+impl fmt::Display for ParseProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid profile at line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for ParseProfileError {}
+
+/// `ProfileTuner` picks algorithms using a rule table loaded at runtime. See the module
+/// documentation for the profile format.
+///
+/// ```
+/// use rdst::RadixSort;
+/// use rdst::tuner::ProfileTuner;
+///
+/// let tuner: ProfileTuner = "
+///     input_len=..128 -> Comparative
+///     len=..200000 -> Lsb
+///     -> Ska
+/// ".parse().unwrap();
+///
+/// let mut data: Vec<usize> = vec![5, 22, 3, 7, 9];
+/// data.radix_sort_builder().with_tuner(&tuner).sort();
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileTuner {
+    rules: Vec<Rule>,
+}
+
+impl ProfileTuner {
+    /// `standard()` returns a profile with the same rules for picking an algorithm as the default
+    /// `StandardTuner`. Only `pick_algorithm()` is covered by a profile, so this never uses
+    /// write-combining or indirect sorting, and ignores key hints.
+    #[cfg(feature = "multi-threaded")]
+    pub fn standard() -> Self {
+        include_str!("profiles/standard.profile")
+            .parse()
+            .expect("built-in standard profile is valid")
+    }
+
+    /// `low_memory()` returns a profile with the same rules for picking an algorithm as
+    /// `LowMemoryTuner`. Key hints are ignored.
+    #[cfg(feature = "multi-threaded")]
+    pub fn low_memory() -> Self {
+        include_str!("profiles/low_memory.profile")
+            .parse()
+            .expect("built-in low_memory profile is valid")
+    }
+
+    /// `single_threaded()` returns a profile with the same rules for picking an algorithm as
+    /// `SingleThreadedTuner`. This never uses write-combining, and ignores key hints.
+    pub fn single_threaded() -> Self {
+        include_str!("profiles/single_threaded.profile")
+            .parse()
+            .expect("built-in single_threaded profile is valid")
+    }
+
+    /// `load()` reads a profile from `r`. Invalid profiles result in an error of kind
+    /// `InvalidData`.
+    pub fn load<R: Read>(mut r: R) -> std::io::Result<Self> {
+        let mut profile = String::new();
+        r.read_to_string(&mut profile)?;
+
+        profile
+            .parse()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    /// `from_file()` reads a profile from the file at `path`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        Self::load(File::open(path)?)
+    }
+}
+
+impl FromStr for ProfileTuner {
+    type Err = ParseProfileError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules = Vec::new();
+        let mut last_line = 0;
+
+        for (i, line) in s.lines().enumerate() {
+            let err = |message: String| ParseProfileError {
+                line: i + 1,
+                message,
+            };
+
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (conditions, algorithm) = line
+                .split_once("->")
+                .ok_or_else(|| err("expected `<conditions> -> <algorithm>`".to_string()))?;
+
+            let algorithm: Algorithm = algorithm.parse().map_err(|e| err(format!("{}", e)))?;
+            let mut parsed: Vec<Condition> = Vec::new();
+
+            for condition in conditions.split_whitespace() {
+                let (key, value) = condition
+                    .split_once('=')
+                    .ok_or_else(|| err(format!("expected `key=value`, found {}", condition)))?;
+                let condition = Condition::parse(key, value).map_err(err)?;
+
+                if parsed.iter().any(|c| c.key() == condition.key()) {
+                    return Err(err(format!("duplicate condition: {}", key)));
+                }
+
+                parsed.push(condition);
+            }
+
+            rules.push(Rule {
+                conditions: parsed,
+                algorithm,
+            });
+            last_line = i + 1;
+        }
+
+        match rules.last() {
+            Some(rule) if rule.conditions.is_empty() => Ok(Self { rules }),
+            _ => Err(ParseProfileError {
+                line: last_line,
+                message: "profile must end with a rule without conditions".to_string(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for ProfileTuner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for rule in self.rules.iter() {
+            for condition in rule.conditions.iter() {
+                write!(f, "{} ", condition)?;
+            }
+
+            writeln!(f, "-> {}", rule.algorithm)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Tuner for ProfileTuner {
+    #[inline]
+    fn pick_algorithm(&self, p: &TuningParams, _counts: &[usize]) -> Algorithm {
+        self.rules
+            .iter()
+            .find(|r| r.conditions.iter().all(|c| c.matches(p)))
+            .map(|r| r.algorithm)
+            // Profiles always end with a catch-all, so this is unreachable
+            .unwrap_or(Algorithm::Lsb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tuner::{Algorithm, Tuner, TuningParams};
+    #[cfg(feature = "multi-threaded")]
+    use crate::tuners::{LowMemoryTuner, StandardTuner};
+    use crate::tuners::{ProfileTuner, SingleThreadedTuner};
+    use crate::utils::test_utils::validate_u32_patterns;
+    use crate::RadixSort;
+
+    fn assert_equivalent(profile: &ProfileTuner, tuner: &dyn Tuner) {
+        let lens = [
+            0, 128, 129, 4_999, 5_000, 50_000, 50_001, 100_000, 100_001, 150_000, 150_001, 200_000,
+            200_001, 260_000, 260_001, 350_000, 350_001, 800_000, 800_001, 1_000_000, 1_000_001,
+            4_000_000, 4_000_001, 5_000_000, 5_000_001, 50_000_000, 50_000_001,
+        ];
+
+        for input_len in lens {
+            for element_size in [4, 8, 16, 64] {
+                for level in 0..4 {
+                    for max_bucket_share in [1.0 / 256.0, 0.5] {
+                        for (sortedness, runs) in [
+                            (0.5, input_len / 2 + 1),
+                            (0.95, input_len / 20 + 1),
+                            (1.0, 2),
+                            (1.0, input_len / 20_000 + 1),
+                            (1.0, input_len / 100_000 + 1),
+                        ] {
+                            for memory_budget in [None, Some(1_000_000)] {
+                                let p = TuningParams {
+                                    threads: 8,
+                                    level,
+                                    total_levels: 4,
+                                    input_len,
+                                    element_size,
+                                    non_empty_buckets: 256,
+                                    max_bucket_share,
+                                    entropy: 8.0,
+                                    sortedness,
+                                    runs,
+                                    parallel: true,
+                                    memory_budget,
+                                    ..Default::default()
+                                };
+
+                                assert_eq!(
+                                    profile.pick_algorithm(&p, &[]),
+                                    tuner.pick_algorithm(&p, &[]),
+                                    "len: {}, elem: {}, level: {}, share: {}, sorted: {}, runs: {}, budget: {:?}",
+                                    input_len,
+                                    element_size,
+                                    level,
+                                    max_bucket_share,
+                                    sortedness,
+                                    runs,
+                                    memory_budget
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "multi-threaded")]
+    pub fn test_standard_profile() {
+        assert_equivalent(&ProfileTuner::standard(), &StandardTuner);
+    }
+
+    #[test]
+    #[cfg(feature = "multi-threaded")]
+    pub fn test_low_memory_profile() {
+        assert_equivalent(&ProfileTuner::low_memory(), &LowMemoryTuner);
+    }
+
+    #[test]
+    pub fn test_single_threaded_profile() {
+        assert_equivalent(&ProfileTuner::single_threaded(), &SingleThreadedTuner);
+    }
+
+    #[test]
+    pub fn test_profile_roundtrip() {
+        let profile = ProfileTuner::single_threaded();
+        let reparsed: ProfileTuner = profile.to_string().parse().unwrap();
+
+        assert_eq!(profile, reparsed);
+    }
+
+    #[test]
+    pub fn test_invalid_profiles() {
+        assert!("-> Lsb".parse::<ProfileTuner>().is_ok());
+        assert!("len=5 -> Lsb".parse::<ProfileTuner>().is_err());
+        assert!("".parse::<ProfileTuner>().is_err());
+        assert!("len=abc -> Lsb\n-> Lsb".parse::<ProfileTuner>().is_err());
+        assert!("colour=blue -> Lsb\n-> Lsb"
+            .parse::<ProfileTuner>()
+            .is_err());
+        assert!("len=1.. len=..5 -> Lsb\n-> Lsb"
+            .parse::<ProfileTuner>()
+            .is_err());
+        assert!("len=1..5 Lsb\n-> Lsb".parse::<ProfileTuner>().is_err());
+        assert!("-> Bogo".parse::<ProfileTuner>().is_err());
+
+        let err = "-> Lsb\nlen=1 -> Unknown\n-> Lsb"
+            .parse::<ProfileTuner>()
+            .unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    #[cfg(not(feature = "multi-threaded"))]
+    pub fn test_rejects_unavailable_algorithms() {
+        assert!("-> MtLsb".parse::<ProfileTuner>().is_err());
+    }
+
+    #[test]
+    pub fn test_conditions() {
+        let tuner: ProfileTuner = "
+            skewed=true -> Ska
+            elem_size=16.. -> LrLsb
+            -> Lsb
+        "
+        .parse()
+        .unwrap();
+
+        let mut p = TuningParams {
+            threads: 1,
+            level: 0,
+            total_levels: 4,
+            input_len: 1_000,
+            element_size: 4,
+            non_empty_buckets: 256,
+            max_bucket_share: 1.0 / 256.0,
+            entropy: 8.0,
+            sortedness: 0.5,
+            runs: 500,
+            ..Default::default()
+        };
+
+        assert_eq!(tuner.pick_algorithm(&p, &[]), Algorithm::Lsb);
+        p.element_size = 16;
+        assert_eq!(tuner.pick_algorithm(&p, &[]), Algorithm::LrLsb);
+        p.max_bucket_share = 0.5;
+        assert_eq!(tuner.pick_algorithm(&p, &[]), Algorithm::Ska);
+    }
+
+    #[test]
+    pub fn test_u32_patterns() {
+        let tuner = ProfileTuner::single_threaded();
+
+        validate_u32_patterns(|inputs| {
+            inputs.radix_sort_builder().with_tuner(&tuner).sort();
+        });
+    }
+}
@@ -0,0 +1,16 @@
+# Same algorithm choices as LowMemoryTuner
+input_len=..128 -> Comparative
+
+# Skewed distributions
+input_len=5000.. skewed=true fits_budget=true len=..50000 -> LrLsb
+input_len=5000.. skewed=true len=..1000000 elem_size=32.. -> AmericanFlag
+input_len=5000.. skewed=true len=..1000000 -> Ska
+input_len=5000.. skewed=true len=..5000000 -> Regions
+input_len=5000.. skewed=true -> Block
+
+# Uniform distributions
+fits_budget=true len=..50000 -> Lsb
+len=..1000000 elem_size=32.. -> AmericanFlag
+len=..1000000 -> Ska
+len=..5000000 -> Regions
+-> Block
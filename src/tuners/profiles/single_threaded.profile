@@ -0,0 +1,17 @@
+# Same algorithm choices as SingleThreadedTuner
+input_len=..128 -> Comparative
+fits_budget=false -> Ska
+
+# A few long sorted runs, which can be merged in a few passes
+input_len=200000.. run_len=25000.. -> Merge
+
+# Mostly sorted already, so there is very little for Ska to swap
+len=100001.. sortedness=0.95.. -> Ska
+
+# Skewed distributions
+input_len=5000.. skewed=true len=100001.. depth=..1 -> Ska
+input_len=5000.. skewed=true -> LrLsb
+
+# Uniform distributions
+len=800001.. depth=0 -> Ska
+-> Lsb
@@ -0,0 +1,33 @@
+# Same algorithm choices as StandardTuner
+input_len=..128 -> Comparative
+
+# Only in-place algorithms fit within the memory budget
+fits_budget=false input_len=..1000000 -> Ska
+fits_budget=false -> Regions
+
+# A few long sorted runs, which can be merged in a few passes
+input_len=200000.. run_len=25000.. -> Merge
+
+# Mostly sorted already, so in-place algorithms have very little to move
+len=200000..800000 sortedness=0.95.. -> Ska
+len=200000.. sortedness=0.95.. -> Regions
+
+# Skewed distributions
+input_len=5000.. skewed=true depth=0 len=..200000 -> LrLsb
+input_len=5000.. skewed=true depth=0 len=..350000 -> Ska
+input_len=5000.. skewed=true depth=0 len=..4000000 -> MtLsb
+input_len=5000.. skewed=true depth=0 -> Regions
+input_len=5000.. skewed=true len=..200000 -> LrLsb
+input_len=5000.. skewed=true len=..800000 -> Ska
+input_len=5000.. skewed=true len=..5000000 -> Recombinating
+input_len=5000.. skewed=true -> Regions
+
+# Uniform distributions
+depth=1.. len=..200000 -> Lsb
+depth=1.. len=..800000 -> Ska
+depth=1.. len=..50000000 -> Recombinating
+depth=1.. -> Scanning
+len=..150000 -> Lsb
+len=..260000 -> Ska
+len=..50000000 -> Recombinating
+-> Scanning
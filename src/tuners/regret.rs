@@ -0,0 +1,323 @@
+//! Regret analysis measures how far a `Tuner` is from optimal on a given dataset.
+//!
+//! Every time the tuner is asked to pick an algorithm during a sort, each available algorithm is
+//! also timed on a copy of the same chunk (including sorting any remaining levels below it, using
+//! the same tuner). The difference between the time taken by the tuner's choice and by the fastest
+//! algorithm (the oracle choice) is the regret for that decision.
+//!
+//! This is slow, as every decision point is sorted once per algorithm, several times over. It is
+//! intended for evaluating tuner changes, not for use in production.
+//!
+//! To avoid decisions competing with each other for threads, decisions are made one at a time
+//! while recording, although multi-threaded algorithms still use all threads.
+
+use crate::sorter::Sorter;
+use crate::tuner::{Algorithm, Decision};
+use crate::RadixKey;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 3;
+
+/// A single decision made by the tuner, along with the best decision it could have made.
+#[derive(Debug, Clone)]
+pub struct RegretSample {
+    pub level: usize,
+    pub input_len: usize,
+    pub chosen: Algorithm,
+    pub chosen_time: Duration,
+    pub oracle: Algorithm,
+    pub oracle_time: Duration,
+}
+
+impl RegretSample {
+    /// `regret()` is the extra time taken by the tuner's choice compared to the oracle's choice.
+    #[inline]
+    pub fn regret(&self) -> Duration {
+        self.chosen_time.saturating_sub(self.oracle_time)
+    }
+}
+
+/// Cumulative regret across a group of decisions.
+#[derive(Debug, Clone, Default)]
+pub struct RegretSummary {
+    /// Number of decisions made by the tuner
+    pub decisions: usize,
+    /// Number of decisions where the tuner picked the same algorithm as the oracle
+    pub optimal: usize,
+    pub chosen_time: Duration,
+    pub oracle_time: Duration,
+}
+
+impl RegretSummary {
+    fn add(&mut self, s: &RegretSample) {
+        self.decisions += 1;
+        self.optimal += (s.chosen == s.oracle) as usize;
+        self.chosen_time += s.chosen_time;
+        self.oracle_time += s.oracle_time;
+    }
+
+    /// `regret()` is the total extra time taken by the tuner's choices in this group.
+    #[inline]
+    pub fn regret(&self) -> Duration {
+        self.chosen_time.saturating_sub(self.oracle_time)
+    }
+}
+
+/// `RegretReport` contains every decision made by a tuner during a sort. See
+/// `RadixSortBuilder::sort_with_regret_report()`.
+///
+/// The `Display` implementation prints a summary of cumulative regret per level and per size
+/// bucket.
+#[derive(Debug, Clone, Default)]
+pub struct RegretReport {
+    samples: Vec<RegretSample>,
+}
+
+impl RegretReport {
+    /// `samples()` returns every decision made, in the order they were made.
+    pub fn samples(&self) -> &[RegretSample] {
+        &self.samples
+    }
+
+    /// `total()` summarizes all decisions made.
+    pub fn total(&self) -> RegretSummary {
+        let mut summary = RegretSummary::default();
+        self.samples.iter().for_each(|s| summary.add(s));
+
+        summary
+    }
+
+    /// `by_level()` summarizes the decisions made for each level.
+    pub fn by_level(&self) -> BTreeMap<usize, RegretSummary> {
+        let mut out: BTreeMap<usize, RegretSummary> = BTreeMap::new();
+        self.samples
+            .iter()
+            .for_each(|s| out.entry(s.level).or_default().add(s));
+
+        out
+    }
+
+    /// `by_size()` summarizes the decisions made for each size bucket. Buckets are keyed by their
+    /// lower bound, with each bucket covering a power of ten (e.g. `1_000..=9_999`).
+    pub fn by_size(&self) -> BTreeMap<usize, RegretSummary> {
+        let mut out: BTreeMap<usize, RegretSummary> = BTreeMap::new();
+        self.samples.iter().for_each(|s| {
+            let bucket = 10usize.pow(s.input_len.max(1).ilog10());
+            out.entry(bucket).or_default().add(s)
+        });
+
+        out
+    }
+}
+
+impl fmt::Display for RegretReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn row(f: &mut fmt::Formatter<'_>, label: &str, s: &RegretSummary) -> fmt::Result {
+            writeln!(
+                f,
+                "{:>12} {:>9} {:>9} {:>14.3?} {:>14.3?} {:>14.3?}",
+                label,
+                s.decisions,
+                s.optimal,
+                s.chosen_time,
+                s.oracle_time,
+                s.regret()
+            )
+        }
+
+        let header = format!(
+            "{:>9} {:>9} {:>14} {:>14} {:>14}",
+            "decisions", "optimal", "chosen", "oracle", "regret"
+        );
+
+        writeln!(f, "{:>12} {}", "level", header)?;
+        for (level, s) in self.by_level() {
+            row(f, &level.to_string(), &s)?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "{:>12} {}", "size", header)?;
+        for (size, s) in self.by_size() {
+            row(f, &format!("{}+", size), &s)?;
+        }
+
+        writeln!(f)?;
+        row(f, "total", &self.total())
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct RegretRecorder {
+    samples: Mutex<Vec<RegretSample>>,
+}
+
+impl RegretRecorder {
+    pub(crate) fn into_report(self) -> RegretReport {
+        RegretReport {
+            samples: self.samples.into_inner().unwrap(),
+        }
+    }
+}
+
+impl<'a> Sorter<'a> {
+    /// `record_regret` times every algorithm the tuner could have picked on a copy of `chunk` and
+    /// records how the tuner's choice compares to the fastest of them. Only algorithms that are
+    /// permitted by strict mode, and stable if the tuner must be, are candidates. The chosen
+    /// algorithm is timed with the tuner's parameters, and the others with the defaults.
+    pub(crate) fn record_regret<T>(
+        &self,
+        recorder: &RegretRecorder,
+        chunk: &[T],
+        level: usize,
+        threads: usize,
+        chosen: Decision,
+    ) where
+        T: RadixKey + Sized + Send + Copy + Sync,
+    {
+        // Decisions within the timed sorts are not part of the tuner's actual path, so the sorter
+        // used for timing must not record them.
+        let sorter = self.without_regret_recorder();
+        let mut chosen_time = Duration::MAX;
+        let mut oracle = (Duration::MAX, chosen.algorithm);
+
+        let stable = self.tuner.stable();
+        let candidates = Algorithm::ALL.iter().copied().filter(|&a| {
+            a == chosen.algorithm || (self.is_permitted(a) && (!stable || a.is_stable()))
+        });
+
+        for algorithm in candidates {
+            let mut elapsed = Duration::MAX;
+
+            let decision = if algorithm == chosen.algorithm {
+                chosen
+            } else {
+                Decision::new(algorithm)
+            };
+
+            for _ in 0..RUNS {
+                let mut data = chunk.to_vec();
+                let start = Instant::now();
+                sorter.sort_with_decision(&mut data, level, threads, decision);
+                elapsed = elapsed.min(start.elapsed());
+            }
+
+            if algorithm == chosen.algorithm {
+                chosen_time = elapsed;
+            }
+
+            if elapsed < oracle.0 {
+                oracle = (elapsed, algorithm);
+            }
+        }
+
+        recorder.samples.lock().unwrap().push(RegretSample {
+            level,
+            input_len: chunk.len(),
+            chosen: chosen.algorithm,
+            chosen_time,
+            oracle: oracle.1,
+            oracle_time: oracle.0,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tuner::{Algorithm, Tuner, TuningParams};
+    use crate::utils::test_utils::validate_u32_patterns;
+    use crate::RadixSort;
+    use block_pseudorand::block_rand;
+
+    struct StableTuner;
+
+    impl Tuner for StableTuner {
+        fn pick_algorithm(&self, p: &TuningParams, _counts: &[usize]) -> Algorithm {
+            if p.input_len <= 128 {
+                Algorithm::LrLsb
+            } else {
+                Algorithm::Lsb
+            }
+        }
+
+        fn stable(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    pub fn test_u32_patterns() {
+        validate_u32_patterns(|inputs| {
+            let report = inputs
+                .radix_sort_builder()
+                .with_strict_single_threaded()
+                .sort_with_regret_report();
+
+            for s in report.samples() {
+                assert!(!s.chosen.is_multi_threaded(), "{:?}", s);
+                assert!(!s.oracle.is_multi_threaded(), "{:?}", s);
+            }
+        });
+    }
+
+    #[test]
+    pub fn test_stable_oracle() {
+        let mut data: Vec<u32> = block_rand(50_000);
+        let mut expected = data.clone();
+        expected.sort_unstable();
+
+        let report = data
+            .radix_sort_builder()
+            .with_tuner(&StableTuner)
+            .sort_with_regret_report();
+
+        assert_eq!(data, expected);
+        assert!(!report.samples().is_empty());
+
+        for s in report.samples() {
+            assert!(s.oracle.is_stable(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    pub fn test_report_summaries() {
+        let mut data: Vec<u32> = (0..200_000u32)
+            .rev()
+            .map(|v| v.wrapping_mul(2_654_435_761))
+            .collect();
+        let mut expected = data.clone();
+        expected.sort_unstable();
+
+        let report = data.radix_sort_builder().sort_with_regret_report();
+        assert_eq!(data, expected);
+
+        let samples = report.samples();
+        assert!(!samples.is_empty());
+        assert_eq!(samples[0].level, 3);
+        assert_eq!(samples[0].input_len, 200_000);
+
+        let total = report.total();
+        assert_eq!(total.decisions, samples.len());
+        assert_eq!(
+            report
+                .by_level()
+                .values()
+                .map(|s| s.decisions)
+                .sum::<usize>(),
+            samples.len()
+        );
+        assert_eq!(
+            report
+                .by_size()
+                .values()
+                .map(|s| s.decisions)
+                .sum::<usize>(),
+            samples.len()
+        );
+        assert!(report.by_size().contains_key(&100_000));
+        assert!(total.optimal <= total.decisions);
+        assert!(report.to_string().contains("total"));
+    }
+}
@@ -6,38 +6,104 @@
 //! SingleThreadedTuner algorithm choice is:
 //!  * single-threaded only
 //!  * aware of basic count distributions
+//!  * aware of element size, existing sortedness, sorted runs and memory budget
+//!  * aware of the mostly sorted and many duplicates key hints
 //!  * dynamic msb / lsb
+//!  * write-combining for LSB sorts larger than the cache
+//!  * counting every level at once for write-combining LSB sorts
 
 use crate::tuner::{Algorithm, Tuner, TuningParams};
 
 pub struct SingleThreadedTuner;
 impl Tuner for SingleThreadedTuner {
     #[inline]
-    fn pick_algorithm(&self, p: &TuningParams, counts: &[usize]) -> Algorithm {
+    fn pick_algorithm(&self, p: &TuningParams, _counts: &[usize]) -> Algorithm {
         if p.input_len <= 128 {
             return Algorithm::Comparative;
         }
 
+        if !p.fits_memory_budget() {
+            return Algorithm::Ska;
+        }
+
         let depth = p.total_levels - p.level - 1;
+        let len = p.weighted_len();
+
+        if p.input_len >= 200_000 && p.mean_run_len() >= 25_000 {
+            // A few long sorted runs, such as several sorted inputs appended to each other, which
+            // can be merged in a few passes
+            return Algorithm::Merge;
+        }
+
+        if len > 100_000 && p.is_nearly_sorted() {
+            // Most items are already in place, so there is very little for Ska to swap
+            return Algorithm::Ska;
+        }
 
-        if p.input_len >= 5_000 {
-            let distribution_threshold = (p.input_len / 256) * 2;
-
-            for c in counts {
-                if *c >= distribution_threshold {
-                    return if p.input_len > 100_000 && depth < 2 {
-                        Algorithm::Ska
-                    } else {
-                        Algorithm::LrLsb
-                    };
-                }
-            }
+        if p.input_len >= 5_000 && p.is_skewed() {
+            return if len > 100_000 && depth < 2 {
+                Algorithm::Ska
+            } else {
+                Algorithm::LrLsb
+            };
         }
 
-        if p.input_len > 800_000 && depth == 0 {
+        if len > 800_000 && depth == 0 {
             Algorithm::Ska
         } else {
             Algorithm::Lsb
         }
     }
+
+    #[inline]
+    fn count_all_levels(&self, p: &TuningParams) -> bool {
+        // With write-combining, each level of an LSB sort would otherwise be counted in a
+        // separate read of the bucket
+        let algorithm = self.pick_algorithm(p, &[]);
+
+        matches!(algorithm, Algorithm::Lsb | Algorithm::LrLsb) && self.write_combining(p, algorithm)
+    }
+
+    #[inline]
+    fn write_combining(&self, p: &TuningParams, algorithm: Algorithm) -> bool {
+        // Buffering only pays off for LSB scatters into outputs that don't fit in the cache
+        matches!(algorithm, Algorithm::Lsb | Algorithm::LrLsb)
+            && p.input_len.saturating_mul(p.element_size) >= 2_000_000
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tuner::{Algorithm, Tuner, TuningParams};
+    use crate::tuners::SingleThreadedTuner;
+
+    #[test]
+    pub fn test_key_hints() {
+        let mut p = TuningParams {
+            level: 3,
+            total_levels: 4,
+            input_len: 50_000,
+            element_size: 4,
+            non_empty_buckets: 256,
+            max_bucket_share: 1.0 / 256.0,
+            entropy: 8.0,
+            sortedness: 0.5,
+            runs: 25_000,
+            ..Default::default()
+        };
+
+        assert_eq!(SingleThreadedTuner.pick_algorithm(&p, &[]), Algorithm::Lsb);
+        p.key_hints.many_duplicates = true;
+        assert_eq!(
+            SingleThreadedTuner.pick_algorithm(&p, &[]),
+            Algorithm::LrLsb
+        );
+
+        p.input_len = 500_000;
+        p.runs = 250_000;
+        p.key_hints.many_duplicates = false;
+        assert_eq!(SingleThreadedTuner.pick_algorithm(&p, &[]), Algorithm::Lsb);
+        p.key_hints.mostly_sorted = true;
+        assert_eq!(SingleThreadedTuner.pick_algorithm(&p, &[]), Algorithm::Ska);
+    }
 }
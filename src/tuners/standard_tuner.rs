@@ -3,62 +3,220 @@
 //! StandardTuner algorithm choice is:
 //!  * multi-threaded
 //!  * aware of basic count distributions
+//!  * aware of element size, existing sortedness, sorted runs and memory budget
+//!  * aware of the mostly sorted and many duplicates key hints
 //!  * dynamic msb / lsb
+//!  * write-combining for LSB and recombinating sorts larger than the cache
+//!  * counting every level at once for write-combining LSB sorts
+//!  * indirect sorting for items much wider than their keys
 
 use crate::tuner::{Algorithm, Tuner, TuningParams};
 
 pub struct StandardTuner;
 impl Tuner for StandardTuner {
     #[inline]
-    fn pick_algorithm(&self, p: &TuningParams, counts: &[usize]) -> Algorithm {
+    fn pick_algorithm(&self, p: &TuningParams, _counts: &[usize]) -> Algorithm {
         if p.input_len <= 128 {
             return Algorithm::Comparative;
         }
 
         let depth = p.total_levels - p.level - 1;
+        let len = p.weighted_len();
 
-        if p.input_len >= 5_000 {
-            let distribution_threshold = (p.input_len / 256) * 2;
-
-            for c in counts {
-                if *c >= distribution_threshold {
-                    return if depth == 0 {
-                        match p.input_len {
-                            0..=200_000 => Algorithm::LrLsb,
-                            200_001..=350_000 => Algorithm::Ska,
-                            350_001..=4_000_000 => Algorithm::MtLsb,
-                            4_000_001..=usize::MAX => Algorithm::Regions,
-                            _ => Algorithm::LrLsb,
-                        }
-                    } else {
-                        match p.input_len {
-                            0..=200_000 => Algorithm::LrLsb,
-                            200_001..=800_000 => Algorithm::Ska,
-                            800_001..=5_000_000 => Algorithm::Recombinating,
-                            5_000_001..=usize::MAX => Algorithm::Regions,
-                            _ => Algorithm::LrLsb,
-                        }
-                    };
+        if !p.fits_memory_budget() {
+            // Only in-place algorithms are available
+            return match p.input_len {
+                0..=1_000_000 => Algorithm::Ska,
+                _ => Algorithm::Regions,
+            };
+        }
+
+        if p.input_len >= 200_000 && p.mean_run_len() >= 25_000 {
+            // A few long sorted runs, such as several sorted inputs appended to each other, which
+            // can be merged in a few passes
+            return Algorithm::Merge;
+        }
+
+        if len >= 200_000 && p.is_nearly_sorted() {
+            // Most items are already in place, so in-place algorithms have very little to move
+            return match len {
+                0..=800_000 => Algorithm::Ska,
+                _ => Algorithm::Regions,
+            };
+        }
+
+        if p.input_len >= 5_000 && p.is_skewed() {
+            return if depth == 0 {
+                match len {
+                    0..=200_000 => Algorithm::LrLsb,
+                    200_001..=350_000 => Algorithm::Ska,
+                    350_001..=4_000_000 => Algorithm::MtLsb,
+                    _ => Algorithm::Regions,
                 }
-            }
+            } else {
+                match len {
+                    0..=200_000 => Algorithm::LrLsb,
+                    200_001..=800_000 => Algorithm::Ska,
+                    800_001..=5_000_000 => Algorithm::Recombinating,
+                    _ => Algorithm::Regions,
+                }
+            };
         }
 
         if depth > 0 {
-            match p.input_len {
+            match len {
                 0..=200_000 => Algorithm::Lsb,
                 200_001..=800_000 => Algorithm::Ska,
                 800_001..=50_000_000 => Algorithm::Recombinating,
-                50_000_001..=usize::MAX => Algorithm::Scanning,
-                _ => Algorithm::Lsb,
+                _ => Algorithm::Scanning,
             }
         } else {
-            match p.input_len {
+            match len {
                 0..=150_000 => Algorithm::Lsb,
                 150_001..=260_000 => Algorithm::Ska,
                 260_001..=50_000_000 => Algorithm::Recombinating,
-                50_000_001..=usize::MAX => Algorithm::Scanning,
-                _ => Algorithm::Lsb,
+                _ => Algorithm::Scanning,
+            }
+        }
+    }
+
+    #[inline]
+    fn count_all_levels(&self, p: &TuningParams) -> bool {
+        // With write-combining, each level of an LSB sort would otherwise be counted in a
+        // separate read of the bucket
+        let algorithm = self.pick_algorithm(p, &[]);
+
+        matches!(
+            algorithm,
+            Algorithm::Lsb | Algorithm::LrLsb | Algorithm::MtLsb
+        ) && self.write_combining(p, algorithm)
+    }
+
+    #[inline]
+    fn write_combining(&self, p: &TuningParams, algorithm: Algorithm) -> bool {
+        // Buffering only pays off for scatters into outputs that don't fit in the cache
+        let bytes = p.input_len.saturating_mul(p.element_size);
+
+        match algorithm {
+            Algorithm::Lsb | Algorithm::LrLsb | Algorithm::MtLsb => bytes >= 2_000_000,
+            // Each thread scatters its own tile, so it's the size of a tile that matters
+            Algorithm::Recombinating => bytes / p.threads.max(1) >= 2_000_000,
+            _ => false,
+        }
+    }
+
+    #[inline]
+    fn indirect(&self, p: &TuningParams) -> bool {
+        // Each item is replaced with a packed key and index, which must fit in the budget too
+        p.fits_indirect_memory_budget() && p.element_size >= p.indirect_item_size() * 5
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tuner::{Algorithm, Tuner, TuningParams};
+    use crate::tuners::{SingleThreadedTuner, StandardTuner};
+    use crate::utils::test_utils::{gen_sorted_runs, top_level_pick};
+
+    #[test]
+    pub fn test_picks_merge_for_sorted_runs() {
+        let tuners: [&(dyn Tuner + Send + Sync); 2] = [&StandardTuner, &SingleThreadedTuner];
+
+        for tuner in tuners {
+            // Nearly sorted, with many more runs than could be found by chance
+            for runs in [4, 16, 64] {
+                let data = gen_sorted_runs(runs, 50_000);
+                assert_eq!(top_level_pick(data, tuner), Algorithm::Merge);
             }
+
+            // Too many short runs to merge
+            let data = gen_sorted_runs(200, 1_000);
+            assert_ne!(top_level_pick(data, tuner), Algorithm::Merge);
+        }
+    }
+
+    #[test]
+    pub fn test_counts_all_levels_for_write_combining_lsb() {
+        let tuners: [&(dyn Tuner + Send + Sync); 2] = [&StandardTuner, &SingleThreadedTuner];
+
+        for tuner in tuners {
+            let mut p = TuningParams {
+                level: 2,
+                total_levels: 4,
+                input_len: 150_000,
+                element_size: 16,
+                non_empty_buckets: 256,
+                max_bucket_share: 1.0 / 256.0,
+                entropy: 8.0,
+                sortedness: 0.5,
+                runs: 75_000,
+                ..Default::default()
+            };
+
+            assert_eq!(tuner.pick_algorithm(&p, &[]), Algorithm::Lsb);
+            assert!(tuner.count_all_levels(&p));
+
+            // Too small for write-combining, so each level is counted while sorting the one before
+            p.element_size = 4;
+            assert!(!tuner.count_all_levels(&p));
         }
     }
+
+    #[test]
+    pub fn test_indirect_needs_memory_budget() {
+        let mut p = TuningParams {
+            total_levels: 4,
+            input_len: 10_000,
+            element_size: 128,
+            ..Default::default()
+        };
+
+        assert!(StandardTuner.indirect(&p));
+
+        // Items at least five times the size of their packed key and index
+        p.element_size = 40;
+        assert!(StandardTuner.indirect(&p));
+        p.element_size = 32;
+        assert!(!StandardTuner.indirect(&p));
+        p.element_size = 128;
+
+        // The packed keys and indexes take 8 bytes per item
+        p.memory_budget = Some(80_000);
+        assert!(StandardTuner.indirect(&p));
+
+        p.memory_budget = Some(79_999);
+        assert!(!StandardTuner.indirect(&p));
+
+        p.memory_budget = Some(0);
+        assert!(!StandardTuner.indirect(&p));
+    }
+
+    #[test]
+    pub fn test_key_hints() {
+        let p = TuningParams {
+            level: 3,
+            total_levels: 4,
+            input_len: 500_000,
+            element_size: 4,
+            non_empty_buckets: 256,
+            max_bucket_share: 1.0 / 256.0,
+            entropy: 8.0,
+            sortedness: 0.5,
+            runs: 250_000,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            StandardTuner.pick_algorithm(&p, &[]),
+            Algorithm::Recombinating
+        );
+
+        let mut hinted = p.clone();
+        hinted.key_hints.many_duplicates = true;
+        assert_eq!(StandardTuner.pick_algorithm(&hinted, &[]), Algorithm::MtLsb);
+
+        let mut hinted = p.clone();
+        hinted.key_hints.mostly_sorted = true;
+        assert_eq!(StandardTuner.pick_algorithm(&hinted, &[]), Algorithm::Ska);
+    }
 }
@@ -3,6 +3,7 @@ pub mod bench_utils;
 #[cfg(all(feature = "multi-threaded", any(test, bench, tuning)))]
 pub mod test_utils;
 
+pub mod simd_count;
 mod sort_utils;
 
 pub use sort_utils::*;
@@ -0,0 +1,489 @@
+//! Vectorised helpers for counting a level of primitive keys.
+//!
+//! Counting is split into two steps. First the byte for the level is extracted from a block of
+//! keys into a small buffer, using a shift and a narrowing pack or truncate per vector of keys.
+//! Then the buffer is counted into four separate histograms (so that runs of equal bytes don't
+//! serialize on a single counter), while ascents and descents between neighbouring bytes are
+//! counted with vector compares.
+//!
+//! Counting is memory bound, so the next block of keys is prefetched while the current one is
+//! counted. Only 32 and 64-bit keys are extracted this way, as for narrower keys the scalar loop
+//! is already as fast.
+//!
+//! AVX-512 and AVX2 are detected at runtime. When neither is available, the extract functions
+//! return `false` and the caller falls back to the scalar counting loop, which produces identical
+//! results.
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+/// Number of keys extracted into the byte buffer at a time. Larger blocks issue too many
+/// prefetches at once.
+pub const COUNT_BLOCK: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SimdLevel {
+    None,
+    #[cfg(target_arch = "x86_64")]
+    Avx2,
+    #[cfg(target_arch = "x86_64")]
+    Avx512,
+}
+
+#[inline]
+fn simd_level() -> SimdLevel {
+    #[cfg(target_arch = "x86_64")]
+    {
+        // Both of these are cached by std after the first call
+        if is_x86_feature_detected!("avx512bw") {
+            return SimdLevel::Avx512;
+        } else if is_x86_feature_detected!("avx2") {
+            return SimdLevel::Avx2;
+        }
+    }
+
+    SimdLevel::None
+}
+
+/// `extract_u32` writes byte `level` of each of `items` into `out`. Returns false, leaving `out`
+/// unchanged, if no vectorised extraction is available.
+#[inline]
+pub fn extract_u32(items: &[u32], level: usize, out: &mut [u8]) -> bool {
+    debug_assert_eq!(items.len(), out.len());
+
+    match simd_level() {
+        SimdLevel::None => false,
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx2 => {
+            // Safety: AVX2 is available
+            unsafe { extract_u32_avx2(items, level, out) };
+            true
+        }
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx512 => {
+            // Safety: AVX-512BW is available
+            unsafe { extract_u32_avx512(items, level, out) };
+            true
+        }
+    }
+}
+
+/// `extract_u64` writes byte `level` of each of `items` into `out`. Returns false, leaving `out`
+/// unchanged, if no vectorised extraction is available.
+#[inline]
+pub fn extract_u64(items: &[u64], level: usize, out: &mut [u8]) -> bool {
+    debug_assert_eq!(items.len(), out.len());
+
+    match simd_level() {
+        SimdLevel::None => false,
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx2 => {
+            // Safety: AVX2 is available
+            unsafe { extract_u64_avx2(items, level, out) };
+            true
+        }
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx512 => {
+            // Safety: AVX-512BW is available
+            unsafe { extract_u64_avx512(items, level, out) };
+            true
+        }
+    }
+}
+
+/// `extract_cast` reinterprets `items` as the unsigned integer `U` of the same width, and
+/// extracts byte `level` of each with `extract`.
+#[inline]
+pub fn extract_cast<S, U>(
+    items: &[S],
+    level: usize,
+    out: &mut [u8],
+    extract: fn(&[U], usize, &mut [u8]) -> bool,
+) -> bool {
+    assert_eq!(size_of::<S>(), size_of::<U>());
+    assert_eq!(align_of::<S>(), align_of::<U>());
+
+    // Safety: the size and alignment match, and any bit pattern is a valid unsigned integer
+    let items = unsafe { std::slice::from_raw_parts(items.as_ptr() as *const U, items.len()) };
+
+    extract(items, level, out)
+}
+
+/// `extract_signed` writes byte `level` of each of `items`, in radix order, into `out`. Returns
+/// false, leaving `out` unchanged, if no vectorised extraction is available.
+#[inline]
+pub fn extract_signed<S, U>(
+    items: &[S],
+    level: usize,
+    out: &mut [u8],
+    extract: fn(&[U], usize, &mut [u8]) -> bool,
+) -> bool {
+    if !extract_cast(items, level, out, extract) {
+        return false;
+    }
+
+    // Flipping the sign bit orders negative numbers before positive ones
+    if level == size_of::<S>() - 1 {
+        flip_top_bit(out);
+    }
+
+    true
+}
+
+/// `flip_top_bit` flips the top bit of each byte in `out`, which converts the top level of a
+/// two's complement signed integer into its radix order.
+#[inline]
+pub fn flip_top_bit(out: &mut [u8]) {
+    out.iter_mut().for_each(|b| *b ^= 0x80);
+}
+
+/// `prefetch` asks the CPU to start loading `items` into cache, so that it can be read from
+/// memory while the previous block is being counted.
+#[inline]
+#[cfg_attr(not(target_arch = "x86_64"), allow(unused_variables))]
+pub fn prefetch<T>(items: &[T]) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        let ptr = items.as_ptr() as *const i8;
+
+        for offset in (0..size_of_val(items)).step_by(64) {
+            // Safety: prefetching is only a hint, and the address is within `items`
+            unsafe { _mm_prefetch::<_MM_HINT_T0>(ptr.add(offset)) };
+        }
+    }
+}
+
+/// `histogram_bytes` adds `bytes` to `counts`, spread across the four histograms.
+#[inline]
+pub fn histogram_bytes(bytes: &[u8], counts: &mut [[usize; 256]; 4]) {
+    let (c0, rest) = counts.split_at_mut(1);
+    let (c1, rest) = rest.split_at_mut(1);
+    let (c2, c3) = rest.split_at_mut(1);
+    let (c0, c1, c2, c3) = (&mut c0[0], &mut c1[0], &mut c2[0], &mut c3[0]);
+
+    let chunks = bytes.chunks_exact(4);
+    let rem = chunks.remainder();
+
+    for chunk in chunks {
+        c0[chunk[0] as usize] += 1;
+        c1[chunk[1] as usize] += 1;
+        c2[chunk[2] as usize] += 1;
+        c3[chunk[3] as usize] += 1;
+    }
+
+    for b in rem {
+        c0[*b as usize] += 1;
+    }
+}
+
+/// `count_bytes` adds `bytes[1..]` to `counts`, spread across the four histograms, and returns
+/// the number of ascents and descents between neighbouring bytes of `bytes`. `bytes[0]` is the
+/// byte of the item before the block, and is not counted.
+#[inline]
+pub fn count_bytes(bytes: &[u8], counts: &mut [[usize; 256]; 4]) -> (usize, usize) {
+    histogram_bytes(&bytes[1..], counts);
+
+    match simd_level() {
+        SimdLevel::None => compare_adjacent_scalar(bytes, 0),
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx2 => {
+            // Safety: AVX2 is available
+            unsafe { compare_adjacent_avx2(bytes) }
+        }
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx512 => {
+            // Safety: AVX-512BW is available
+            unsafe { compare_adjacent_avx512(bytes) }
+        }
+    }
+}
+
+/// `compare_adjacent_scalar` counts ascents and descents between neighbouring bytes from `start`
+/// onwards.
+#[inline]
+fn compare_adjacent_scalar(bytes: &[u8], start: usize) -> (usize, usize) {
+    let mut ascents = 0;
+    let mut descents = 0;
+
+    for pair in bytes[start..].windows(2) {
+        ascents += (pair[1] > pair[0]) as usize;
+        descents += (pair[1] < pair[0]) as usize;
+    }
+
+    (ascents, descents)
+}
+
+/// `pack_u32_avx2` packs four vectors of 32-bit values, each no more than 255, into 32 bytes in
+/// their original order.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+#[inline]
+unsafe fn pack_u32_avx2(a: __m256i, b: __m256i, c: __m256i, d: __m256i) -> __m256i {
+    let ab = _mm256_packus_epi32(a, b);
+    let cd = _mm256_packus_epi32(c, d);
+    let abcd = _mm256_packus_epi16(ab, cd);
+
+    // Packing works within each 128-bit lane, so each group of four bytes needs moving back
+    _mm256_permutevar8x32_epi32(abcd, _mm256_setr_epi32(0, 4, 1, 5, 2, 6, 3, 7))
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn extract_u32_avx2(items: &[u32], level: usize, out: &mut [u8]) {
+    let shift = _mm_cvtsi64_si128((level * 8) as i64);
+    let mask = _mm256_set1_epi32(0xFF);
+    let chunks = items.len() / 32;
+
+    for i in 0..chunks {
+        let src = items.as_ptr().add(i * 32) as *const __m256i;
+        let load = |j: usize| {
+            _mm256_and_si256(
+                _mm256_srl_epi32(_mm256_loadu_si256(src.add(j)), shift),
+                mask,
+            )
+        };
+        let packed = pack_u32_avx2(load(0), load(1), load(2), load(3));
+        _mm256_storeu_si256(out.as_mut_ptr().add(i * 32) as *mut __m256i, packed);
+    }
+
+    for i in chunks * 32..items.len() {
+        out[i] = (items[i] >> (level * 8)) as u8;
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512bw")]
+unsafe fn extract_u32_avx512(items: &[u32], level: usize, out: &mut [u8]) {
+    let shift = _mm_cvtsi64_si128((level * 8) as i64);
+    let chunks = items.len() / 16;
+
+    for i in 0..chunks {
+        let v = _mm512_loadu_si512(items.as_ptr().add(i * 16) as *const _);
+        let bytes = _mm512_cvtepi32_epi8(_mm512_srl_epi32(v, shift));
+        _mm_storeu_si128(out.as_mut_ptr().add(i * 16) as *mut __m128i, bytes);
+    }
+
+    for i in chunks * 16..items.len() {
+        out[i] = (items[i] >> (level * 8)) as u8;
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn extract_u64_avx2(items: &[u64], level: usize, out: &mut [u8]) {
+    let shift = _mm_cvtsi64_si128((level * 8) as i64);
+    let mask = _mm256_set1_epi64x(0xFF);
+    let low_dwords = _mm256_setr_epi32(0, 2, 4, 6, 1, 3, 5, 7);
+    let chunks = items.len() / 32;
+
+    for i in 0..chunks {
+        let src = items.as_ptr().add(i * 32) as *const __m256i;
+
+        // Narrow each pair of vectors of 64-bit values into one vector of 32-bit values
+        let load = |j: usize| {
+            let a = _mm256_and_si256(
+                _mm256_srl_epi64(_mm256_loadu_si256(src.add(j)), shift),
+                mask,
+            );
+            let b = _mm256_and_si256(
+                _mm256_srl_epi64(_mm256_loadu_si256(src.add(j + 1)), shift),
+                mask,
+            );
+            let a = _mm256_permutevar8x32_epi32(a, low_dwords);
+            let b = _mm256_permutevar8x32_epi32(b, low_dwords);
+
+            _mm256_permute2x128_si256(a, b, 0x20)
+        };
+
+        let packed = pack_u32_avx2(load(0), load(2), load(4), load(6));
+        _mm256_storeu_si256(out.as_mut_ptr().add(i * 32) as *mut __m256i, packed);
+    }
+
+    for i in chunks * 32..items.len() {
+        out[i] = (items[i] >> (level * 8)) as u8;
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512bw")]
+unsafe fn extract_u64_avx512(items: &[u64], level: usize, out: &mut [u8]) {
+    let shift = _mm_cvtsi64_si128((level * 8) as i64);
+    let chunks = items.len() / 8;
+
+    for i in 0..chunks {
+        let v = _mm512_loadu_si512(items.as_ptr().add(i * 8) as *const _);
+        let bytes = _mm512_cvtepi64_epi8(_mm512_srl_epi64(v, shift));
+        _mm_storel_epi64(out.as_mut_ptr().add(i * 8) as *mut __m128i, bytes);
+    }
+
+    for i in chunks * 8..items.len() {
+        out[i] = (items[i] >> (level * 8)) as u8;
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn compare_adjacent_avx2(bytes: &[u8]) -> (usize, usize) {
+    // There is no unsigned byte compare in AVX2, so flip the top bit and compare as signed
+    let flip = _mm256_set1_epi8(i8::MIN);
+    let pairs = bytes.len().saturating_sub(1);
+    let chunks = pairs / 32;
+    let mut ascents = 0;
+    let mut descents = 0;
+
+    for i in 0..chunks {
+        let prev = _mm256_loadu_si256(bytes.as_ptr().add(i * 32) as *const __m256i);
+        let next = _mm256_loadu_si256(bytes.as_ptr().add(i * 32 + 1) as *const __m256i);
+        let prev = _mm256_xor_si256(prev, flip);
+        let next = _mm256_xor_si256(next, flip);
+
+        ascents +=
+            (_mm256_movemask_epi8(_mm256_cmpgt_epi8(next, prev)) as u32).count_ones() as usize;
+        descents +=
+            (_mm256_movemask_epi8(_mm256_cmpgt_epi8(prev, next)) as u32).count_ones() as usize;
+    }
+
+    let (a, d) = compare_adjacent_scalar(bytes, chunks * 32);
+
+    (ascents + a, descents + d)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512bw")]
+unsafe fn compare_adjacent_avx512(bytes: &[u8]) -> (usize, usize) {
+    let pairs = bytes.len().saturating_sub(1);
+    let chunks = pairs / 64;
+    let mut ascents = 0;
+    let mut descents = 0;
+
+    for i in 0..chunks {
+        let prev = _mm512_loadu_si512(bytes.as_ptr().add(i * 64) as *const _);
+        let next = _mm512_loadu_si512(bytes.as_ptr().add(i * 64 + 1) as *const _);
+
+        ascents += _mm512_cmpgt_epu8_mask(next, prev).count_ones() as usize;
+        descents += _mm512_cmplt_epu8_mask(next, prev).count_ones() as usize;
+    }
+
+    let (a, d) = compare_adjacent_scalar(bytes, chunks * 64);
+
+    (ascents + a, descents + d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use block_pseudorand::block_rand;
+
+    fn validate_extract<T, F>(extract: F, bytes: usize)
+    where
+        T: Copy + Into<u128>,
+        F: Fn(&[T], usize, &mut [u8]) -> bool,
+    {
+        for len in [0, 1, 7, 31, 32, 33, 100, 1_000] {
+            let items: Vec<T> = block_rand(len);
+
+            for level in 0..bytes {
+                let mut out = vec![0u8; len];
+
+                if !extract(&items, level, &mut out) {
+                    return;
+                }
+
+                let expected: Vec<u8> = items
+                    .iter()
+                    .map(|v| ((*v).into() >> (level * 8)) as u8)
+                    .collect();
+
+                assert_eq!(out, expected, "len {} level {}", len, level);
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_extract_u32() {
+        validate_extract::<u32, _>(extract_u32, 4);
+
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("avx2") {
+            validate_extract::<u32, _>(
+                |i, l, o| {
+                    unsafe { extract_u32_avx2(i, l, o) };
+                    true
+                },
+                4,
+            );
+        }
+
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("avx512bw") {
+            validate_extract::<u32, _>(
+                |i, l, o| {
+                    unsafe { extract_u32_avx512(i, l, o) };
+                    true
+                },
+                4,
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_extract_u64() {
+        validate_extract::<u64, _>(extract_u64, 8);
+
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("avx2") {
+            validate_extract::<u64, _>(
+                |i, l, o| {
+                    unsafe { extract_u64_avx2(i, l, o) };
+                    true
+                },
+                8,
+            );
+        }
+
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("avx512bw") {
+            validate_extract::<u64, _>(
+                |i, l, o| {
+                    unsafe { extract_u64_avx512(i, l, o) };
+                    true
+                },
+                8,
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_count_bytes() {
+        for len in [1, 2, 3, 33, 64, 65, 66, 513] {
+            for modulo in [2u8, 255] {
+                let bytes: Vec<u8> = block_rand::<u8>(len)
+                    .into_iter()
+                    .map(|b| b % modulo)
+                    .collect();
+                let mut counts = [[0usize; 256]; 4];
+                let (ascents, descents) = count_bytes(&bytes, &mut counts);
+
+                let mut expected = [0usize; 256];
+                bytes[1..].iter().for_each(|b| expected[*b as usize] += 1);
+
+                for (i, e) in expected.iter().enumerate() {
+                    assert_eq!(counts.iter().map(|c| c[i]).sum::<usize>(), *e);
+                }
+
+                let expected = compare_adjacent_scalar(&bytes, 0);
+                assert_eq!((ascents, descents), expected);
+
+                #[cfg(target_arch = "x86_64")]
+                if is_x86_feature_detected!("avx2") {
+                    assert_eq!(unsafe { compare_adjacent_avx2(&bytes) }, expected);
+                }
+
+                #[cfg(target_arch = "x86_64")]
+                if is_x86_feature_detected!("avx512bw") {
+                    assert_eq!(unsafe { compare_adjacent_avx512(&bytes) }, expected);
+                }
+            }
+        }
+    }
+}
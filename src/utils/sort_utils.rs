@@ -1,3 +1,4 @@
+use crate::utils::simd_count::{count_bytes, histogram_bytes, prefetch, COUNT_BLOCK};
 use crate::RadixKey;
 #[cfg(feature = "multi-threaded")]
 use rayon::prelude::*;
@@ -27,6 +28,117 @@ pub fn get_end_offsets(counts: &[usize; 256], prefix_sums: &[usize; 256]) -> [us
     end_offsets
 }
 
+/// CountMeta holds everything learned about a bucket while counting a level, other than the
+/// counts themselves.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CountMeta {
+    /// The bucket is already in ascending order for this level
+    pub already_sorted: bool,
+    /// The byte of the first item in the bucket for this level
+    pub first: u8,
+    /// The byte of the last item in the bucket for this level
+    pub last: u8,
+    /// The number of adjacent pairs where the byte for this level decreases
+    pub descents: usize,
+    /// The number of adjacent pairs where the byte for this level increases
+    pub ascents: usize,
+}
+
+impl CountMeta {
+    /// sortedness is the fraction of adjacent pairs already in ascending order for this level.
+    #[inline]
+    pub fn sortedness(&self, len: usize) -> f64 {
+        if len < 2 {
+            return 1.0;
+        }
+
+        1.0 - (self.descents as f64 / (len - 1) as f64)
+    }
+
+    /// runs is the number of ascending runs for this level, which is one more than the number of
+    /// descents.
+    #[inline]
+    pub fn runs(&self) -> usize {
+        self.descents + 1
+    }
+
+    /// reverse_sortedness is the fraction of adjacent pairs already in descending order for this
+    /// level.
+    #[inline]
+    pub fn reverse_sortedness(&self, len: usize) -> f64 {
+        if len < 2 {
+            return 1.0;
+        }
+
+        1.0 - (self.ascents as f64 / (len - 1) as f64)
+    }
+
+    /// already_reversed is true when the bucket is in descending order for this level, so that
+    /// reversing it would leave it sorted. Buckets where every item is the same for this level are
+    /// already sorted rather than reversed.
+    #[inline]
+    pub fn already_reversed(&self) -> bool {
+        self.ascents == 0 && !self.already_sorted
+    }
+
+    /// unordered is the meta expected of `len` uniformly random items, for a bucket that was
+    /// counted without looking at the order of its items. Neighbouring items are the same 1 time
+    /// in 256, and otherwise equally likely to ascend or descend. Rounding up means that a bucket
+    /// of more than one item is never taken to be in descending order.
+    #[inline]
+    pub fn unordered(len: usize) -> CountMeta {
+        let changes = cdiv(len.saturating_sub(1) * 255, 256);
+        let descents = changes / 2;
+
+        CountMeta {
+            already_sorted: len < 2,
+            first: 0,
+            last: 0,
+            descents,
+            ascents: changes - descents,
+        }
+    }
+
+    /// reversed returns the meta for the same bucket after it has been reversed.
+    #[inline]
+    pub fn reversed(&self) -> CountMeta {
+        CountMeta {
+            already_sorted: self.ascents == 0,
+            first: self.last,
+            last: self.first,
+            descents: self.ascents,
+            ascents: self.descents,
+        }
+    }
+}
+
+/// combine_count_meta combines the meta of consecutive sub-slices of a bucket into the meta for
+/// the whole bucket.
+#[inline]
+pub fn combine_count_meta(metas: &[CountMeta]) -> CountMeta {
+    let mut meta = metas[0];
+
+    for w in metas.windows(2) {
+        meta.descents += w[1].descents;
+        meta.ascents += w[1].ascents;
+
+        if !w[1].already_sorted {
+            meta.already_sorted = false;
+        }
+
+        if w[1].first < w[0].last {
+            meta.already_sorted = false;
+            meta.descents += 1;
+        } else if w[1].first > w[0].last {
+            meta.ascents += 1;
+        }
+    }
+
+    meta.last = metas[metas.len() - 1].last;
+
+    meta
+}
+
 #[inline]
 #[cfg(any(test, bench, tuning))]
 pub fn par_get_counts<T>(bucket: &[T], level: usize) -> ([usize; 256], bool)
@@ -37,18 +149,19 @@ where
         return ([0usize; 256], true);
     }
 
-    let (counts, sorted, _, _) = par_get_counts_with_ends(bucket, level);
-    (counts, sorted)
+    let (counts, meta) = par_get_counts_with_ends(bucket, level);
+    (counts, meta.already_sorted)
 }
 
 #[inline]
 #[cfg(feature = "multi-threaded")]
-pub fn par_get_counts_with_ends<T>(bucket: &[T], level: usize) -> ([usize; 256], bool, u8, u8)
+pub fn par_get_counts_with_ends<T>(bucket: &[T], level: usize) -> ([usize; 256], CountMeta)
 where
     T: RadixKey + Sized + Send + Sync,
 {
-    #[cfg(feature = "work_profiles")]
-    println!("({}) PAR_COUNT", level);
+    #[cfg(feature = "tracing")]
+    let _span = tracing::trace_span!("par_count", level, len = bucket.len()).entered();
+    trace_scope!("par_count", level = level, len = bucket.len());
 
     if bucket.len() < 400_000 {
         return get_counts_with_ends(bucket, level);
@@ -62,61 +175,92 @@ where
     let (tx, rx) = channel();
 
     chunks.enumerate().for_each_with(tx, |tx, (i, chunk)| {
-        let counts = get_counts_with_ends(chunk, level);
-        tx.send((i, counts.0, counts.1, counts.2, counts.3))
-            .unwrap();
+        let (counts, meta) = get_counts_with_ends(chunk, level);
+        tx.send((i, counts, meta)).unwrap();
     });
 
     let mut msb_counts = [0usize; 256];
-    let mut already_sorted = true;
-    let mut boundaries = vec![(0u8, 0u8); len];
+    let mut metas = Vec::with_capacity(len);
 
-    for _ in 0..len {
-        let (i, counts, chunk_sorted, start, end) = rx.recv().unwrap();
+    for (i, counts, meta) in rx.iter().take(len) {
+        metas.push((i, meta));
 
-        if !chunk_sorted {
-            already_sorted = false;
+        for (i, c) in counts.iter().enumerate() {
+            msb_counts[i] += *c;
         }
+    }
 
-        boundaries[i].0 = start;
-        boundaries[i].1 = end;
+    // Combine the meta of each counted chunk in order, to include the boundaries between chunks
+    metas.sort_unstable_by_key(|m| m.0);
+    let metas: Vec<CountMeta> = metas.into_iter().map(|m| m.1).collect();
 
-        for (i, c) in counts.iter().enumerate() {
-            msb_counts[i] += *c;
+    (msb_counts, combine_count_meta(&metas))
+}
+
+/// `count_extracted` adds the counts of `bucket` at `level` to `counts` using SIMD, returning the
+/// ascents and descents between neighbouring items (including from `prev`, the level of the item
+/// before `bucket`). Returns `None`, without counting anything, if `T` or the CPU doesn't support
+/// it.
+#[inline]
+fn count_extracted<T>(
+    bucket: &[T],
+    level: usize,
+    prev: u8,
+    counts: &mut [usize; 256],
+) -> Option<(usize, usize)>
+where
+    T: RadixKey,
+{
+    // The first byte of the buffer is the last byte of the previous block
+    let mut buf = [0u8; COUNT_BLOCK + 1];
+    let mut block_counts = [[0usize; 256]; 4];
+    let mut ascents = 0;
+    let mut descents = 0;
+    buf[0] = prev;
+
+    let mut blocks = bucket.chunks(COUNT_BLOCK).peekable();
+
+    while let Some(block) = blocks.next() {
+        if !T::extract_level(block, level, &mut buf[1..=block.len()]) {
+            // CPU support doesn't change between blocks, so this can only happen on the first
+            return None;
+        }
+
+        if let Some(next) = blocks.peek() {
+            prefetch(next);
         }
+
+        let (a, d) = count_bytes(&buf[..=block.len()], &mut block_counts);
+        ascents += a;
+        descents += d;
+        buf[0] = buf[block.len()];
     }
 
-    // Check the boundaries of each counted chunk, to see if the full bucket
-    // is already sorted
-    if already_sorted {
-        for w in boundaries.windows(2) {
-            if w[1].0 < w[0].1 {
-                already_sorted = false;
-                break;
-            }
+    for c in block_counts.iter() {
+        for i in 0..256 {
+            counts[i] += c[i];
         }
     }
 
-    (
-        msb_counts,
-        already_sorted,
-        boundaries[0].0,
-        boundaries[boundaries.len() - 1].1,
-    )
+    Some((ascents, descents))
 }
 
 #[inline]
-pub fn get_counts_with_ends<T>(bucket: &[T], level: usize) -> ([usize; 256], bool, u8, u8)
+pub fn get_counts_with_ends<T>(bucket: &[T], level: usize) -> ([usize; 256], CountMeta)
 where
     T: RadixKey,
 {
-    #[cfg(feature = "work_profiles")]
-    println!("({}) COUNT", level);
+    #[cfg(feature = "tracing")]
+    let _span = tracing::trace_span!("count", level, len = bucket.len()).entered();
+    trace_scope!("count", level = level, len = bucket.len());
+
+    let b_first = bucket.first().unwrap().get_level(level);
+    let b_last = bucket.last().unwrap().get_level(level);
 
-    let mut already_sorted = true;
     let mut continue_from = bucket.len();
     let mut counts_1 = [0usize; 256];
-    let mut last = 0usize;
+    let mut last = b_first as usize;
+    let mut ascents = 0;
 
     for (i, item) in bucket.iter().enumerate() {
         let b = item.get_level(level) as usize;
@@ -124,25 +268,52 @@ where
 
         if b < last {
             continue_from = i + 1;
-            already_sorted = false;
             break;
         }
 
+        ascents += (b > last) as usize;
         last = b;
     }
 
     if continue_from == bucket.len() {
+        // Either everything is in order, or the only descent is the last item
+        let descents = (b_last as usize != last) as usize;
+
         return (
             counts_1,
-            already_sorted,
-            bucket[0].get_level(level),
-            last as u8,
+            CountMeta {
+                already_sorted: descents == 0,
+                first: b_first,
+                last: b_last,
+                descents,
+                ascents,
+            },
+        );
+    }
+
+    let mut descents = 1;
+    let prev = bucket[continue_from - 1].get_level(level);
+
+    if let Some((a, d)) = count_extracted(&bucket[continue_from..], level, prev, &mut counts_1) {
+        ascents += a;
+        descents += d;
+
+        return (
+            counts_1,
+            CountMeta {
+                already_sorted: false,
+                first: b_first,
+                last: b_last,
+                descents,
+                ascents,
+            },
         );
     }
 
     let mut counts_2 = [0usize; 256];
     let mut counts_3 = [0usize; 256];
     let mut counts_4 = [0usize; 256];
+    let mut prev = prev as usize;
     let chunks = bucket[continue_from..].chunks_exact(4);
     let rem = chunks.remainder();
 
@@ -156,11 +327,18 @@ where
         counts_2[b] += 1;
         counts_3[c] += 1;
         counts_4[d] += 1;
+
+        descents += (a < prev) as usize + (b < a) as usize + (c < b) as usize + (d < c) as usize;
+        ascents += (a > prev) as usize + (b > a) as usize + (c > b) as usize + (d > c) as usize;
+        prev = d;
     });
 
     rem.iter().for_each(|v| {
         let b = v.get_level(level) as usize;
         counts_1[b] += 1;
+        descents += (b < prev) as usize;
+        ascents += (b > prev) as usize;
+        prev = b;
     });
 
     for i in 0..256 {
@@ -169,10 +347,73 @@ where
         counts_1[i] += counts_4[i];
     }
 
-    let b_first = bucket.first().unwrap().get_level(level);
-    let b_last = bucket.last().unwrap().get_level(level);
+    (
+        counts_1,
+        CountMeta {
+            already_sorted: false,
+            first: b_first,
+            last: b_last,
+            descents,
+            ascents,
+        },
+    )
+}
+
+/// get_plain_counts counts `bucket` at `level` without looking at the order of the items, for
+/// when it isn't worth checking whether the bucket is already sorted.
+#[inline]
+pub fn get_plain_counts<T>(bucket: &[T], level: usize) -> [usize; 256]
+where
+    T: RadixKey,
+{
+    #[cfg(feature = "tracing")]
+    let _span = tracing::trace_span!("count", level, len = bucket.len()).entered();
+    trace_scope!("count", level = level, len = bucket.len());
+
+    let mut buf = [0u8; COUNT_BLOCK];
+    let mut block_counts = [[0usize; 256]; 4];
+    let mut extracted = true;
+    let mut blocks = bucket.chunks(COUNT_BLOCK).peekable();
+
+    while let Some(block) = blocks.next() {
+        if !T::extract_level(block, level, &mut buf[..block.len()]) {
+            // CPU support doesn't change between blocks, so this can only happen on the first
+            extracted = false;
+            break;
+        }
+
+        if let Some(next) = blocks.peek() {
+            prefetch(next);
+        }
+
+        histogram_bytes(&buf[..block.len()], &mut block_counts);
+    }
+
+    if !extracted {
+        let chunks = bucket.chunks_exact(4);
+        let rem = chunks.remainder();
+
+        chunks.into_iter().for_each(|chunk| {
+            block_counts[0][chunk[0].get_level(level) as usize] += 1;
+            block_counts[1][chunk[1].get_level(level) as usize] += 1;
+            block_counts[2][chunk[2].get_level(level) as usize] += 1;
+            block_counts[3][chunk[3].get_level(level) as usize] += 1;
+        });
+
+        rem.iter().for_each(|v| {
+            block_counts[0][v.get_level(level) as usize] += 1;
+        });
+    }
 
-    (counts_1, already_sorted, b_first, b_last)
+    let mut counts = block_counts[0];
+
+    for c in block_counts[1..].iter() {
+        for i in 0..256 {
+            counts[i] += c[i];
+        }
+    }
+
+    counts
 }
 
 #[inline]
@@ -184,9 +425,225 @@ where
         return ([0usize; 256], true);
     }
 
-    let (counts, sorted, _, _) = get_counts_with_ends(bucket, level);
+    let (counts, meta) = get_counts_with_ends(bucket, level);
+
+    (counts, meta.already_sorted)
+}
+
+/// get_all_counts counts every level from `start_level` to `end_level` inclusive in a single read
+/// of `bucket`. The counts for `level` are at index `level - start_level`.
+#[inline]
+pub fn get_all_counts<T>(bucket: &[T], start_level: usize, end_level: usize) -> Vec<[usize; 256]>
+where
+    T: RadixKey,
+{
+    #[cfg(feature = "tracing")]
+    let _span =
+        tracing::trace_span!("count_all", start_level, end_level, len = bucket.len()).entered();
+    trace_scope!("count_all", level = end_level, len = bucket.len());
+
+    let mut buf = [0u8; COUNT_BLOCK];
+    let mut block_counts = vec![[[0usize; 256]; 4]; end_level + 1 - start_level];
+    let mut extracted = true;
+    let mut blocks = bucket.chunks(COUNT_BLOCK).peekable();
+
+    // Each block is extracted once per level while it's still in the cache, so the bucket is only
+    // read from memory once
+    'blocks: while let Some(block) = blocks.next() {
+        if let Some(next) = blocks.peek() {
+            prefetch(next);
+        }
+
+        for (i, c) in block_counts.iter_mut().enumerate() {
+            if !T::extract_level(block, start_level + i, &mut buf[..block.len()]) {
+                // CPU support doesn't change between blocks, so this can only happen on the first
+                extracted = false;
+                break 'blocks;
+            }
+
+            histogram_bytes(&buf[..block.len()], c);
+        }
+    }
+
+    if !extracted {
+        block_counts
+            .iter_mut()
+            .for_each(|c| *c = [[0usize; 256]; 4]);
+
+        for item in bucket {
+            for (i, c) in block_counts.iter_mut().enumerate() {
+                c[0][item.get_level(start_level + i) as usize] += 1;
+            }
+        }
+    }
+
+    block_counts
+        .iter()
+        .map(|c| {
+            let mut counts = c[0];
+
+            for other in c[1..].iter() {
+                for i in 0..256 {
+                    counts[i] += other[i];
+                }
+            }
+
+            counts
+        })
+        .collect()
+}
+
+/// par_get_all_counts is the same as `get_all_counts`, but splits larger buckets across threads.
+#[inline]
+#[cfg(feature = "multi-threaded")]
+pub fn par_get_all_counts<T>(
+    bucket: &[T],
+    start_level: usize,
+    end_level: usize,
+) -> Vec<[usize; 256]>
+where
+    T: RadixKey + Sized + Send + Sync,
+{
+    if bucket.len() < 400_000 {
+        return get_all_counts(bucket, start_level, end_level);
+    }
+
+    let chunk_size = (bucket.len() / rayon::current_num_threads()) + 1;
+
+    bucket
+        .par_chunks(chunk_size)
+        .map(|chunk| get_all_counts(chunk, start_level, end_level))
+        .reduce_with(|mut a, b| {
+            for (a, b) in a.iter_mut().zip(b.iter()) {
+                for i in 0..256 {
+                    a[i] += b[i];
+                }
+            }
+
+            a
+        })
+        .unwrap_or_else(|| vec![[0usize; 256]; end_level + 1 - start_level])
+}
+
+/// update_differing_levels marks, in `differs`, each of the `remaining` levels at which any key in
+/// `block` holds a different byte to `first`. Levels found to differ are removed from `remaining`,
+/// so they aren't checked again in later blocks.
+#[inline]
+fn update_differing_levels<T>(
+    block: &[T],
+    first: &T,
+    remaining: &mut Vec<usize>,
+    differs: &mut [bool],
+) where
+    T: RadixKey,
+{
+    remaining.retain(|&level| {
+        let b = first.get_level(level);
+        let diff = block
+            .iter()
+            .fold(0u8, |acc, i| acc | (i.get_level(level) ^ b));
+        differs[level] = diff != 0;
+
+        diff == 0
+    });
+}
+
+/// get_counts_with_differing_levels is `get_counts_with_ends`, but also returns, for each level
+/// up to `level`, whether any key in `bucket` holds a different byte to `first` at that level, for
+/// use with `get_level_range`. Levels above `level` are assumed not to differ. Both are gathered from the same block of items while it is in the cache.
+/// Each level is only checked until a difference is found, so for most inputs the differing
+/// levels are all found in the first block, and the rest is counted as usual.
+#[inline]
+pub fn get_counts_with_differing_levels<T>(
+    bucket: &[T],
+    level: usize,
+    first: &T,
+) -> ([usize; 256], CountMeta, Vec<bool>)
+where
+    T: RadixKey,
+{
+    #[cfg(feature = "tracing")]
+    let _span = tracing::trace_span!("count_differing", level, len = bucket.len()).entered();
+    trace_scope!("count_differing", level = level, len = bucket.len());
+
+    let mut differs = vec![false; T::LEVELS];
+    let mut remaining: Vec<usize> = (0..=level).collect();
+    let mut counts = [0usize; 256];
+    let mut metas = Vec::new();
+    let mut start = 0;
+
+    while start < bucket.len() {
+        let block = if remaining.is_empty() {
+            &bucket[start..]
+        } else {
+            let block = &bucket[start..bucket.len().min(start + 1024)];
+            update_differing_levels(block, first, &mut remaining, &mut differs);
+
+            block
+        };
+
+        let (block_counts, meta) = get_counts_with_ends(block, level);
+        for (c, b) in counts.iter_mut().zip(block_counts.iter()) {
+            *c += *b;
+        }
+
+        metas.push(meta);
+        start += block.len();
+    }
+
+    (counts, combine_count_meta(&metas), differs)
+}
 
-    (counts, sorted)
+/// get_tile_counts_with_differing_levels is `get_tile_counts_with_meta`, but also returns which
+/// levels differ from `first` anywhere in `bucket`, as `get_counts_with_differing_levels` does.
+#[inline]
+#[allow(clippy::type_complexity)]
+pub fn get_tile_counts_with_differing_levels<T>(
+    bucket: &[T],
+    tile_size: usize,
+    level: usize,
+    first: &T,
+) -> (Vec<[usize; 256]>, Vec<CountMeta>, Vec<bool>)
+where
+    T: RadixKey + Copy + Sized + Send + Sync,
+{
+    #[cfg(feature = "multi-threaded")]
+    let tiles: Vec<([usize; 256], CountMeta, Vec<bool>)> = bucket
+        .par_chunks(tile_size)
+        .map(|chunk| get_counts_with_differing_levels(chunk, level, first))
+        .collect();
+
+    #[cfg(not(feature = "multi-threaded"))]
+    let tiles: Vec<([usize; 256], CountMeta, Vec<bool>)> = bucket
+        .chunks(tile_size)
+        .map(|chunk| get_counts_with_differing_levels(chunk, level, first))
+        .collect();
+
+    let mut differs = vec![false; T::LEVELS];
+    let mut tile_counts = Vec::with_capacity(tiles.len());
+    let mut tile_metas = Vec::with_capacity(tiles.len());
+
+    for (counts, meta, tile_differs) in tiles {
+        for (d, t) in differs.iter_mut().zip(tile_differs.iter()) {
+            *d |= *t;
+        }
+
+        tile_counts.push(counts);
+        tile_metas.push(meta);
+    }
+
+    (tile_counts, tile_metas, differs)
+}
+
+/// get_level_range returns the highest and lowest levels at which any keys differ, given the
+/// differing levels returned by `get_counts_with_differing_levels`. Levels outside of this range hold the same byte in every
+/// key, so don't need to be sorted. Returns `None` if every key is the same.
+#[inline]
+pub fn get_level_range(differs: &[bool]) -> Option<(usize, usize)> {
+    let start_level = differs.iter().rposition(|d| *d)?;
+    let end_level = differs.iter().position(|d| *d)?;
+
+    Some((start_level, end_level))
 }
 
 #[allow(clippy::uninit_vec)]
@@ -209,42 +666,92 @@ pub const fn cdiv(a: usize, b: usize) -> usize {
     (a + b - 1) / b
 }
 
+/// get_tile_counts_with_meta counts each tile of `bucket` separately, returning the counts and
+/// meta for each tile. Use `combine_count_meta` to get the meta for the whole bucket.
 #[inline]
-pub fn get_tile_counts<T>(bucket: &[T], tile_size: usize, level: usize) -> (Vec<[usize; 256]>, bool)
+pub fn get_tile_counts_with_meta<T>(
+    bucket: &[T],
+    tile_size: usize,
+    level: usize,
+) -> (Vec<[usize; 256]>, Vec<CountMeta>)
 where
     T: RadixKey + Copy + Sized + Send + Sync,
 {
-    #[cfg(feature = "work_profiles")]
-    println!("({}) TILE_COUNT", level);
+    #[cfg(feature = "tracing")]
+    let _span = tracing::trace_span!("tile_count", level, len = bucket.len(), tile_size).entered();
+    trace_scope!(
+        "tile_count",
+        level = level,
+        len = bucket.len(),
+        tile_size = tile_size
+    );
 
     #[cfg(feature = "multi-threaded")]
-    let tiles: Vec<([usize; 256], bool, u8, u8)> = bucket
+    let tiles: Vec<([usize; 256], CountMeta)> = bucket
         .par_chunks(tile_size)
         .map(|chunk| par_get_counts_with_ends(chunk, level))
         .collect();
 
     #[cfg(not(feature = "multi-threaded"))]
-    let tiles: Vec<([usize; 256], bool, u8, u8)> = bucket
+    let tiles: Vec<([usize; 256], CountMeta)> = bucket
         .chunks(tile_size)
         .map(|chunk| get_counts_with_ends(chunk, level))
         .collect();
 
-    let mut all_sorted = true;
-
-    if tiles.len() == 1 {
-        // If there is only one tile, we already have a flag for if it is sorted
-        all_sorted = tiles[0].1;
-    } else {
-        // Check if any of the tiles, or any of the tile boundaries are unsorted
-        for tile in tiles.windows(2) {
-            if !tile[0].1 || !tile[1].1 || tile[1].2 < tile[0].3 {
-                all_sorted = false;
-                break;
-            }
-        }
-    }
+    tiles.into_iter().unzip()
+}
+
+#[inline]
+#[cfg(feature = "multi-threaded")]
+pub fn get_tile_counts<T>(bucket: &[T], tile_size: usize, level: usize) -> (Vec<[usize; 256]>, bool)
+where
+    T: RadixKey + Copy + Sized + Send + Sync,
+{
+    let (tile_counts, metas) = get_tile_counts_with_meta(bucket, tile_size, level);
+
+    (tile_counts, combine_count_meta(&metas).already_sorted)
+}
+
+/// get_plain_tile_counts counts each tile of `bucket` separately in parallel, without looking at
+/// the order of the items (see `get_plain_counts`).
+#[inline]
+#[cfg(feature = "multi-threaded")]
+pub fn get_plain_tile_counts<T>(bucket: &[T], tile_size: usize, level: usize) -> Vec<[usize; 256]>
+where
+    T: RadixKey + Sized + Send + Sync,
+{
+    #[cfg(feature = "tracing")]
+    let _span = tracing::trace_span!("tile_count", level, len = bucket.len(), tile_size).entered();
+    trace_scope!(
+        "tile_count",
+        level = level,
+        len = bucket.len(),
+        tile_size = tile_size
+    );
 
-    (tiles.into_iter().map(|v| v.0).collect(), all_sorted)
+    bucket
+        .par_chunks(tile_size)
+        .map(|tile| get_plain_counts(tile, level))
+        .collect()
+}
+
+/// par_reverse reverses `bucket` in place, using multiple threads.
+#[inline]
+#[cfg(feature = "multi-threaded")]
+pub fn par_reverse<T: Send>(bucket: &mut [T]) {
+    let half = bucket.len() / 2;
+    let (front, rest) = bucket.split_at_mut(half);
+    let back_start = rest.len() - half;
+    let back = &mut rest[back_start..];
+
+    front
+        .par_chunks_mut(16_384)
+        .zip(back.par_rchunks_mut(16_384))
+        .for_each(|(f, b)| {
+            f.iter_mut()
+                .zip(b.iter_mut().rev())
+                .for_each(|(x, y)| std::mem::swap(x, y));
+        });
 }
 
 #[inline]
@@ -275,9 +782,45 @@ pub fn is_homogenous_bucket(counts: &[usize; 256]) -> bool {
     true
 }
 
+/// get_distribution_stats returns the number of non-empty buckets, the share of the input held by
+/// the largest bucket, and the Shannon entropy of the counts in bits (between 0 and 8).
+#[inline]
+pub fn get_distribution_stats(counts: &[usize; 256], len: usize) -> (usize, f64, f64) {
+    if len == 0 {
+        return (0, 0.0, 0.0);
+    }
+
+    let len_f = len as f64;
+    let mut non_empty = 0;
+    let mut max = 0;
+    let mut entropy = 0.0;
+
+    for c in counts.iter().copied() {
+        if c == 0 {
+            continue;
+        }
+
+        non_empty += 1;
+        max = max.max(c);
+
+        let p = c as f64 / len_f;
+        entropy -= p * p.log2();
+    }
+
+    (non_empty, max as f64 / len_f, entropy)
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::utils::get_tile_counts;
+    use crate::utils::{
+        combine_count_meta, get_all_counts, get_counts_with_differing_levels, get_counts_with_ends,
+        get_distribution_stats, get_level_range, get_plain_counts, get_tile_counts,
+        get_tile_counts_with_differing_levels, get_tile_counts_with_meta, CountMeta,
+    };
+    #[cfg(feature = "multi-threaded")]
+    use crate::utils::{par_get_all_counts, par_reverse};
+    use crate::RadixKey;
+    use block_pseudorand::block_rand;
 
     #[test]
     pub fn test_get_tile_counts_correctly_marks_already_sorted_single_tile() {
@@ -304,4 +847,219 @@ mod tests {
         let (_counts, already_sorted) = get_tile_counts(&mut data, 2, 0);
         assert_eq!(already_sorted, true);
     }
+
+    #[test]
+    pub fn test_count_meta_descents() {
+        let data: Vec<u8> = vec![0, 5, 2, 3, 1, 1, 7, 9, 8];
+        let descents = data.windows(2).filter(|w| w[1] < w[0]).count();
+
+        let (_counts, meta) = get_counts_with_ends(&data, 0);
+        assert_eq!(meta.descents, descents);
+        assert_eq!(meta.first, 0);
+        assert_eq!(meta.last, 8);
+
+        for tile_size in 1..=data.len() {
+            let (_counts, metas) = get_tile_counts_with_meta(&data, tile_size, 0);
+            let meta = combine_count_meta(&metas);
+            assert_eq!(meta.descents, descents);
+            assert!(!meta.already_sorted);
+        }
+
+        let data: Vec<u8> = vec![0, 1, 2, 3, 4, 5, 6, 7, 3];
+
+        let (_counts, meta) = get_counts_with_ends(&data, 0);
+        assert_eq!(meta.descents, 1);
+        assert!(!meta.already_sorted);
+        assert_eq!(meta.sortedness(data.len()), 1.0 - (1.0 / 8.0));
+    }
+
+    #[test]
+    pub fn test_count_meta_ascents() {
+        let data: Vec<u8> = vec![9, 7, 7, 5, 6, 3, 1, 1, 0];
+        let ascents = data.windows(2).filter(|w| w[1] > w[0]).count();
+
+        let (_counts, meta) = get_counts_with_ends(&data, 0);
+        assert_eq!(meta.ascents, ascents);
+        assert!(!meta.already_reversed());
+
+        for tile_size in 1..=data.len() {
+            let (_counts, metas) = get_tile_counts_with_meta(&data, tile_size, 0);
+            assert_eq!(metas.len(), data.len().div_ceil(tile_size));
+            assert_eq!(combine_count_meta(&metas).ascents, ascents);
+        }
+
+        let data: Vec<u8> = vec![9, 7, 7, 5, 4, 3, 1, 1, 0];
+
+        let (_counts, meta) = get_counts_with_ends(&data, 0);
+        assert_eq!(meta.ascents, 0);
+        assert!(meta.already_reversed());
+        assert_eq!(meta.reverse_sortedness(data.len()), 1.0);
+
+        let reversed = meta.reversed();
+        assert!(reversed.already_sorted);
+        assert_eq!(reversed.first, 0);
+        assert_eq!(reversed.last, 9);
+
+        let data: Vec<u8> = vec![3, 3, 3];
+        let (_counts, meta) = get_counts_with_ends(&data, 0);
+        assert!(meta.already_sorted);
+        assert!(!meta.already_reversed());
+    }
+
+    #[test]
+    pub fn test_count_meta_unordered() {
+        for len in [1, 2, 3, 129, 257, 100_000] {
+            let meta = CountMeta::unordered(len);
+            assert_eq!(meta.already_sorted, len < 2);
+            assert!(!meta.already_reversed() || len < 2);
+            assert!(meta.ascents + meta.descents < len);
+        }
+
+        let meta = CountMeta::unordered(100_001);
+        assert!((meta.sortedness(100_001) - 0.502).abs() < 0.001);
+    }
+
+    fn validate_counts<T>(inputs: &[T])
+    where
+        T: RadixKey + Copy,
+    {
+        for level in 0..T::LEVELS {
+            let bytes: Vec<u8> = inputs.iter().map(|v| v.get_level(level)).collect();
+            let mut expected = [0usize; 256];
+            bytes.iter().for_each(|b| expected[*b as usize] += 1);
+
+            let (counts, meta) = get_counts_with_ends(inputs, level);
+
+            assert_eq!(counts, expected, "level {}", level);
+            assert_eq!(get_plain_counts(inputs, level), expected, "level {}", level);
+            assert_eq!(
+                meta.ascents,
+                bytes.windows(2).filter(|w| w[1] > w[0]).count()
+            );
+            assert_eq!(
+                meta.descents,
+                bytes.windows(2).filter(|w| w[1] < w[0]).count()
+            );
+        }
+    }
+
+    fn validate_counts_suite<T>()
+    where
+        T: RadixKey + Copy,
+    {
+        for len in [1, 2, 31, 33, 513, 1_025, 10_000] {
+            let inputs: Vec<T> = block_rand(len);
+            validate_counts(&inputs);
+
+            // Start sorted, so counting switches over part way through
+            let mut inputs: Vec<T> = block_rand(len);
+            inputs[..len / 2].sort_unstable_by_key(|v| v.get_level(0));
+            validate_counts(&inputs);
+        }
+    }
+
+    #[test]
+    pub fn test_simd_counts_match_scalar() {
+        validate_counts_suite::<u8>();
+        validate_counts_suite::<u16>();
+        validate_counts_suite::<u32>();
+        validate_counts_suite::<u64>();
+        validate_counts_suite::<usize>();
+        validate_counts_suite::<i8>();
+        validate_counts_suite::<i16>();
+        validate_counts_suite::<i32>();
+        validate_counts_suite::<i64>();
+        validate_counts_suite::<isize>();
+    }
+
+    #[test]
+    pub fn test_get_all_counts() {
+        for len in [1, 255, 10_000] {
+            let inputs: Vec<u64> = block_rand(len);
+            let level_counts = get_all_counts(&inputs, 2, 6);
+            assert_eq!(level_counts.len(), 5);
+
+            for (i, counts) in level_counts.iter().enumerate() {
+                assert_eq!(*counts, get_counts_with_ends(&inputs, i + 2).0);
+            }
+
+            let inputs: Vec<u32> = block_rand(len);
+            let level_counts = get_all_counts(&inputs, 0, 3);
+
+            for (i, counts) in level_counts.iter().enumerate() {
+                assert_eq!(*counts, get_counts_with_ends(&inputs, i).0);
+            }
+        }
+
+        #[cfg(feature = "multi-threaded")]
+        {
+            let inputs: Vec<u64> = block_rand(1_000_000);
+            assert_eq!(
+                par_get_all_counts(&inputs, 0, 7),
+                get_all_counts(&inputs, 0, 7)
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_get_level_range() {
+        let inputs: Vec<u64> = block_rand(100_000);
+        let (counts, meta, differs) = get_counts_with_differing_levels(&inputs, 7, &inputs[0]);
+        assert_eq!(differs, vec![true; 8]);
+        assert_eq!(get_level_range(&differs), Some((7, 0)));
+        assert_eq!((counts, meta), get_counts_with_ends(&inputs, 7));
+
+        // Only levels 1 and 4 differ, and only in the very last item
+        let mut inputs = vec![0x00AA_0000_1100_0022u64; 1_000_000];
+        inputs[999_999] = 0x00AA_00FF_1100_3322;
+        let (counts, meta, differs) = get_counts_with_differing_levels(&inputs, 4, &inputs[0]);
+        assert_eq!(
+            differs,
+            vec![false, true, false, false, true, false, false, false]
+        );
+        assert_eq!(get_level_range(&differs), Some((4, 1)));
+        assert_eq!((counts, meta), get_counts_with_ends(&inputs, 4));
+
+        let (tile_counts, tile_metas, tile_differs) =
+            get_tile_counts_with_differing_levels(&inputs, 300_000, 4, &inputs[0]);
+        assert_eq!(tile_differs, differs);
+        assert_eq!(
+            (tile_counts, tile_metas),
+            get_tile_counts_with_meta(&inputs, 300_000, 4)
+        );
+
+        let inputs = vec![5u32; 5_000];
+        let (_, _, differs) = get_counts_with_differing_levels(&inputs, 3, &inputs[0]);
+        assert_eq!(get_level_range(&differs), None);
+    }
+
+    #[test]
+    #[cfg(feature = "multi-threaded")]
+    pub fn test_par_reverse() {
+        for len in [0, 1, 2, 3, 16_384, 32_769, 100_001] {
+            let mut data: Vec<usize> = (0..len).collect();
+            par_reverse(&mut data);
+
+            assert!(data.iter().rev().copied().eq(0..len));
+        }
+    }
+
+    #[test]
+    pub fn test_get_distribution_stats() {
+        let mut counts = [0usize; 256];
+        counts[3] = 50;
+        counts[7] = 50;
+
+        let (non_empty, max_share, entropy) = get_distribution_stats(&counts, 100);
+        assert_eq!(non_empty, 2);
+        assert_eq!(max_share, 0.5);
+        assert_eq!(entropy, 1.0);
+
+        let counts = [4usize; 256];
+
+        let (non_empty, max_share, entropy) = get_distribution_stats(&counts, 1024);
+        assert_eq!(non_empty, 256);
+        assert_eq!(max_share, 1.0 / 256.0);
+        assert_eq!(entropy, 8.0);
+    }
 }
@@ -4,6 +4,7 @@ use block_pseudorand::block_rand;
 use rayon::prelude::*;
 use std::fmt::Debug;
 use std::ops::{Shl, ShlAssign, Shr, ShrAssign};
+use std::sync::Mutex;
 
 pub trait NumericTest<T>:
     RadixKey
@@ -48,6 +49,53 @@ impl Tuner for SingleAlgoTuner {
     }
 }
 
+/// `RecordingTuner` picks the same algorithms as the tuner it wraps, and remembers the first one.
+struct RecordingTuner<'a> {
+    inner: &'a (dyn Tuner + Send + Sync),
+    first: Mutex<Option<Algorithm>>,
+}
+
+impl<'a> Tuner for RecordingTuner<'a> {
+    #[inline]
+    fn pick_algorithm(&self, p: &TuningParams, counts: &[usize]) -> Algorithm {
+        let algorithm = self.inner.pick_algorithm(p, counts);
+        self.first.lock().unwrap().get_or_insert(algorithm);
+
+        algorithm
+    }
+}
+
+/// `top_level_pick` sorts `inputs` with `tuner`, checks the result and returns the algorithm the
+/// tuner picked for the whole input.
+pub fn top_level_pick<T>(mut inputs: Vec<T>, tuner: &(dyn Tuner + Send + Sync)) -> Algorithm
+where
+    T: NumericTest<T>,
+{
+    let recorder = RecordingTuner {
+        inner: tuner,
+        first: Mutex::new(None),
+    };
+
+    let mut expected = inputs.clone();
+    expected.sort_unstable();
+    inputs.radix_sort_builder().with_tuner(&recorder).sort();
+    assert_eq!(inputs, expected);
+
+    recorder
+        .first
+        .into_inner()
+        .unwrap()
+        .expect("tuner was consulted")
+}
+
+/// `gen_sorted_runs` returns `runs` sorted runs of `run_len` random items, one after another.
+pub fn gen_sorted_runs(runs: usize, run_len: usize) -> Vec<u32> {
+    let mut data: Vec<u32> = block_rand(runs * run_len);
+    data.chunks_mut(run_len).for_each(|c| c.sort_unstable());
+
+    data
+}
+
 pub fn gen_inputs<T>(n: usize, shift: T) -> Vec<T>
 where
     T: NumericTest<T>,